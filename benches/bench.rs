@@ -10,8 +10,8 @@ use quirc_rs::decode::*;
 use quirc_rs::identify::*;
 use quirc_rs::quirc::*;
 
-use quirc_wrapper as qw;
 use test_utils::dbgutil::*;
+use test_utils::oracle::qw;
 
 fn run(width: u32, height: u32, image_bytes: &[u8]) {
     let mut image_bytes = image_bytes.to_owned();
@@ -0,0 +1,27 @@
+fn main() {
+    #[cfg(feature = "capi")]
+    generate_header();
+}
+
+/// Generate the C header of the libquirc compatible API into `OUT_DIR`.
+/// `include/quirc.h` is a checked in copy of it.
+#[cfg(feature = "capi")]
+fn generate_header() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    // Only the C API and the types it uses, so that nothing else of the
+    // crate ends up in the header
+    let crate_dir = std::path::Path::new(&crate_dir);
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_dir.join("src/capi.rs"))
+        .with_src(crate_dir.join("src/quirc.rs"))
+        .generate()
+        .expect("Unable to generate C header")
+        .write_to_file(std::path::Path::new(&out_dir).join("quirc.h"));
+}
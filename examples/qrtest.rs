@@ -13,266 +13,674 @@
  * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
-extern crate clap;
-extern crate quirc_rs;
 
-use std::path::Path;
+//! Benchmark and regression harness.
+//!
+//! Scans the given images and directories, comparing the decoded payloads
+//! against a ground-truth manifest (see `tests/images/manifest.json`) and
+//! the results against a baseline saved by an earlier run:
+//!
+//! ```text
+//! cargo run --release --example qrtest -- -m tests/images/manifest.json \
+//!     -b baseline.json tests/images
+//! ```
+//!
+//! The exit status is 1 if anything regressed against the baseline.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
 
 use clap::{App, Arg};
-use libc::{memset, timespec};
+use serde::{Deserialize, Serialize};
+
+use quirc_rs::identify::{quirc_extract, quirc_identify};
+use quirc_rs::quirc::consts::*;
+use quirc_rs::quirc::{quirc_count, quirc_version, Code, Data, Image, Quirc};
 
-use quirc_rs::decode::*;
-use quirc_rs::identify::*;
-use quirc_rs::quirc::*;
+/// Expected contents of a single image.
+#[derive(Debug, Deserialize)]
+struct Truth {
+    /// Defaults to the name of the directory holding the image
+    category: Option<String>,
 
-use test_utils::dbgutil::*;
+    /// Payload of each code in the image. `null` stands for a code whose
+    /// payload isn't known, and matches any payload not expected
+    /// otherwise.
+    expected: Vec<Option<String>>,
+}
 
-static mut WANT_CELL_DUMP: bool = false;
-static mut WANT_VALIDATE: bool = false;
-static mut WANT_VERBOSE: bool = false;
+/// Ground truth, keyed by paths relative to the manifest.
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    images: BTreeMap<String, Truth>,
+}
 
-fn ms(ts: libc::timespec) -> u32 {
-    ((ts.tv_sec * 1000) + (ts.tv_nsec / 1_000_000)) as u32
+/// A manifest entry, found by the canonical path of the image.
+struct Entry {
+    name: String,
+    truth: Truth,
 }
 
-#[derive(Copy)]
-#[repr(C)]
-pub struct ResultInfo {
-    pub file_count: i32,
-    pub id_count: i32,
-    pub decode_count: i32,
-    pub load_time: u32,
-    pub identify_time: u32,
-    pub total_time: u32,
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+struct Timings {
+    load_ms: f64,
+    identify_ms: f64,
+    extract_ms: f64,
+    decode_ms: f64,
 }
 
-impl Clone for ResultInfo {
-    fn clone(&self) -> Self {
-        *self
+impl Timings {
+    fn total_ms(&self) -> f64 {
+        self.load_ms + self.identify_ms + self.extract_ms + self.decode_ms
+    }
+
+    fn add(&mut self, other: &Timings) {
+        self.load_ms += other.load_ms;
+        self.identify_ms += other.identify_ms;
+        self.extract_ms += other.extract_ms;
+        self.decode_ms += other.decode_ms;
     }
 }
 
-pub unsafe fn print_result(name: &str, info: *mut ResultInfo) {
-    println!("-------------------------------------------------------------------------------");
-    print!(
-        "{}: {} files, {} codes, {} decoded ({} failures)",
-        name,
-        (*info).file_count,
-        (*info).id_count,
-        (*info).decode_count,
-        (*info).id_count - (*info).decode_count
-    );
-    if (*info).id_count != 0 {
-        print!(
-            ", {}% success rate",
-            ((*info).decode_count * 100i32 + (*info).id_count / 2i32) / (*info).id_count
-        );
+/// Decoded payloads compared against the expected ones.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+struct Counts {
+    true_positives: usize,
+    false_positives: usize,
+    false_negatives: usize,
+}
+
+impl Counts {
+    fn precision(&self) -> Option<f64> {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives,
+        )
     }
-    println!();
-    println!(
-        "Total time [load: {}, identify: {}, total: {}]",
-        (*info).load_time,
-        (*info).identify_time,
-        (*info).total_time
-    );
-    if (*info).file_count != 0 {
-        println!(
-            "Average time [load: {}, identify: {}, total: {}]",
-            (*info).load_time.wrapping_div((*info).file_count as (u32)),
-            (*info)
-                .identify_time
-                .wrapping_div((*info).file_count as (u32)),
-            (*info).total_time.wrapping_div((*info).file_count as (u32))
-        );
+
+    fn recall(&self) -> Option<f64> {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_negatives,
+        )
+    }
+
+    fn add(&mut self, other: &Counts) {
+        self.true_positives += other.true_positives;
+        self.false_positives += other.false_positives;
+        self.false_negatives += other.false_negatives;
     }
 }
 
-pub unsafe fn add_result(mut sum: *mut ResultInfo, inf: *mut ResultInfo) {
-    (*sum).file_count += (*inf).file_count;
-    (*sum).id_count += (*inf).id_count;
-    (*sum).decode_count += (*inf).decode_count;
-    (*sum).load_time = (*sum).load_time.wrapping_add((*inf).load_time);
-    (*sum).identify_time = (*sum).identify_time.wrapping_add((*inf).identify_time);
-    (*sum).total_time = (*sum).total_time.wrapping_add((*inf).total_time);
+fn ratio(n: usize, d: usize) -> Option<f64> {
+    if d == 0 {
+        None
+    } else {
+        Some(n as f64 / d as f64)
+    }
 }
 
-pub unsafe fn scan_file(path: &Path, mut info: *mut ResultInfo) {
-    let filename = path.file_name().unwrap();
-    let mut tp: libc::timespec = std::mem::uninitialized();
-    let mut start: u32;
-    let total_start: u32;
+/// Result of scanning a single image. This is also what baselines store.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct ImageResult {
+    category: String,
 
-    libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut tp as (*mut timespec));
-    total_start = {
-        start = ms(tp);
-        start
-    };
-    let (width, height, mut image_bytes) = load_image(path);
-    libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut tp as (*mut timespec));
-    (*info).load_time = ms(tp).wrapping_sub(start);
-
-    let mut image_bytes_clone = image_bytes.clone();
-
-    libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut tp as (*mut timespec));
-    start = ms(tp);
-    let mut decoder = Quirc::new(Image::new(width, height, &mut image_bytes));
-    quirc_identify(&mut decoder);
-    libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut tp as (*mut timespec));
-    (*info).identify_time = ms(tp).wrapping_sub(start);
-    (*info).id_count = quirc_count(&decoder);
-    for i in 0..(*info).id_count {
-        let code = quirc_extract(&mut decoder, i).unwrap();
-        if quirc_decode(&code).is_ok() {
-            (*info).decode_count += 1;
+    /// Number of codes found by `quirc_identify()`
+    identified: usize,
+
+    /// Payloads of the codes which were decoded
+    decoded: Vec<String>,
+
+    /// Why the image couldn't be loaded
+    load_error: Option<String>,
+
+    /// Why the other codes couldn't be decoded
+    errors: Vec<String>,
+
+    /// Missing if the image isn't in the manifest
+    counts: Option<Counts>,
+    timings: Timings,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Results {
+    images: BTreeMap<String, ImageResult>,
+}
+
+/// Totals of a category of images.
+#[derive(Default)]
+struct Summary {
+    files: usize,
+    identified: usize,
+    decoded: usize,
+    counts: Counts,
+    timings: Timings,
+}
+
+impl Summary {
+    fn add(&mut self, result: &ImageResult) {
+        self.files += 1;
+        self.identified += result.identified;
+        self.decoded += result.decoded.len();
+        if let Some(counts) = &result.counts {
+            self.counts.add(counts);
         }
+        self.timings.add(&result.timings);
     }
-    libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut tp as (*mut timespec));
-    (*info).total_time = (*info)
-        .total_time
-        .wrapping_add(ms(tp).wrapping_sub(total_start));
-    println!(
-        "  {:-30}: {:5} {:5} {:5} {:5} {:5}",
-        filename.to_str().unwrap(),
-        (*info).load_time,
-        (*info).identify_time,
-        (*info).total_time,
-        (*info).id_count,
-        (*info).decode_count
-    );
-    if WANT_CELL_DUMP || WANT_VERBOSE {
-        for i in 0..(*info).id_count {
-            let code = quirc_extract(&mut decoder, i).unwrap();
-            if WANT_CELL_DUMP {
-                dump_cells(&code);
-                println!();
-            }
-            if WANT_VERBOSE {
-                match quirc_decode(&code) {
-                    Ok(data) => {
-                        println!("  Decode successful:");
-                        dump_data(&data);
-                        println!();
-                    }
-                    Err(e) => {
-                        println!("  ERROR: {}", quirc_strerror(e));
-                        println!();
-                    }
-                }
+}
+
+struct Flags {
+    cell_dump: bool,
+    verbose: bool,
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn percent(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_owned(), |v| format!("{:.0}%", v * 100.0))
+}
+
+fn count(value: Option<usize>) -> String {
+    value.map_or_else(|| "-".to_owned(), |v| v.to_string())
+}
+
+/// Match the decoded payloads against the expected ones. Exact matches
+/// are taken first, so that a code of unknown payload can't take the
+/// place of a known one.
+fn score(truth: &Truth, decoded: &[String]) -> Counts {
+    let mut known: Vec<&str> = truth.expected.iter().filter_map(|e| e.as_deref()).collect();
+    let unknown = truth.expected.len() - known.len();
+
+    let mut unmatched: usize = 0;
+    for payload in decoded {
+        match known.iter().position(|k| k == payload) {
+            Some(i) => {
+                known.swap_remove(i);
             }
+            None => unmatched += 1,
         }
     }
-    if WANT_VALIDATE {
-        validate(
-            &mut decoder,
-            Image::new(width, height, &mut image_bytes_clone),
-        );
+
+    let false_positives = unmatched.saturating_sub(unknown);
+    Counts {
+        true_positives: decoded.len() - false_positives,
+        false_positives,
+        false_negatives: known.len() + unknown.saturating_sub(unmatched),
+    }
+}
+
+fn load_manifest(path: &Path) -> Result<HashMap<PathBuf, Entry>, Box<dyn Error>> {
+    let manifest: Manifest = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut entries = HashMap::new();
+    for (name, truth) in manifest.images {
+        match dir.join(&name).canonicalize() {
+            Ok(image) => {
+                entries.insert(image, Entry { name, truth });
+            }
+            Err(e) => eprintln!("{}: {}: {}", path.display(), name, e),
+        }
     }
-    (*info).file_count = 1i32;
+    Ok(entries)
+}
+
+fn load_results(path: &Path) -> Result<Results, Box<dyn Error>> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
 }
 
-pub unsafe fn scan_dir(path: &Path, info: *mut ResultInfo) -> i32 {
-    let entries = path.read_dir().unwrap();
+fn save_results(path: &Path, results: &Results) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), results)?;
+    Ok(())
+}
 
-    println!("{}:", path.display());
+/// Expand directories into the images they hold, recursively. Files named
+/// explicitly are taken as they are.
+fn collect_images(path: &Path, images: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        images.push(path.to_owned());
+        return Ok(());
+    }
+
+    let mut entries = path
+        .read_dir()?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
 
-    let mut count: i32 = 0i32;
     for entry in entries {
-        let entry = entry.unwrap();
-        if !entry.file_name().to_str().unwrap().starts_with('.') {
-            let mut sub: ResultInfo = std::mem::uninitialized();
-            let p = entry.path();
-            let fullpath = p.as_path();
-
-            if test_scan(fullpath, &mut sub) > 0i32 {
-                add_result(info, &mut sub);
-                count += 1;
-            }
+        let hidden = match entry.file_name() {
+            Some(name) => name.to_string_lossy().starts_with('.'),
+            None => false,
+        };
+        if !hidden && (entry.is_dir() || image::ImageFormat::from_path(&entry).is_ok()) {
+            collect_images(&entry, images)?;
         }
     }
+    Ok(())
+}
+
+fn payload_string(data: &Data) -> String {
+    data.payload_text()
+        .unwrap_or_else(|_| String::from_utf8_lossy(&data.payload).into_owned())
+}
 
-    if count > 1i32 {
-        print_result(path.file_name().unwrap().to_str().unwrap(), info);
-        println!();
+fn dump_cells(code: &Code) {
+    print!("    {} cells, corners:", code.size);
+    for c in &code.corners {
+        print!(" ({},{})", c.x, c.y);
+    }
+    println!();
+    for y in 0..code.size {
+        let row: String = (0..code.size)
+            .map(|x| if code.cell(x, y) { "[]" } else { "  " })
+            .collect();
+        println!("    {}", row);
     }
-    (count > 0i32) as (i32)
 }
 
-pub unsafe fn test_scan(path: &Path, info: *mut ResultInfo) -> i32 {
-    memset(
-        info as (*mut ::std::os::raw::c_void),
-        0i32,
-        ::std::mem::size_of::<ResultInfo>(),
-    );
+fn dump_data(data: &Data) {
+    const ECC_LEVELS: [&str; 4] = ["M", "L", "H", "Q"];
 
-    if path.is_file() {
-        scan_file(path, info);
-        1
-    } else if path.is_dir() {
-        scan_dir(path, info);
-        1
-    } else {
-        0
+    println!("    Version: {}", data.version);
+    println!("    ECC level: {}", ECC_LEVELS[data.ecc_level as usize & 3]);
+    println!("    Mask: {}", data.mask);
+    println!(
+        "    Data type: {} ({})",
+        data.data_type,
+        match data.data_type {
+            DATA_TYPE_NUMERIC => "NUMERIC",
+            DATA_TYPE_ALPHA => "ALPHA",
+            DATA_TYPE_BYTE => "BYTE",
+            DATA_TYPE_KANJI => "KANJI",
+            _ => "unknown",
+        }
+    );
+    println!("    Length: {}", data.payload.len());
+    println!("    Payload: {}", payload_string(data));
+    if data.eci != 0 {
+        println!("    ECI: {}", data.eci);
     }
 }
 
-pub unsafe fn run_tests(paths: Vec<&str>) {
-    let mut sum: ResultInfo = std::mem::uninitialized();
-    let mut count: i32 = 0i32;
+/// Scan a single image, timing each stage. The codes are returned as well
+/// for dumping.
+fn scan_image(
+    path: &Path,
+    category: String,
+    truth: Option<&Truth>,
+) -> (ImageResult, Vec<(Code, quirc_rs::quirc::Result<Data>)>) {
+    let mut result = ImageResult {
+        category,
+        ..Default::default()
+    };
+    let mut codes = Vec::new();
+
+    let start = Instant::now();
+    match image::open(path) {
+        Ok(img) => {
+            let img = img.to_luma8();
+            result.timings.load_ms = ms(start.elapsed());
+
+            let (width, height) = img.dimensions();
+            let mut pixels = img.into_raw();
+
+            let start = Instant::now();
+            let mut q = Quirc::new(Image::new(width, height, &mut pixels));
+            quirc_identify(&mut q);
+            result.timings.identify_ms = ms(start.elapsed());
+            result.identified = quirc_count(&q) as usize;
+
+            for i in 0..quirc_count(&q) {
+                let start = Instant::now();
+                let code = quirc_extract(&mut q, i).and_then(|c| Code::try_from(&c).ok());
+                result.timings.extract_ms += ms(start.elapsed());
 
-    println!("  {:30}  {:>17} {:>11}", "", "Time (ms)", "Count");
+                if let Some(code) = code {
+                    let start = Instant::now();
+                    let data = code.decode();
+                    result.timings.decode_ms += ms(start.elapsed());
+
+                    match &data {
+                        Ok(data) => result.decoded.push(payload_string(data)),
+                        Err(e) => result.errors.push(e.to_string()),
+                    }
+                    codes.push((code, data));
+                }
+            }
+        }
+        Err(e) => result.load_error = Some(e.to_string()),
+    }
+
+    result.counts = truth.map(|t| score(t, &result.decoded));
+    (result, codes)
+}
+
+fn print_header() {
     println!(
-        "  {:<30}  {:>5} {:>5} {:>5} {:>5} {:>5}",
-        "Filename", "Load", "ID", "Total", "ID", "Dec"
+        "  {:<30}  {:^35}  {:^7}  {:^11}  {:^9}",
+        "", "Time (ms)", "Count", "Truth", "Score"
     );
-    println!("-------------------------------------------------------------------------------");
-    memset(
-        &mut sum as (*mut ResultInfo) as (*mut ::std::os::raw::c_void),
-        0i32,
-        ::std::mem::size_of::<ResultInfo>(),
+    println!(
+        "  {:<30}  {:>6} {:>6} {:>6} {:>6} {:>7}  {:>3} {:>3}  {:>3} {:>3} {:>3}  {:>4} {:>4}",
+        "Filename",
+        "Load",
+        "ID",
+        "Ext",
+        "Dec",
+        "Total",
+        "ID",
+        "Dec",
+        "TP",
+        "FP",
+        "FN",
+        "Prec",
+        "Rec"
     );
+    println!("{:-<100}", "");
+}
+
+fn print_row(
+    name: &str,
+    files: usize,
+    identified: usize,
+    decoded: usize,
+    counts: Option<&Counts>,
+    timings: &Timings,
+) {
+    // Long names go on a line of their own to keep the columns aligned
+    let mut name = name;
+    if name.chars().count() > 30 {
+        println!("  {}", name);
+        name = "";
+    }
+
+    let n = files.max(1) as f64;
+    println!(
+        "  {:<30}  {:6.1} {:6.1} {:6.1} {:6.1} {:7.1}  {:>3} {:>3}  {:>3} {:>3} {:>3}  {:>4} {:>4}",
+        name,
+        timings.load_ms / n,
+        timings.identify_ms / n,
+        timings.extract_ms / n,
+        timings.decode_ms / n,
+        timings.total_ms() / n,
+        identified,
+        decoded,
+        count(counts.map(|c| c.true_positives)),
+        count(counts.map(|c| c.false_positives)),
+        count(counts.map(|c| c.false_negatives)),
+        percent(counts.and_then(Counts::precision)),
+        percent(counts.and_then(Counts::recall)),
+    );
+}
+
+fn print_summaries(results: &Results) {
+    let mut categories: BTreeMap<&str, Summary> = BTreeMap::new();
+    let mut total = Summary::default();
+
+    for result in results.images.values() {
+        categories.entry(&result.category).or_default().add(result);
+        total.add(result);
+    }
+
+    println!("{:-<100}", "");
+    println!("Average time per file, by category:");
+    for (category, summary) in categories.iter().chain(Some((&"TOTAL", &total))) {
+        let name = format!("{} ({} files)", category, summary.files);
+        print_row(
+            &name,
+            summary.files,
+            summary.identified,
+            summary.decoded,
+            Some(&summary.counts),
+            &summary.timings,
+        );
+    }
+}
+
+/// Payloads in `a` but not in `b`, counting duplicates.
+fn missing_from<'a>(a: &'a [String], b: &[String]) -> Vec<&'a str> {
+    let mut rest: Vec<&str> = b.iter().map(String::as_str).collect();
+    let mut missing = Vec::new();
+
+    for payload in a {
+        match rest.iter().position(|p| p == payload) {
+            Some(i) => {
+                rest.swap_remove(i);
+            }
+            None => missing.push(payload.as_str()),
+        }
+    }
+    missing
+}
+
+/// Print the differences against the baseline, returning the number of
+/// regressed images. Timings are reported but never count as
+/// regressions, as they are too noisy.
+fn compare(baseline: &Results, results: &Results) -> usize {
+    let mut regressions = 0;
+    let mut times = (0.0, 0.0);
+
+    println!();
+    println!("Changes against baseline:");
+    for (name, result) in &results.images {
+        let base = match baseline.images.get(name) {
+            Some(base) => base,
+            None => {
+                println!("  {}: not in baseline", name);
+                continue;
+            }
+        };
+
+        times.0 += base.timings.total_ms();
+        times.1 += result.timings.total_ms();
+
+        let lost = missing_from(&base.decoded, &result.decoded);
+        let gained = missing_from(&result.decoded, &base.decoded);
+
+        // Without ground truth, any payload no longer decoded is
+        // suspicious.
+        let regressed = match (&base.counts, &result.counts) {
+            (Some(b), Some(r)) => {
+                r.true_positives < b.true_positives || r.false_positives > b.false_positives
+            }
+            _ => !lost.is_empty(),
+        };
+        let improved = match (&base.counts, &result.counts) {
+            (Some(b), Some(r)) => {
+                r.true_positives > b.true_positives || r.false_positives < b.false_positives
+            }
+            _ => !gained.is_empty(),
+        };
+
+        let verdict = if regressed {
+            regressions += 1;
+            "REGRESSED"
+        } else if improved {
+            "improved"
+        } else if !lost.is_empty() || !gained.is_empty() || base.identified != result.identified {
+            "changed"
+        } else {
+            continue;
+        };
+        println!(
+            "  {}: {} (identified {} -> {})",
+            name, verdict, base.identified, result.identified
+        );
+        for payload in lost {
+            println!("    - {:?}", payload);
+        }
+        for payload in gained {
+            println!("    + {:?}", payload);
+        }
+    }
+
+    for name in baseline.images.keys() {
+        if !results.images.contains_key(name) {
+            println!("  {}: not scanned", name);
+        }
+    }
+
+    if times.0 > 0.0 {
+        println!(
+            "Total time: {:.1} ms -> {:.1} ms ({:+.1}%)",
+            times.0,
+            times.1,
+            (times.1 / times.0 - 1.0) * 100.0
+        );
+    }
+    println!("{} regression(s)", regressions);
+    regressions
+}
+
+fn run(
+    paths: Vec<&str>,
+    manifest: Option<&str>,
+    baseline: Option<&str>,
+    save: Option<&str>,
+    flags: &Flags,
+) -> Result<usize, Box<dyn Error>> {
+    let manifest = match manifest {
+        Some(path) => load_manifest(Path::new(path))?,
+        None => HashMap::new(),
+    };
+    let baseline = match baseline {
+        Some(path) => Some(load_results(Path::new(path))?),
+        None => None,
+    };
+
+    let mut images = Vec::new();
     for path in paths {
-        let mut info: ResultInfo = std::mem::uninitialized();
-        if test_scan(Path::new(path), &mut info) > 0i32 {
-            add_result(&mut sum, &mut info);
-            count += 1;
+        collect_images(Path::new(path), &mut images)?;
+    }
+
+    let mut results = Results::default();
+    print_header();
+    for path in images {
+        let entry = path.canonicalize().ok().and_then(|p| manifest.get(&p));
+        let name = entry.map_or_else(|| path.display().to_string(), |e| e.name.clone());
+        let category = entry
+            .and_then(|e| e.truth.category.clone())
+            .or_else(|| {
+                let dir = path.parent()?.file_name()?;
+                Some(dir.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "-".to_owned());
+
+        let (result, codes) = scan_image(&path, category, entry.map(|e| &e.truth));
+        print_row(
+            &name,
+            1,
+            result.identified,
+            result.decoded.len(),
+            result.counts.as_ref(),
+            &result.timings,
+        );
+
+        if let Some(e) = &result.load_error {
+            println!("    ERROR: {}", e);
+        }
+        for (code, data) in &codes {
+            if flags.cell_dump {
+                dump_cells(code);
+                println!();
+            }
+            if flags.verbose {
+                match data {
+                    Ok(data) => {
+                        println!("  Decode successful:");
+                        dump_data(data);
+                    }
+                    Err(e) => println!("  ERROR: {}", e),
+                }
+                println!();
+            }
         }
+
+        results.images.insert(name, result);
     }
-    if count > 1i32 {
-        print_result("TOTAL", &mut sum);
+
+    if results.images.len() > 1 {
+        print_summaries(&results);
+    }
+
+    let regressions = match &baseline {
+        Some(baseline) => compare(baseline, &results),
+        None => 0,
+    };
+    if let Some(path) = save {
+        save_results(Path::new(path), &results)?;
     }
+    Ok(regressions)
 }
 
 fn main() {
-    unsafe { _c_main() }
-}
-
-pub unsafe fn _c_main() {
-    let cell_dump_arg_name = "cell-dump";
-    let cell_dump_arg = Arg::with_name(cell_dump_arg_name)
-        .short("d")
-        .help("Dumps cell data");
-    let no_validation_arg_name = "no-validate";
-    let no_validation_arg = Arg::with_name(no_validation_arg_name)
-        .long(no_validation_arg_name)
-        .help("Disables validating the results against quirc");
-    let verbose_arg_name = "verbose";
-    let verbose_arg = Arg::with_name(verbose_arg_name)
-        .short("v")
-        .help("Enables verbose output");
-    let paths_arg_name = "paths";
-    let paths_arg = Arg::with_name(paths_arg_name).multiple(true).required(true);
-
     let args = App::new("qrtest")
-        .about("quirc test program")
+        .about("quirc benchmark and regression harness")
         .version(quirc_version())
         .author("Copyright (C) 2010-2012 Daniel Beer <dlbeer@gmail.com>")
-        .args(&[cell_dump_arg, no_validation_arg, verbose_arg, paths_arg]);
+        .arg(
+            Arg::with_name("cell-dump")
+                .short("d")
+                .help("Dumps cell data"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .help("Enables verbose output"),
+        )
+        .arg(
+            Arg::with_name("manifest")
+                .short("m")
+                .long("manifest")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Ground-truth manifest listing the expected payloads"),
+        )
+        .arg(
+            Arg::with_name("baseline")
+                .short("b")
+                .long("baseline")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Compares the results against a saved baseline"),
+        )
+        .arg(
+            Arg::with_name("save")
+                .short("s")
+                .long("save")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Saves the results for use as a baseline"),
+        )
+        .arg(Arg::with_name("paths").multiple(true).required(true));
 
     let matches = args.get_matches();
-    WANT_CELL_DUMP = matches.is_present(cell_dump_arg_name);
-    WANT_VALIDATE = !matches.is_present(no_validation_arg_name);
-    WANT_VERBOSE = matches.is_present(verbose_arg_name);
-    let paths = matches.values_of(paths_arg_name).unwrap();
+    let flags = Flags {
+        cell_dump: matches.is_present("cell-dump"),
+        verbose: matches.is_present("verbose"),
+    };
 
-    run_tests(paths.collect())
+    match run(
+        matches.values_of("paths").unwrap().collect(),
+        matches.value_of("manifest"),
+        matches.value_of("baseline"),
+        matches.value_of("save"),
+        &flags,
+    ) {
+        Ok(0) => {}
+        Ok(_) => process::exit(1),
+        Err(e) => {
+            eprintln!("qrtest: {}", e);
+            process::exit(2);
+        }
+    }
 }
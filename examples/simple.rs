@@ -13,16 +13,8 @@
  * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
-extern crate image;
-extern crate quirc_rs;
-
-use std::ffi::CStr;
-
-use libc::c_char;
-
-use quirc_rs::decode::*;
-use quirc_rs::identify::*;
-use quirc_rs::quirc::*;
+use quirc_rs::image::quirc_decode_image;
+use quirc_rs::quirc::quirc_strerror;
 
 fn main() {
     let args: Vec<_> = std::env::args().collect();
@@ -35,31 +27,17 @@ fn main() {
         _ => panic!(),
     };
 
-    let img = image::open(&image_path).unwrap().grayscale().to_luma();
-    let (width, height) = img.dimensions();
-    let mut image_bytes = img.into_raw();
-
-    let mut decoder = Quirc::new(Image::new(width, height, &mut image_bytes));
-    quirc_identify(&mut decoder);
+    let img = image::open(image_path).unwrap();
+    let codes = quirc_decode_image(&img);
 
-    let count: i32 = quirc_count(&decoder);
-    println!("Found {} QR codes", count);
-    for i in 0..count {
-        let code = quirc_extract(&mut decoder, i).unwrap();
-        let result = quirc_decode(&code);
+    println!("Found {} QR codes", codes.len());
+    for (_, result) in codes {
         match result {
             Ok(data) => {
                 println!("  Decoding successful:");
                 println!("    Data type: {}", data.data_type);
-                println!("    Length: {}", data.payload_len);
-                unsafe {
-                    println!(
-                        "    Payload: {}",
-                        CStr::from_ptr(data.payload.as_ptr() as *const c_char)
-                            .to_str()
-                            .unwrap()
-                    );
-                }
+                println!("    Length: {}", data.payload.len());
+                println!("    Payload: {}", String::from_utf8_lossy(&data.payload));
             }
             Err(e) => println!("  Decoding FAILED: {}", quirc_strerror(e)),
         }
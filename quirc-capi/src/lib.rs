@@ -0,0 +1,6 @@
+//! Builds `libquirc.so` and `libquirc.a` from the C API of quirc-rs.
+//!
+//! The library crate itself can't list these crate types, as they
+//! can't be built without `std`.
+
+pub use quirc_rs::capi::*;
@@ -0,0 +1,295 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! `quirc` -- scan images for QR-codes.
+
+mod output;
+mod scan;
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
+
+use clap::{App, AppSettings, Arg, ErrorKind};
+
+use quirc_rs::quirc::Binarizer;
+
+use crate::output::{Format, Writer};
+use crate::scan::{Options, Report};
+
+/// Every input had at least one decoded code
+const EXIT_OK: i32 = 0;
+/// Some input had no decodable code
+const EXIT_NOT_FOUND: i32 = 1;
+/// Some input couldn't be read, or the arguments were invalid
+const EXIT_ERROR: i32 = 2;
+
+const AFTER_HELP: &str = "\
+Directories are scanned recursively for image files. A path of - reads
+raw 8-bit grayscale frames of --size bytes from stdin until it is closed.
+
+The json format writes one object per input and line.
+
+Exit status is 0 if a code was decoded from every input, 1 if some
+input had none, and 2 if some input couldn't be read.";
+
+fn parse_binarizer(s: &str) -> Option<Binarizer> {
+    match s {
+        "adaptive" => Some(Binarizer::Adaptive),
+        "otsu" => Some(Binarizer::Otsu),
+        _ => s.parse().ok().map(Binarizer::Fixed),
+    }
+}
+
+fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let mut parts = s.splitn(2, 'x');
+    let w = parts.next()?.parse().ok()?;
+    let h = parts.next()?.parse().ok()?;
+    if w == 0 || h == 0 {
+        return None;
+    }
+    Some((w, h))
+}
+
+/// Scans inputs and keeps track of the exit status.
+struct Scanner<'a, W: io::Write> {
+    opts: Options,
+    size: Option<(u32, u32)>,
+    out: &'a mut Writer<W>,
+    status: i32,
+}
+
+impl<'a, W: io::Write> Scanner<'a, W> {
+    fn report(&mut self, report: Report) -> io::Result<()> {
+        let status = if report.error.is_some() {
+            EXIT_ERROR
+        } else if report.decoded() == 0 {
+            EXIT_NOT_FOUND
+        } else {
+            EXIT_OK
+        };
+        self.status = self.status.max(status);
+        self.out.write(&report)
+    }
+
+    fn scan_file(&mut self, path: &Path) -> io::Result<()> {
+        let input = path.display().to_string();
+        let start = Instant::now();
+
+        let report = match image::open(path) {
+            Ok(img) => {
+                let img = img.to_luma8();
+                let (width, height) = img.dimensions();
+                let pixels = img.into_raw();
+                scan::scan(input, width, height, pixels, start.elapsed(), &self.opts)
+            }
+            Err(e) => Report::failed(input, e.to_string()),
+        };
+
+        self.report(report)
+    }
+
+    fn scan_dir(&mut self, path: &Path) -> io::Result<()> {
+        let mut entries: Vec<PathBuf> = match path.read_dir() {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(e) => {
+                return self.report(Report::failed(path.display().to_string(), e.to_string()))
+            }
+        };
+        entries.sort();
+
+        for entry in entries {
+            let hidden = match entry.file_name() {
+                Some(name) => name.to_string_lossy().starts_with('.'),
+                None => true,
+            };
+            if hidden {
+                continue;
+            }
+
+            if entry.is_dir() {
+                self.scan_dir(&entry)?;
+            } else if image::ImageFormat::from_path(&entry).is_ok() {
+                self.scan_file(&entry)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn scan_stdin(&mut self) -> io::Result<()> {
+        let (width, height) = match self.size {
+            Some(size) => size,
+            None => {
+                let error = "reading frames from stdin needs --size".to_owned();
+                return self.report(Report::failed("-".to_owned(), error));
+            }
+        };
+
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        let mut frame = 0;
+
+        loop {
+            let start = Instant::now();
+            let mut pixels = vec![0; width as usize * height as usize];
+            match stdin.read_exact(&mut pixels) {
+                Ok(()) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            }
+
+            let input = format!("-:{}", frame);
+            let report = scan::scan(input, width, height, pixels, start.elapsed(), &self.opts);
+            self.report(report)?;
+            self.out.flush()?;
+            frame += 1;
+        }
+    }
+
+    fn scan_path(&mut self, path: &str) -> io::Result<()> {
+        let p = Path::new(path);
+
+        if path == "-" {
+            self.scan_stdin()
+        } else if p.is_dir() {
+            self.scan_dir(p)
+        } else {
+            self.scan_file(p)
+        }
+    }
+}
+
+fn main() {
+    let app = App::new("quirc")
+        .about("Scans images for QR-codes")
+        .version(env!("CARGO_PKG_VERSION"))
+        .setting(AppSettings::ArgRequiredElseHelp)
+        .after_help(AFTER_HELP)
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .takes_value(true)
+                .possible_values(Format::NAMES)
+                .default_value("text")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::with_name("binarizer")
+                .short("b")
+                .long("binarizer")
+                .takes_value(true)
+                .value_name("adaptive|otsu|0-255")
+                .default_value("adaptive")
+                .validator(|s| {
+                    parse_binarizer(&s)
+                        .map(|_| ())
+                        .ok_or_else(|| "expected adaptive, otsu or a threshold".to_owned())
+                })
+                .help("How to turn the image into black and white"),
+        )
+        .arg(
+            Arg::with_name("min-score")
+                .long("min-score")
+                .takes_value(true)
+                .value_name("0-1")
+                .default_value("0")
+                .validator(|s| {
+                    s.parse::<f64>()
+                        .map(|_| ())
+                        .map_err(|_| "expected a number".to_owned())
+                })
+                .help("Drops grids which fit the image worse than this"),
+        )
+        .arg(
+            Arg::with_name("invert")
+                .short("i")
+                .long("invert")
+                .help("Scans the negative of the images"),
+        )
+        .arg(
+            Arg::with_name("try-inverted")
+                .short("I")
+                .long("try-inverted")
+                .help("Also scans the negative if nothing was decoded"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .short("s")
+                .long("size")
+                .takes_value(true)
+                .value_name("WIDTHxHEIGHT")
+                .validator(|s| {
+                    parse_size(&s)
+                        .map(|_| ())
+                        .ok_or_else(|| "expected WIDTHxHEIGHT".to_owned())
+                })
+                .help("Frame size of raw grayscale input on stdin"),
+        )
+        .arg(
+            Arg::with_name("paths")
+                .multiple(true)
+                .required(true)
+                .help("Image files, directories, or - for stdin"),
+        );
+
+    let matches = match app.get_matches_safe() {
+        Ok(matches) => matches,
+        Err(e) => match e.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+            _ => {
+                eprintln!("{}", e.message);
+                process::exit(EXIT_ERROR);
+            }
+        },
+    };
+
+    let opts = Options {
+        binarizer: parse_binarizer(matches.value_of("binarizer").unwrap()).unwrap(),
+        min_grid_score: matches.value_of("min-score").unwrap().parse().unwrap(),
+        invert: matches.is_present("invert"),
+        try_inverted: matches.is_present("try-inverted"),
+    };
+    let format = Format::from_name(matches.value_of("format").unwrap()).unwrap();
+
+    let stdout = io::stdout();
+    let result = Writer::new(format, stdout.lock()).and_then(|mut out| {
+        let mut scanner = Scanner {
+            opts,
+            size: matches.value_of("size").and_then(parse_size),
+            out: &mut out,
+            status: EXIT_OK,
+        };
+
+        for path in matches.values_of("paths").unwrap() {
+            scanner.scan_path(path)?;
+        }
+
+        let status = scanner.status;
+        out.flush()?;
+        Ok(status)
+    });
+
+    match result {
+        Ok(status) => process::exit(status),
+        Err(e) => {
+            eprintln!("quirc: {}", e);
+            process::exit(EXIT_ERROR);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Writing scan reports as text, JSON Lines or CSV.
+
+use std::io::{self, Write};
+
+use crate::scan::{CodeReport, Report};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub const NAMES: &'static [&'static str] = &["text", "json", "csv"];
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+pub enum Writer<W: Write> {
+    Text(W),
+    Json(W),
+    Csv(Box<csv::Writer<W>>),
+}
+
+const CSV_HEADER: &[&str] = &[
+    "input",
+    "index",
+    "version",
+    "ecc_level",
+    "mask",
+    "data_type",
+    "eci",
+    "x0",
+    "y0",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "x3",
+    "y3",
+    "load_ms",
+    "identify_ms",
+    "decode_ms",
+    "inverted",
+    "error",
+    "payload",
+    "payload_hex",
+];
+
+fn opt<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(String::new, |v| v.to_string())
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(format: Format, out: W) -> io::Result<Self> {
+        Ok(match format {
+            Format::Text => Writer::Text(out),
+            Format::Json => Writer::Json(out),
+            Format::Csv => {
+                let mut w = csv::Writer::from_writer(out);
+                w.write_record(CSV_HEADER)?;
+                Writer::Csv(Box::new(w))
+            }
+        })
+    }
+
+    pub fn write(&mut self, report: &Report) -> io::Result<()> {
+        match self {
+            Writer::Text(out) => write_text(out, report),
+            Writer::Json(out) => {
+                serde_json::to_writer(&mut *out, report)?;
+                writeln!(out)
+            }
+            Writer::Csv(out) => write_csv(out, report),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::Text(out) | Writer::Json(out) => out.flush(),
+            Writer::Csv(out) => out.flush(),
+        }
+    }
+}
+
+fn write_text<W: Write>(out: &mut W, report: &Report) -> io::Result<()> {
+    if let Some(error) = &report.error {
+        return writeln!(out, "{}: error: {}", report.input, error);
+    }
+
+    let t = &report.timings;
+    writeln!(
+        out,
+        "{}: {} code{}, {} decoded{} (load {:.1} ms, identify {:.1} ms, decode {:.1} ms)",
+        report.input,
+        report.codes.len(),
+        if report.codes.len() == 1 { "" } else { "s" },
+        report.decoded(),
+        if report.inverted { ", inverted" } else { "" },
+        t.load_ms,
+        t.identify_ms,
+        t.decode_ms
+    )?;
+
+    for (i, code) in report.codes.iter().enumerate() {
+        let corners: Vec<String> = code
+            .corners
+            .iter()
+            .map(|p| format!("({},{})", p[0], p[1]))
+            .collect();
+
+        match &code.error {
+            None => {
+                write!(
+                    out,
+                    "  [{}] version {}, ECC {}, mask {}, {}",
+                    i,
+                    opt(code.version),
+                    opt(code.ecc_level),
+                    opt(code.mask),
+                    opt(code.data_type)
+                )?;
+                if let Some(eci) = code.eci {
+                    write!(out, ", ECI {}", eci)?;
+                }
+                writeln!(out, ", corners {}", corners.join(" "))?;
+                match (&code.payload, &code.payload_hex) {
+                    (Some(text), _) => writeln!(out, "      {}", text)?,
+                    (None, Some(hex)) => writeln!(out, "      hex: {}", hex)?,
+                    (None, None) => (),
+                }
+            }
+            Some(error) => writeln!(out, "  [{}] corners {}: {}", i, corners.join(" "), error)?,
+        }
+    }
+
+    Ok(())
+}
+
+fn csv_row(report: &Report, index: Option<usize>, code: Option<&CodeReport>) -> Vec<String> {
+    let t = &report.timings;
+    let mut row = vec![report.input.clone(), opt(index)];
+
+    match code {
+        Some(code) => {
+            row.extend(vec![
+                opt(code.version),
+                opt(code.ecc_level),
+                opt(code.mask),
+                opt(code.data_type),
+                opt(code.eci),
+            ]);
+            row.extend(
+                code.corners
+                    .iter()
+                    .flat_map(|p| vec![p[0].to_string(), p[1].to_string()]),
+            );
+        }
+        None => row.extend(vec![String::new(); 13]),
+    }
+
+    row.extend(vec![
+        format!("{:.3}", t.load_ms),
+        format!("{:.3}", t.identify_ms),
+        format!("{:.3}", t.decode_ms),
+        report.inverted.to_string(),
+        opt(code
+            .and_then(|c| c.error.clone())
+            .or_else(|| report.error.clone())),
+        opt(code.and_then(|c| c.payload.clone())),
+        opt(code.and_then(|c| c.payload_hex.clone())),
+    ]);
+
+    row
+}
+
+/// One row per code; inputs without codes get a single row with the
+/// code columns left empty.
+fn write_csv<W: Write>(out: &mut csv::Writer<W>, report: &Report) -> io::Result<()> {
+    if report.codes.is_empty() {
+        out.write_record(csv_row(report, None, None))?;
+    }
+
+    for (i, code) in report.codes.iter().enumerate() {
+        out.write_record(csv_row(report, Some(i), Some(code)))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::Timings;
+
+    fn reports() -> Vec<Report> {
+        let code = |x: i32, size: i32| CodeReport {
+            corners: [[x, 2], [x + size, 2], [x + size, 2 + size], [x, 2 + size]],
+            size,
+            version: None,
+            ecc_level: None,
+            mask: None,
+            data_type: None,
+            eci: None,
+            payload: None,
+            payload_hex: None,
+            error: None,
+        };
+        let decoded = CodeReport {
+            version: Some(1),
+            ecc_level: Some("M"),
+            mask: Some(3),
+            data_type: Some("byte"),
+            payload: Some("hi, there".to_string()),
+            payload_hex: Some("68692c207468657265".to_string()),
+            ..code(1, 21)
+        };
+        let failed = CodeReport {
+            error: Some("ECC failure in block 0 (1 corrected, ~2 uncorrectable)".to_string()),
+            ..code(40, 25)
+        };
+
+        vec![
+            Report {
+                input: "a.png".to_string(),
+                width: 100,
+                height: 80,
+                inverted: false,
+                timings: Timings {
+                    load_ms: 1.0,
+                    identify_ms: 2.5,
+                    decode_ms: 0.5,
+                },
+                codes: vec![decoded, failed],
+                error: None,
+            },
+            Report::failed("b.png".to_string(), "No such file".to_string()),
+        ]
+    }
+
+    fn written(format: Format) -> String {
+        let mut buf = Vec::new();
+        let mut out = Writer::new(format, &mut buf).unwrap();
+        for report in &reports() {
+            out.write(report).unwrap();
+        }
+        out.flush().unwrap();
+        drop(out);
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(
+            written(Format::Text),
+            concat!(
+                "a.png: 2 codes, 1 decoded (load 1.0 ms, identify 2.5 ms, decode 0.5 ms)\n",
+                "  [0] version 1, ECC M, mask 3, byte, corners (1,2) (22,2) (22,23) (1,23)\n",
+                "      hi, there\n",
+                "  [1] corners (40,2) (65,2) (65,27) (40,27): ",
+                "ECC failure in block 0 (1 corrected, ~2 uncorrectable)\n",
+                "b.png: error: No such file\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let written = written(Format::Json);
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+
+        let codes = &lines[0]["codes"];
+        assert_eq!(lines[0]["input"], "a.png");
+        assert_eq!(lines[0]["timings"]["identify_ms"], 2.5);
+        assert_eq!(codes[0]["version"], 1);
+        assert_eq!(codes[0]["payload"], "hi, there");
+        assert_eq!(codes[0]["corners"][2], serde_json::json!([22, 23]));
+        assert!(codes[1]["version"].is_null());
+        assert_eq!(lines[1]["error"], "No such file");
+        assert_eq!(lines[1]["codes"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_csv() {
+        let written = written(Format::Csv);
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER.join(","));
+        assert_eq!(
+            &lines[1..],
+            [
+                "a.png,0,1,M,3,byte,,1,2,22,2,22,23,1,23,1.000,2.500,0.500,false,,\
+                 \"hi, there\",68692c207468657265",
+                "a.png,1,,,,,,40,2,65,2,65,27,40,27,1.000,2.500,0.500,false,\
+                 \"ECC failure in block 0 (1 corrected, ~2 uncorrectable)\",,",
+                "b.png,,,,,,,,,,,,,,,0.000,0.000,0.000,false,No such file,,",
+            ]
+        );
+    }
+}
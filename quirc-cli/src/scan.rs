@@ -0,0 +1,246 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Scanning a single grayscale frame.
+
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use quirc_rs::identify::*;
+use quirc_rs::quirc::consts::*;
+use quirc_rs::quirc::*;
+
+/// How to scan each frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    pub binarizer: Binarizer,
+
+    /// Drop grids scoring below this, see `Grid::score`
+    pub min_grid_score: f64,
+
+    /// Scan the negative of the image
+    pub invert: bool,
+
+    /// Scan the negative as well if nothing was decoded
+    pub try_inverted: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Timings {
+    pub load_ms: f64,
+    pub identify_ms: f64,
+    pub decode_ms: f64,
+}
+
+/// A code found in a frame. The decoded fields are missing if decoding
+/// failed.
+#[derive(Clone, Debug, Serialize)]
+pub struct CodeReport {
+    pub corners: [[i32; 2]; 4],
+    pub size: i32,
+    pub version: Option<i32>,
+    pub ecc_level: Option<&'static str>,
+    pub mask: Option<i32>,
+    pub data_type: Option<&'static str>,
+    pub eci: Option<u32>,
+
+    /// The payload as text, if it could be converted
+    pub payload: Option<String>,
+    pub payload_hex: Option<String>,
+    pub error: Option<String>,
+}
+
+impl CodeReport {
+    pub fn is_decoded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Result of scanning a single input.
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    pub input: String,
+    pub width: u32,
+    pub height: u32,
+
+    /// Whether the codes were found in the negative of the image
+    pub inverted: bool,
+    pub timings: Timings,
+    pub codes: Vec<CodeReport>,
+
+    /// Set if the input couldn't be read
+    pub error: Option<String>,
+}
+
+impl Report {
+    pub fn failed(input: String, error: String) -> Self {
+        Report {
+            input,
+            width: 0,
+            height: 0,
+            inverted: false,
+            timings: Timings::default(),
+            codes: Vec::new(),
+            error: Some(error),
+        }
+    }
+
+    pub fn decoded(&self) -> usize {
+        self.codes.iter().filter(|c| c.is_decoded()).count()
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn ecc_level_name(ecc_level: i32) -> &'static str {
+    match ecc_level {
+        ECC_LEVEL_M => "M",
+        ECC_LEVEL_L => "L",
+        ECC_LEVEL_H => "H",
+        ECC_LEVEL_Q => "Q",
+        _ => "?",
+    }
+}
+
+fn data_type_name(data_type: i32) -> &'static str {
+    match data_type {
+        DATA_TYPE_NUMERIC => "numeric",
+        DATA_TYPE_ALPHA => "alphanumeric",
+        DATA_TYPE_BYTE => "byte",
+        DATA_TYPE_KANJI => "kanji",
+        _ => "unknown",
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn report_code(code: &QuircCode) -> CodeReport {
+    let mut report = CodeReport {
+        corners: [[0; 2]; 4],
+        size: code.size,
+        version: None,
+        ecc_level: None,
+        mask: None,
+        data_type: None,
+        eci: None,
+        payload: None,
+        payload_hex: None,
+        error: None,
+    };
+    for (dst, p) in report.corners.iter_mut().zip(code.corners.iter()) {
+        *dst = [p.x, p.y];
+    }
+
+    match Code::try_from(code).and_then(|code| code.decode()) {
+        Ok(data) => {
+            report.version = Some(data.version);
+            report.ecc_level = Some(ecc_level_name(data.ecc_level));
+            report.mask = Some(data.mask);
+            report.data_type = Some(data_type_name(data.data_type));
+            report.eci = Some(data.eci).filter(|&eci| eci != 0);
+            report.payload = data.payload_text().ok();
+            report.payload_hex = Some(hex(&data.payload));
+        }
+        Err(e) => report.error = Some(e.to_string()),
+    }
+
+    report
+}
+
+/// Identify and decode the codes in one binarization of `pixels`.
+fn scan_once(
+    width: u32,
+    height: u32,
+    pixels: &mut [u8],
+    opts: &Options,
+    timings: &mut Timings,
+) -> Vec<CodeReport> {
+    let start = Instant::now();
+    let mut q = Quirc::new(Image::new(width, height, pixels));
+    q.binarizer = opts.binarizer;
+    q.min_grid_score = opts.min_grid_score;
+    quirc_identify(&mut q);
+    timings.identify_ms += ms(start.elapsed());
+
+    let start = Instant::now();
+    let codes = (0..quirc_count(&q))
+        .filter_map(|i| quirc_extract(&mut q, i))
+        .map(|code| report_code(&code))
+        .collect();
+    timings.decode_ms += ms(start.elapsed());
+
+    codes
+}
+
+fn invert(pixels: &mut [u8]) {
+    pixels.iter_mut().for_each(|p| *p = 255 - *p);
+}
+
+/// Scan a grayscale frame, which was loaded in `load_time`.
+pub fn scan(
+    input: String,
+    width: u32,
+    height: u32,
+    mut pixels: Vec<u8>,
+    load_time: Duration,
+    opts: &Options,
+) -> Report {
+    let mut timings = Timings {
+        load_ms: ms(load_time),
+        ..Default::default()
+    };
+
+    if opts.invert {
+        invert(&mut pixels);
+    }
+
+    // quirc_identify() binarizes in place, so keep a copy for retrying
+    let original = if opts.try_inverted {
+        Some(pixels.clone())
+    } else {
+        None
+    };
+
+    let mut inverted = opts.invert;
+    let mut codes = scan_once(width, height, &mut pixels, opts, &mut timings);
+
+    if let Some(mut pixels) = original {
+        if !codes.iter().any(CodeReport::is_decoded) {
+            invert(&mut pixels);
+            let retry = scan_once(width, height, &mut pixels, opts, &mut timings);
+            if retry.iter().any(CodeReport::is_decoded) {
+                codes = retry;
+                inverted = !inverted;
+            }
+        }
+    }
+
+    Report {
+        input,
+        width,
+        height,
+        inverted,
+        timings,
+        codes,
+        error: None,
+    }
+}
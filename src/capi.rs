@@ -0,0 +1,288 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! C API compatible with libquirc.
+//!
+//! The header is generated into `OUT_DIR` when building with the `capi`
+//! feature, and checked in as `include/quirc.h`.
+//!
+//! A panic never unwinds into C: each function returns what libquirc
+//! returns on failure instead, and does the same for a NULL recognizer.
+
+#![allow(non_camel_case_types)]
+
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::decode;
+use crate::identify;
+use crate::quirc::*;
+
+/// Decoder state. This is opaque to C callers.
+pub struct quirc {
+    pixels: Vec<u8>,
+    w: c_int,
+    h: c_int,
+
+    /// Codes extracted by the last call to `quirc_end()`
+    codes: Vec<QuircCode>,
+}
+
+/// This enum describes the various decoder errors which may occur.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum quirc_decode_error_t {
+    QUIRC_SUCCESS = 0,
+    QUIRC_ERROR_INVALID_GRID_SIZE,
+    QUIRC_ERROR_INVALID_VERSION,
+    QUIRC_ERROR_FORMAT_ECC,
+    QUIRC_ERROR_DATA_ECC,
+    QUIRC_ERROR_UNKNOWN_DATA_TYPE,
+    QUIRC_ERROR_DATA_OVERFLOW,
+    QUIRC_ERROR_DATA_UNDERFLOW,
+}
+
+impl From<DecodeError> for quirc_decode_error_t {
+    fn from(err: DecodeError) -> Self {
+        use self::quirc_decode_error_t::*;
+
+        match err {
+            DecodeError::InvalidGridSize => QUIRC_ERROR_INVALID_GRID_SIZE,
+            DecodeError::InvalidVersion => QUIRC_ERROR_INVALID_VERSION,
+            DecodeError::FormatEcc => QUIRC_ERROR_FORMAT_ECC,
+            DecodeError::DataEcc { .. } => QUIRC_ERROR_DATA_ECC,
+            DecodeError::UnknownDataType => QUIRC_ERROR_UNKNOWN_DATA_TYPE,
+            DecodeError::DataOverflow => QUIRC_ERROR_DATA_OVERFLOW,
+            DecodeError::DataUnderflow { .. } => QUIRC_ERROR_DATA_UNDERFLOW,
+        }
+    }
+}
+
+/// Run `f`, returning `failed` if it panics rather than unwinding into the
+/// C caller.
+fn guard<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(failed)
+}
+
+/// Obtain the library version string.
+#[no_mangle]
+pub extern "C" fn quirc_version() -> *const c_char {
+    guard(ptr::null(), || b"1.0\0".as_ptr() as *const c_char)
+}
+
+/// Construct a new QR-code recognizer. This function will return NULL
+/// if sufficient memory could not be allocated.
+#[no_mangle]
+pub extern "C" fn quirc_new() -> *mut quirc {
+    guard(ptr::null_mut(), || {
+        Box::into_raw(Box::new(quirc {
+            pixels: Vec::new(),
+            w: 0,
+            h: 0,
+            codes: Vec::new(),
+        }))
+    })
+}
+
+/// Destroy a QR-code recognizer.
+///
+/// # Safety
+///
+/// `q` must have been returned by `quirc_new()` and not destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn quirc_destroy(q: *mut quirc) {
+    if !q.is_null() {
+        guard((), || drop(Box::from_raw(q)));
+    }
+}
+
+/// Resize the QR-code recognizer. The size of an image must be
+/// specified before codes can be analyzed.
+///
+/// This function returns 0 on success, or -1 if sufficient memory could
+/// not be allocated.
+///
+/// # Safety
+///
+/// `q` must be NULL or point to a live recognizer.
+#[no_mangle]
+pub unsafe extern "C" fn quirc_resize(q: *mut quirc, w: c_int, h: c_int) -> c_int {
+    guard(-1, || {
+        let q = match q.as_mut() {
+            Some(q) => q,
+            None => return -1,
+        };
+        if w < 0 || h < 0 {
+            return -1;
+        }
+        let len = match (w as usize).checked_mul(h as usize) {
+            Some(len) => len,
+            None => return -1,
+        };
+
+        let mut pixels = Vec::new();
+        if pixels.try_reserve_exact(len).is_err() {
+            return -1;
+        }
+        pixels.resize(len, 0);
+
+        q.pixels = pixels;
+        q.w = w;
+        q.h = h;
+        q.codes.clear();
+        0
+    })
+}
+
+/// Return the number of QR-codes identified in the last processed
+/// image.
+///
+/// # Safety
+///
+/// `q` must be NULL or point to a live recognizer.
+#[no_mangle]
+pub unsafe extern "C" fn quirc_count(q: *const quirc) -> c_int {
+    guard(0, || q.as_ref().map_or(0, |q| q.codes.len() as c_int))
+}
+
+/// These functions are used to process images for QR-code recognition.
+/// `quirc_begin()` must first be called to obtain access to a buffer
+/// into which the input image should be placed. Optionally, the current
+/// width and height may be returned. NULL is returned for a NULL
+/// recognizer.
+///
+/// # Safety
+///
+/// `q`, `w` and `h` must each be NULL or valid, `q` pointing to a live
+/// recognizer.
+#[no_mangle]
+pub unsafe extern "C" fn quirc_begin(q: *mut quirc, w: *mut c_int, h: *mut c_int) -> *mut u8 {
+    guard(ptr::null_mut(), || {
+        let q = match q.as_mut() {
+            Some(q) => q,
+            None => return ptr::null_mut(),
+        };
+
+        q.codes.clear();
+        if !w.is_null() {
+            *w = q.w;
+        }
+        if !h.is_null() {
+            *h = q.h;
+        }
+
+        q.pixels.as_mut_ptr()
+    })
+}
+
+/// After filling the buffer, `quirc_end()` should be called to process
+/// the image for QR-code recognition. Nothing is found if the size of
+/// the image hasn't been set by `quirc_resize()`.
+///
+/// # Safety
+///
+/// `q` must be NULL or point to a live recognizer.
+#[no_mangle]
+pub unsafe extern "C" fn quirc_end(q: *mut quirc) {
+    let q = match q.as_mut() {
+        Some(q) => q,
+        None => return,
+    };
+    let (w, h) = (q.w, q.h);
+
+    q.codes = guard(Vec::new(), || {
+        if w <= 0 || h <= 0 || q.pixels.len() != w as usize * h as usize {
+            return Vec::new();
+        }
+
+        let mut decoder = Quirc::new(Image::new(w as u32, h as u32, &mut q.pixels));
+        identify::quirc_identify(&mut decoder);
+
+        (0..crate::quirc::quirc_count(&decoder))
+            .filter_map(|i| identify::quirc_extract(&mut decoder, i))
+            .collect()
+    });
+}
+
+/// Extract the QR-code specified by the given index. The code is zeroed
+/// if there's no such code.
+///
+/// # Safety
+///
+/// `q` must be NULL or point to a live recognizer, and `code` NULL or
+/// point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn quirc_extract(q: *const quirc, index: c_int, code: *mut QuircCode) {
+    if code.is_null() {
+        return;
+    }
+
+    ptr::write(code, Default::default());
+    guard((), || {
+        if let Some(q) = q.as_ref() {
+            if index >= 0 && (index as usize) < q.codes.len() {
+                ptr::write(code, q.codes[index as usize]);
+            }
+        }
+    });
+}
+
+/// Decode a QR-code, returning the payload data. A NULL code, or one
+/// which makes the decoder panic, is reported as having an invalid grid
+/// size.
+///
+/// # Safety
+///
+/// `code` must be NULL or point to a valid code, and `data` NULL or
+/// point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn quirc_decode(
+    code: *const QuircCode,
+    data: *mut QuircData,
+) -> quirc_decode_error_t {
+    let result = guard(Err(DecodeError::InvalidGridSize), || match code.as_ref() {
+        Some(code) => decode::quirc_decode(code),
+        None => Err(DecodeError::InvalidGridSize),
+    });
+    let (d, err) = match result {
+        Ok(d) => (d, quirc_decode_error_t::QUIRC_SUCCESS),
+        Err(e) => (Default::default(), e.into()),
+    };
+    if !data.is_null() {
+        ptr::write(data, d);
+    }
+    err
+}
+
+/// Return a string error message for an error code.
+#[no_mangle]
+pub extern "C" fn quirc_strerror(err: quirc_decode_error_t) -> *const c_char {
+    use self::quirc_decode_error_t::*;
+
+    let msg: &'static [u8] = guard(b"Unknown error\0", || match err {
+        QUIRC_SUCCESS => b"Success\0",
+        QUIRC_ERROR_INVALID_GRID_SIZE => b"Invalid grid size\0",
+        QUIRC_ERROR_INVALID_VERSION => b"Invalid version\0",
+        QUIRC_ERROR_FORMAT_ECC => b"Format data ECC failure\0",
+        QUIRC_ERROR_DATA_ECC => b"ECC failure\0",
+        QUIRC_ERROR_UNKNOWN_DATA_TYPE => b"Unknown data type\0",
+        QUIRC_ERROR_DATA_OVERFLOW => b"Data overflow\0",
+        QUIRC_ERROR_DATA_UNDERFLOW => b"Data underflow\0",
+    });
+
+    msg.as_ptr() as *const c_char
+}
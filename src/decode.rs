@@ -14,269 +14,40 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
+use crate::identify::{extract_grid, quirc_extract_into};
 use crate::quirc::consts::*;
 use crate::quirc::*;
+use crate::rs::*;
 use crate::version_db::*;
 
-const MAX_POLY: usize = 64;
+use alloc::vec::Vec;
 
-/*************************************************************************
- * Galois fields
- */
-
-const GF16_EXP: [u8; 16] = [
-    0x01, 0x02, 0x04, 0x08, 0x03, 0x06, 0x0c, 0x0b, 0x05, 0x0a, 0x07, 0x0e, 0x0f, 0x0d, 0x09, 0x01,
-];
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-const GF16_LOG: [u8; 16] = [
-    0x00, 0x0f, 0x01, 0x04, 0x02, 0x08, 0x05, 0x0a, 0x03, 0x0e, 0x09, 0x07, 0x06, 0x0d, 0x0b, 0x0c,
-];
-
-#[derive(Copy)]
-#[repr(C)]
-struct GaloisField {
-    p: i32,
-    log: &'static [u8],
-    exp: &'static [u8],
-}
-
-impl Clone for GaloisField {
-    fn clone(&self) -> Self {
-        *self
-    }
-}
-
-const GF16: GaloisField = GaloisField {
-    p: 15,
-    log: &GF16_LOG,
-    exp: &GF16_EXP,
-};
-
-const GF256_EXP: [u8; 256] = [
-    0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1d, 0x3a, 0x74, 0xe8, 0xcd, 0x87, 0x13, 0x26,
-    0x4c, 0x98, 0x2d, 0x5a, 0xb4, 0x75, 0xea, 0xc9, 0x8f, 0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0xc0,
-    0x9d, 0x27, 0x4e, 0x9c, 0x25, 0x4a, 0x94, 0x35, 0x6a, 0xd4, 0xb5, 0x77, 0xee, 0xc1, 0x9f, 0x23,
-    0x46, 0x8c, 0x05, 0x0a, 0x14, 0x28, 0x50, 0xa0, 0x5d, 0xba, 0x69, 0xd2, 0xb9, 0x6f, 0xde, 0xa1,
-    0x5f, 0xbe, 0x61, 0xc2, 0x99, 0x2f, 0x5e, 0xbc, 0x65, 0xca, 0x89, 0x0f, 0x1e, 0x3c, 0x78, 0xf0,
-    0xfd, 0xe7, 0xd3, 0xbb, 0x6b, 0xd6, 0xb1, 0x7f, 0xfe, 0xe1, 0xdf, 0xa3, 0x5b, 0xb6, 0x71, 0xe2,
-    0xd9, 0xaf, 0x43, 0x86, 0x11, 0x22, 0x44, 0x88, 0x0d, 0x1a, 0x34, 0x68, 0xd0, 0xbd, 0x67, 0xce,
-    0x81, 0x1f, 0x3e, 0x7c, 0xf8, 0xed, 0xc7, 0x93, 0x3b, 0x76, 0xec, 0xc5, 0x97, 0x33, 0x66, 0xcc,
-    0x85, 0x17, 0x2e, 0x5c, 0xb8, 0x6d, 0xda, 0xa9, 0x4f, 0x9e, 0x21, 0x42, 0x84, 0x15, 0x2a, 0x54,
-    0xa8, 0x4d, 0x9a, 0x29, 0x52, 0xa4, 0x55, 0xaa, 0x49, 0x92, 0x39, 0x72, 0xe4, 0xd5, 0xb7, 0x73,
-    0xe6, 0xd1, 0xbf, 0x63, 0xc6, 0x91, 0x3f, 0x7e, 0xfc, 0xe5, 0xd7, 0xb3, 0x7b, 0xf6, 0xf1, 0xff,
-    0xe3, 0xdb, 0xab, 0x4b, 0x96, 0x31, 0x62, 0xc4, 0x95, 0x37, 0x6e, 0xdc, 0xa5, 0x57, 0xae, 0x41,
-    0x82, 0x19, 0x32, 0x64, 0xc8, 0x8d, 0x07, 0x0e, 0x1c, 0x38, 0x70, 0xe0, 0xdd, 0xa7, 0x53, 0xa6,
-    0x51, 0xa2, 0x59, 0xb2, 0x79, 0xf2, 0xf9, 0xef, 0xc3, 0x9b, 0x2b, 0x56, 0xac, 0x45, 0x8a, 0x09,
-    0x12, 0x24, 0x48, 0x90, 0x3d, 0x7a, 0xf4, 0xf5, 0xf7, 0xf3, 0xfb, 0xeb, 0xcb, 0x8b, 0x0b, 0x16,
-    0x2c, 0x58, 0xb0, 0x7d, 0xfa, 0xe9, 0xcf, 0x83, 0x1b, 0x36, 0x6c, 0xd8, 0xad, 0x47, 0x8e, 0x01,
-];
-
-const GF256_LOG: [u8; 256] = [
-    0x00, 0xff, 0x01, 0x19, 0x02, 0x32, 0x1a, 0xc6, 0x03, 0xdf, 0x33, 0xee, 0x1b, 0x68, 0xc7, 0x4b,
-    0x04, 0x64, 0xe0, 0x0e, 0x34, 0x8d, 0xef, 0x81, 0x1c, 0xc1, 0x69, 0xf8, 0xc8, 0x08, 0x4c, 0x71,
-    0x05, 0x8a, 0x65, 0x2f, 0xe1, 0x24, 0x0f, 0x21, 0x35, 0x93, 0x8e, 0xda, 0xf0, 0x12, 0x82, 0x45,
-    0x1d, 0xb5, 0xc2, 0x7d, 0x6a, 0x27, 0xf9, 0xb9, 0xc9, 0x9a, 0x09, 0x78, 0x4d, 0xe4, 0x72, 0xa6,
-    0x06, 0xbf, 0x8b, 0x62, 0x66, 0xdd, 0x30, 0xfd, 0xe2, 0x98, 0x25, 0xb3, 0x10, 0x91, 0x22, 0x88,
-    0x36, 0xd0, 0x94, 0xce, 0x8f, 0x96, 0xdb, 0xbd, 0xf1, 0xd2, 0x13, 0x5c, 0x83, 0x38, 0x46, 0x40,
-    0x1e, 0x42, 0xb6, 0xa3, 0xc3, 0x48, 0x7e, 0x6e, 0x6b, 0x3a, 0x28, 0x54, 0xfa, 0x85, 0xba, 0x3d,
-    0xca, 0x5e, 0x9b, 0x9f, 0x0a, 0x15, 0x79, 0x2b, 0x4e, 0xd4, 0xe5, 0xac, 0x73, 0xf3, 0xa7, 0x57,
-    0x07, 0x70, 0xc0, 0xf7, 0x8c, 0x80, 0x63, 0x0d, 0x67, 0x4a, 0xde, 0xed, 0x31, 0xc5, 0xfe, 0x18,
-    0xe3, 0xa5, 0x99, 0x77, 0x26, 0xb8, 0xb4, 0x7c, 0x11, 0x44, 0x92, 0xd9, 0x23, 0x20, 0x89, 0x2e,
-    0x37, 0x3f, 0xd1, 0x5b, 0x95, 0xbc, 0xcf, 0xcd, 0x90, 0x87, 0x97, 0xb2, 0xdc, 0xfc, 0xbe, 0x61,
-    0xf2, 0x56, 0xd3, 0xab, 0x14, 0x2a, 0x5d, 0x9e, 0x84, 0x3c, 0x39, 0x53, 0x47, 0x6d, 0x41, 0xa2,
-    0x1f, 0x2d, 0x43, 0xd8, 0xb7, 0x7b, 0xa4, 0x76, 0xc4, 0x17, 0x49, 0xec, 0x7f, 0x0c, 0x6f, 0xf6,
-    0x6c, 0xa1, 0x3b, 0x52, 0x29, 0x9d, 0x55, 0xaa, 0xfb, 0x60, 0x86, 0xb1, 0xbb, 0xcc, 0x3e, 0x5a,
-    0xcb, 0x59, 0x5f, 0xb0, 0x9c, 0xa9, 0xa0, 0x51, 0x0b, 0xf5, 0x16, 0xeb, 0x7a, 0x75, 0x2c, 0xd7,
-    0x4f, 0xae, 0xd5, 0xe9, 0xe6, 0xe7, 0xad, 0xe8, 0x74, 0xd6, 0xf4, 0xea, 0xa8, 0x50, 0x58, 0xaf,
-];
-
-const GF256: GaloisField = GaloisField {
-    p: 255,
-    log: &GF256_LOG,
-    exp: &GF256_EXP,
-};
-
-/************************************************************************
- * Polynomial operations
- */
-
-fn poly_add(dst: &mut [u8; MAX_POLY], src: &[u8; MAX_POLY], c: u8, shift: i32, gf: &GaloisField) {
-    if c == 0 {
-        return;
-    }
-
-    let log_c: i32 = i32::from(gf.log[c as usize]);
-    for i in 0..MAX_POLY as i32 {
-        let p = i + shift;
-        let v: u8 = src[i as usize];
-
-        if !(p < 0 || p >= MAX_POLY as i32) && v != 0 {
-            dst[p as usize] ^= gf.exp[((i32::from(gf.log[v as usize]) + log_c) % gf.p) as usize];
-        }
-    }
-}
-
-fn poly_eval(s: &[u8; MAX_POLY], x: u8, gf: &GaloisField) -> u8 {
-    if x == 0 {
-        s[0]
-    } else {
-        let mut sum: u8 = 0;
-        let log_x: u8 = gf.log[x as usize];
-
-        for i in 0..MAX_POLY as i32 {
-            let c: u8 = s[i as usize];
-
-            if c != 0 {
-                sum ^= gf.exp
-                    [((i32::from(gf.log[c as usize]) + i32::from(log_x) * i) % gf.p) as usize];
-            }
-        }
-        sum
-    }
-}
-
-/// Berlekamp-Massey algorithm for finding error locator polynomials.
-fn berlekamp_massey(s: &[u8; MAX_POLY], N: usize, gf: &GaloisField) -> [u8; MAX_POLY] {
-    let mut C: [u8; MAX_POLY] = [0; MAX_POLY];
-    let mut B: [u8; MAX_POLY] = [0; MAX_POLY];
-    let mut L: usize = 0;
-    let mut m: i32 = 1;
-    let mut b: u8 = 1;
-
-    B[0] = 1;
-    C[0] = 1;
-
-    for n in 0..N {
-        let mut d: u8 = s[n];
-
-        for i in 1..=L {
-            if C[i] != 0 && (s[n - i] != 0) {
-                d ^= gf.exp[((i32::from(gf.log[C[i as usize] as usize])
-                    + i32::from(gf.log[s[n - i] as usize]))
-                    % gf.p) as usize];
-            }
-        }
-
-        let mult = gf.exp[((gf.p - i32::from(gf.log[b as usize]) + i32::from(gf.log[d as usize]))
-            % gf.p) as usize];
-
-        if d == 0 {
-            m += 1;
-        } else if L * 2 <= n {
-            let T = C;
-            poly_add(&mut C, &B, mult, m, gf);
-            B = T;
-            L = n + 1 - L;
-            b = d;
-            m = 1;
-        } else {
-            poly_add(&mut C, &B, mult, m, gf);
-            m += 1;
-        }
-    }
-
-    C
-}
+pub use crate::version_db::{MODEL1_MAX_VERSION, QUIRC_MAX_GRID_SIZE, QUIRC_MAX_VERSION};
 
 /************************************************************************
  * Code stream error correction
- *
- * Generator polynomial for GF(2^8) is x^8 + x^4 + x^3 + x^2 + 1
  */
 
-fn block_syndromes(data: &[u8], bs: i32, npar: i32) -> Option<[u8; MAX_POLY]> {
-    let mut nonzero: i32 = 0;
-    let mut s: [u8; MAX_POLY] = [0; MAX_POLY];
-
-    for i in 0..npar {
-        for j in 0..bs {
-            let c: u8 = data[(bs - j - 1) as usize];
-
-            if c != 0 {
-                s[i as usize] ^=
-                    GF256_EXP[((i32::from(GF256_LOG[c as usize]) + i * j) % 255) as usize];
-            }
-        }
-
-        if s[i as usize] != 0 {
-            nonzero = 1;
-        }
-    }
-
-    if nonzero != 0 {
-        Some(s)
-    } else {
-        None
-    }
-}
-
-fn eloc_poly(s: &[u8; MAX_POLY], sigma: &[u8; MAX_POLY], npar: i32) -> [u8; MAX_POLY] {
-    let mut omega: [u8; MAX_POLY] = [0; MAX_POLY];
-
-    for i in 0..npar as usize {
-        let a: u8 = sigma[i];
-        let log_a: u8 = GF256_LOG[a as usize];
-
-        if a != 0 {
-            for j in 0..MAX_POLY - 1 {
-                let b: u8 = s[j + 1];
-
-                if i + j >= npar as usize {
-                    break;
-                }
-
-                if b != 0 {
-                    omega[i + j] ^= GF256_EXP
-                        [((i32::from(log_a) + i32::from(GF256_LOG[b as usize])) % 255) as usize];
-                }
-            }
-        }
-    }
-
-    omega
-}
-
-fn correct_block(data: &mut [u8], ecc: &RsParams) -> Result<()> {
-    let npar: i32 = ecc.bs - ecc.dw;
-
-    /* Compute syndrome vector */
-    let s = block_syndromes(data, ecc.bs, npar);
-    if s.is_none() {
-        return Ok(());
-    }
-    let s = s.unwrap();
-
-    let sigma = berlekamp_massey(&s, npar as usize, &GF256);
-
-    /* Compute derivative of sigma */
-    let mut sigma_deriv: [u8; MAX_POLY] = [0; MAX_POLY];
-    for i in (0..MAX_POLY - 1).step_by(2) {
-        sigma_deriv[i] = sigma[i + 1];
-    }
-
-    /* Compute error evaluator polynomial */
-    let omega = eloc_poly(&s, &sigma, npar - 1);
-
-    /* Find error locations and magnitudes */
-    for i in 0..ecc.bs {
-        let xinv: u8 = GF256_EXP[(255 - i) as usize];
-
-        if poly_eval(&sigma, xinv, &GF256) == 0 {
-            let sd_x: u8 = poly_eval(&sigma_deriv, xinv, &GF256);
-            let omega_x: u8 = poly_eval(&omega, xinv, &GF256);
-            let error: u8 = GF256_EXP[((255 - i32::from(GF256_LOG[sd_x as usize])
-                + i32::from(GF256_LOG[omega_x as usize]))
-                % 255) as usize];
-
-            data[(ecc.bs - i - 1) as usize] ^= error;
-        }
-    }
-
-    if block_syndromes(data, ecc.bs, npar).is_some() {
-        Err(DecodeError::DataEcc)
-    } else {
-        Ok(())
-    }
+/// Correct a single RS block in place, returning the number of corrected
+/// codewords.
+fn correct_block(data: &mut [u8], ecc: &RsParams, block: i32) -> Result<i32> {
+    let rs = ReedSolomon::new(&GF256, 0, ecc.bs - ecc.dw).ok_or(DecodeError::InvalidVersion)?;
+
+    rs.decode(&mut data[..ecc.bs as usize])
+        .map_err(|err| match err {
+            RsError::Uncorrectable {
+                corrected,
+                uncorrectable,
+            } => DecodeError::DataEcc {
+                block,
+                corrected,
+                uncorrectable,
+            },
+            RsError::BlockLength { .. } | RsError::Symbol { .. } => DecodeError::InvalidVersion,
+        })
 }
 
 /************************************************************************
@@ -298,7 +69,7 @@ fn format_syndromes(u: u16) -> Option<[u8; MAX_POLY]> {
 
         for j in 0..FORMAT_BITS {
             if i32::from(u) & 1 << j != 0 {
-                s[i] ^= GF16_EXP[(i + 1) * j % 15];
+                s[i] ^= GF16.exp[(i + 1) * j % 15];
             }
         }
 
@@ -329,7 +100,7 @@ fn correct_format(f_ret: &mut u16) -> Result<()> {
 
     // Now, find the roots of the polynomial
     for i in 0..15 {
-        if poly_eval(&sigma, GF16_EXP[(15 - i) as usize], &GF16) == 0 {
+        if poly_eval(&sigma, GF16.exp[(15 - i) as usize], &GF16) == 0 {
             u = (i32::from(u) ^ 1 << i) as u16;
         }
     }
@@ -348,7 +119,7 @@ fn correct_format(f_ret: &mut u16) -> Result<()> {
 
 #[derive(Copy)]
 #[repr(C)]
-struct DataStream {
+pub(crate) struct DataStream {
     raw: [u8; MAX_PAYLOAD],
     data_bits: i32,
     ptr: i32,
@@ -372,36 +143,60 @@ impl Default for DataStream {
     }
 }
 
+impl DataStream {
+    /// Reset in place, rather than building another copy on the stack
+    pub(crate) fn clear(&mut self) {
+        self.raw.iter_mut().for_each(|b| *b = 0);
+        self.data.iter_mut().for_each(|b| *b = 0);
+        self.data_bits = 0;
+        self.ptr = 0;
+    }
+
+    /// Append a bit read from the grid to the raw codewords.
+    pub(crate) fn push_raw(&mut self, v: bool) {
+        if v {
+            self.raw[(self.data_bits >> 3) as usize] |= 0x80 >> (self.data_bits & 7);
+        }
+        self.data_bits += 1;
+    }
+}
+
 fn grid_bit(code: &QuircCode, x: i32, y: i32) -> bool {
     let p: i32 = y * code.size + x;
 
     i32::from(code.cell_bitmap[(p >> 3) as usize]) >> (p & 7) & 1 == 1
 }
 
-fn read_format(code: &QuircCode, mut data: &mut QuircData, which: i32) -> Result<()> {
+/// Read and correct the format information of a `size` cell grid, from
+/// around the top left capstone (`which` 0) or the other two (1), `dark`
+/// telling whether a cell is dark.
+pub(crate) fn format_info(which: i32, size: i32, dark: &dyn Fn(i32, i32) -> bool) -> Result<u16> {
     let mut format: u16 = 0;
 
     if which != 0 {
         for i in 0..7 {
-            format = (i32::from(format) << 1 | grid_bit(code, 8, code.size - 1 - i) as i32) as u16;
+            format = (i32::from(format) << 1 | dark(8, size - 1 - i) as i32) as u16;
         }
         for i in 0..8 {
-            format = (i32::from(format) << 1 | grid_bit(code, code.size - 8 + i, 8) as i32) as u16;
+            format = (i32::from(format) << 1 | dark(size - 8 + i, 8) as i32) as u16;
         }
     } else {
         const XS: [i32; 15] = [8, 8, 8, 8, 8, 8, 8, 8, 7, 5, 4, 3, 2, 1, 0];
         const YS: [i32; 15] = [0, 1, 2, 3, 4, 5, 7, 8, 8, 8, 8, 8, 8, 8, 8];
 
         for i in (0..=14).rev() {
-            format = (i32::from(format) << 1
-                | grid_bit(code, XS[i as usize], YS[i as usize]) as i32)
-                as u16;
+            format = (i32::from(format) << 1 | dark(XS[i as usize], YS[i as usize]) as i32) as u16;
         }
     }
 
     format = (i32::from(format) ^ 0x5412) as u16;
 
     correct_format(&mut format)?;
+    Ok(format)
+}
+
+fn read_format(code: &QuircCode, mut data: &mut QuircData, which: i32) -> Result<()> {
+    let format = format_info(which, code.size, &|x, y| grid_bit(code, x, y))?;
 
     let fdata = (i32::from(format) >> 10) as u16;
     data.ecc_level = i32::from(fdata) >> 3;
@@ -493,25 +288,15 @@ fn reserved_cell(version: i32, i: i32, j: i32) -> bool {
 }
 
 fn read_bit(code: &QuircCode, data: &QuircData, ds: &mut DataStream, i: i32, j: i32) {
-    let bitpos: i32 = ds.data_bits & 7;
-    let bytepos: i32 = ds.data_bits >> 3;
-    let mut v = grid_bit(code, j, i);
-
-    v ^= mask_bit(data.mask, i, j);
-
-    if v {
-        ds.raw[bytepos as usize] |= 0x80 >> bitpos
-    }
-
-    ds.data_bits += 1;
+    ds.push_raw(grid_bit(code, j, i) ^ mask_bit(data.mask, i, j));
 }
 
-fn read_data(code: &QuircCode, data: &QuircData) -> DataStream {
+fn read_data(code: &QuircCode, data: &QuircData, ds: &mut DataStream) {
     let mut y: i32 = code.size - 1;
     let mut x: i32 = code.size - 1;
     let mut dir: i32 = -1;
 
-    let mut ds: DataStream = Default::default();
+    ds.clear();
 
     while x > 0 {
         if x == 6 {
@@ -519,11 +304,11 @@ fn read_data(code: &QuircCode, data: &QuircData) -> DataStream {
         }
 
         if !reserved_cell(data.version, y, x) {
-            read_bit(code, data, &mut ds, y, x);
+            read_bit(code, data, ds, y, x);
         }
 
         if !reserved_cell(data.version, y, x - 1) {
-            read_bit(code, data, &mut ds, y, x - 1);
+            read_bit(code, data, ds, y, x - 1);
         }
 
         y += dir;
@@ -533,14 +318,92 @@ fn read_data(code: &QuircCode, data: &QuircData) -> DataStream {
             y += dir;
         }
     }
+}
 
-    ds
+/// Call `f` with the bottom right cell of each block of a Model 1 code of
+/// `version`, in order, whether it's 2 cells wide and 4 high rather than
+/// 4 wide and 2 high, and whether it's an extension pattern rather than a
+/// codeword. Extension patterns are a dark bar along the edge of the code,
+/// 4 cells long, with a light one inside it.
+pub(crate) fn model1_blocks(version: i32, f: &mut dyn FnMut(i32, i32, bool, bool)) {
+    let size: i32 = version * 4 + 17;
+    let columns: i32 = size / 4 + 3;
+
+    // Two column pairs up the right, below the top right finder. Every
+    // other block of the outer one bar the first and last is an extension
+    // pattern.
+    let rows: i32 = (size - 8) / 4;
+    for j in 0..2 {
+        for i in 0..rows {
+            let extension = j == 0 && i % 2 == 0 && i > 0 && i < rows - 1;
+            f(size - 1 - j * 2, size - 1 - i * 4, true, extension);
+        }
+    }
+
+    // Columns four cells wide up the middle, stepping over the timing
+    // pattern. The bottom block of every other column bar the last is an
+    // extension pattern.
+    let rows: i32 = size / 2;
+    for j in 2..columns - 4 {
+        for i in 0..rows {
+            if j == 2 && i >= rows - 4 {
+                continue;
+            }
+            let extension = i == 0 && j % 2 == 1 && j + 1 != columns - 4;
+            let y = size - 1 - i * 2 - if i >= rows - 3 { 1 } else { 0 };
+            f(size - 5 - (j - 2) * 4, y, false, extension);
+        }
+    }
+
+    // Column pairs up the left, between the finders
+    let rows: i32 = (size - 17) / 4;
+    for &x in &[8, 5, 3, 1] {
+        for i in 0..rows {
+            f(x, size - 9 - i * 4, true, false);
+        }
+    }
 }
 
-fn codestream_ecc(data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
-    let ver: &VersionInfo = &VERSION_DB[data.version as usize];
-    let sb_ecc: &RsParams = &ver.ecc[data.ecc_level as usize];
-    let lb_count: i32 = (ver.data_bytes - sb_ecc.bs * sb_ecc.ns) / (sb_ecc.bs + 1);
+/// Read the codewords of a Model 1 code. Rather than following two-column
+/// zigzags like `read_data()`, each one fills a block of cells, see
+/// `model1_blocks()`, from its bottom right cell.
+fn read_data_model1(code: &QuircCode, data: &QuircData, ds: &mut DataStream) {
+    ds.clear();
+
+    model1_blocks(data.version, &mut |x, y, vertical, extension| {
+        if extension {
+            return;
+        }
+        for b in 0..8 {
+            if vertical {
+                read_bit(code, data, ds, y - b / 2, x - b % 2);
+            } else {
+                read_bit(code, data, ds, y - b / 4, x - b % 4);
+            }
+        }
+    });
+}
+
+/// Error correction statistics for a single RS block.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BlockEcc {
+    /// Number of codewords corrected
+    pub corrected: i32,
+
+    /// Number of parity codewords in the block
+    pub parity: i32,
+}
+
+/// Deinterleave the raw codewords of `ds` into blocks laid out as
+/// `sb_ecc`, followed by blocks one word longer up to `data_bytes` words
+/// in all, and correct them.
+pub(crate) fn codestream_ecc(
+    data_bytes: i32,
+    sb_ecc: &RsParams,
+    ds: &mut DataStream,
+    mut stats: Option<&mut Vec<BlockEcc>>,
+) -> Result<()> {
+    let lb_count: i32 = (data_bytes - sb_ecc.bs * sb_ecc.ns) / (sb_ecc.bs + 1);
     let bc: i32 = lb_count + sb_ecc.ns;
     let ecc_offset: i32 = sb_ecc.dw * bc + lb_count;
     let mut dst_offset: i32 = 0;
@@ -553,15 +416,27 @@ fn codestream_ecc(data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
         let ecc: &RsParams = if i < sb_ecc.ns { sb_ecc } else { &lb_ecc };
         let num_ec: i32 = ecc.bs - ecc.dw;
 
-        for j in 0..ecc.dw {
+        for j in 0..sb_ecc.dw {
             ds.data[(dst_offset + j) as usize] = ds.raw[(j * bc + i) as usize];
         }
+        // The last data word of each long block comes after all the short
+        // blocks have run out
+        if i >= sb_ecc.ns {
+            ds.data[(dst_offset + sb_ecc.dw) as usize] =
+                ds.raw[(sb_ecc.dw * bc + i - sb_ecc.ns) as usize];
+        }
         for j in 0..num_ec {
             ds.data[(dst_offset + ecc.dw + j) as usize] =
                 ds.raw[(ecc_offset + j * bc + i) as usize];
         }
 
-        correct_block(&mut ds.data[dst_offset as usize..], ecc)?;
+        let corrected = correct_block(&mut ds.data[dst_offset as usize..], ecc, i)?;
+        if let Some(stats) = stats.as_mut() {
+            stats.push(BlockEcc {
+                corrected,
+                parity: num_ec,
+            });
+        }
 
         dst_offset += ecc.dw;
     }
@@ -593,9 +468,15 @@ fn take_bits(ds: &mut DataStream, mut len: i32) -> i32 {
     ret
 }
 
-fn numeric_tuple(data: &mut QuircData, ds: &mut DataStream, bits: i32, digits: i32) -> i32 {
+fn underflow(ds: &DataStream) -> DecodeError {
+    DecodeError::DataUnderflow {
+        remaining: bits_remaining(ds),
+    }
+}
+
+fn numeric_tuple(data: &mut QuircData, ds: &mut DataStream, bits: i32, digits: i32) -> Result<()> {
     if bits_remaining(ds) < bits {
-        -1
+        Err(underflow(ds))
     } else {
         let mut tuple = take_bits(ds, bits);
 
@@ -606,46 +487,36 @@ fn numeric_tuple(data: &mut QuircData, ds: &mut DataStream, bits: i32, digits: i
 
         data.payload_len += digits;
 
-        0
+        Ok(())
     }
 }
 
-fn decode_numeric(data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
-    let bits = match data.version {
-        v if v < 10 => 10,
-        v if v < 27 => 12,
-        _ => 14,
-    };
-
+fn decode_numeric(data: &mut QuircData, ds: &mut DataStream, bits: i32) -> Result<()> {
     let mut count = take_bits(ds, bits);
     if data.payload_len + count + 1 > MAX_PAYLOAD as i32 {
         return Err(DecodeError::DataOverflow);
     }
 
     while count >= 3 {
-        if numeric_tuple(data, ds, 10, 3) < 0 {
-            return Err(DecodeError::DataUnderflow);
-        }
+        numeric_tuple(data, ds, 10, 3)?;
         count -= 3;
     }
 
     if count >= 2 {
-        if numeric_tuple(data, ds, 7, 2) < 0 {
-            return Err(DecodeError::DataUnderflow);
-        }
+        numeric_tuple(data, ds, 7, 2)?;
         count -= 2;
     }
 
-    if count != 0 && numeric_tuple(data, ds, 4, 1) < 0 {
-        return Err(DecodeError::DataUnderflow);
+    if count != 0 {
+        numeric_tuple(data, ds, 4, 1)?;
     }
 
     Ok(())
 }
 
-fn alpha_tuple(data: &mut QuircData, ds: &mut DataStream, bits: i32, digits: i32) -> i32 {
+fn alpha_tuple(data: &mut QuircData, ds: &mut DataStream, bits: i32, digits: i32) -> Result<()> {
     if bits_remaining(ds) < bits {
-        -1
+        Err(underflow(ds))
     } else {
         let mut tuple = take_bits(ds, bits);
 
@@ -657,44 +528,34 @@ fn alpha_tuple(data: &mut QuircData, ds: &mut DataStream, bits: i32, digits: i32
         }
 
         data.payload_len += digits;
-        0
+        Ok(())
     }
 }
 
-fn decode_alpha(data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
-    let bits = match data.version {
-        v if v < 10 => 9,
-        v if v < 27 => 11,
-        _ => 13,
-    };
-
+fn decode_alpha(data: &mut QuircData, ds: &mut DataStream, bits: i32) -> Result<()> {
     let mut count = take_bits(ds, bits);
     if data.payload_len + count + 1 > MAX_PAYLOAD as i32 {
         return Err(DecodeError::DataOverflow);
     }
 
     while count >= 2 {
-        if alpha_tuple(data, ds, 11, 2) < 0 {
-            return Err(DecodeError::DataUnderflow);
-        }
+        alpha_tuple(data, ds, 11, 2)?;
         count -= 2;
     }
 
-    if count != 0 && alpha_tuple(data, ds, 6, 1) < 0 {
-        return Err(DecodeError::DataUnderflow);
+    if count != 0 {
+        alpha_tuple(data, ds, 6, 1)?;
     }
 
     Ok(())
 }
 
-fn decode_byte(mut data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
-    let bits = if data.version < 10 { 8 } else { 16 };
-
+fn decode_byte(mut data: &mut QuircData, ds: &mut DataStream, bits: i32) -> Result<()> {
     let count = take_bits(ds, bits);
     if data.payload_len + count + 1 > MAX_PAYLOAD as i32 {
         Err(DecodeError::DataOverflow)
     } else if bits_remaining(ds) < count * 8 {
-        Err(DecodeError::DataUnderflow)
+        Err(underflow(ds))
     } else {
         for _ in 0..count {
             data.payload[data.payload_len as usize] = take_bits(ds, 8) as u8;
@@ -705,18 +566,12 @@ fn decode_byte(mut data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
     }
 }
 
-fn decode_kanji(mut data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
-    let bits = match data.version {
-        v if v < 10 => 8,
-        v if v < 27 => 10,
-        _ => 12,
-    };
-
+fn decode_kanji(mut data: &mut QuircData, ds: &mut DataStream, bits: i32) -> Result<()> {
     let count = take_bits(ds, bits);
     if data.payload_len + count * 2 + 1 > MAX_PAYLOAD as i32 {
         Err(DecodeError::DataOverflow)
     } else if bits_remaining(ds) < count * 13 {
-        Err(DecodeError::DataUnderflow)
+        Err(underflow(ds))
     } else {
         for _ in 0..count {
             let d: i32 = take_bits(ds, 13);
@@ -744,19 +599,19 @@ fn decode_kanji(mut data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
 
 fn decode_eci(mut data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
     if bits_remaining(ds) < 8 {
-        Err(DecodeError::DataUnderflow)
+        Err(underflow(ds))
     } else {
         data.eci = take_bits(ds, 8) as u32;
 
         if data.eci & 0xc0 == 0x80 {
             if bits_remaining(ds) < 8 {
-                return Err(DecodeError::DataUnderflow);
+                return Err(underflow(ds));
             }
 
             data.eci = data.eci << 8 | take_bits(ds, 8) as u32;
         } else if data.eci & 0xe0 == 0xc0 {
             if bits_remaining(ds) < 16 {
-                return Err(DecodeError::DataUnderflow);
+                return Err(underflow(ds));
             }
 
             data.eci = data.eci << 16 | take_bits(ds, 16) as u32;
@@ -766,25 +621,108 @@ fn decode_eci(mut data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
     }
 }
 
-fn decode_payload(data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
-    while bits_remaining(ds) >= 4 {
-        let type_: i32 = take_bits(ds, 4);
+/// A single segment of the data stream.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Segment {
+    /// Mode indicator: one of the `DATA_TYPE_*` constants
+    pub mode: i32,
+
+    /// ECI assignment number in effect for this segment, or the one
+    /// designated by it for ECI segments. Zero if there is none.
+    pub eci: u32,
+
+    /// Offset of the mode indicator in the error corrected data stream
+    pub bit_offset: i32,
+
+    /// Number of characters given by the character count indicator
+    pub count: i32,
+
+    /// Decoded content, encoded as in `QuircData::payload`
+    pub data: Vec<u8>,
+}
+
+/// How segments are introduced in the data stream of a symbol.
+pub(crate) struct Modes {
+    /// Length of the mode indicator
+    pub(crate) indicator_bits: i32,
+
+    /// The `DATA_TYPE_*` of each mode indicator, 0 for the terminator
+    pub(crate) types: [i32; 16],
+
+    /// Length of the character count indicator of numeric,
+    /// alphanumeric, byte and kanji segments
+    pub(crate) count_bits: [i32; 4],
+}
+
+impl Modes {
+    /// The modes of a QR-code of the given version.
+    fn qr(version: i32) -> Self {
+        let mut types = [0; 16];
+        for &t in &[
+            DATA_TYPE_NUMERIC,
+            DATA_TYPE_ALPHA,
+            DATA_TYPE_BYTE,
+            DATA_TYPE_KANJI,
+            DATA_TYPE_ECI,
+        ] {
+            types[t as usize] = t;
+        }
+
+        Modes {
+            indicator_bits: 4,
+            types,
+            count_bits: match version {
+                v if v < 10 => [10, 9, 8, 8],
+                v if v < 27 => [12, 11, 16, 10],
+                _ => [14, 13, 16, 12],
+            },
+        }
+    }
+}
+
+pub(crate) fn decode_payload(
+    data: &mut QuircData,
+    ds: &mut DataStream,
+    modes: &Modes,
+    mut segments: Option<&mut Vec<Segment>>,
+) -> Result<()> {
+    while bits_remaining(ds) >= modes.indicator_bits {
+        let bit_offset = ds.ptr;
+        let start = data.payload_len;
+        let type_: i32 = modes.types[take_bits(ds, modes.indicator_bits) as usize];
+        let [numeric, alpha, byte, kanji] = modes.count_bits;
         match type_ {
-            DATA_TYPE_NUMERIC => decode_numeric(data, ds)?,
-            DATA_TYPE_ALPHA => decode_alpha(data, ds)?,
-            DATA_TYPE_BYTE => decode_byte(data, ds)?,
-            DATA_TYPE_KANJI => decode_kanji(data, ds)?,
-            7 => decode_eci(data, ds)?,
+            DATA_TYPE_NUMERIC => decode_numeric(data, ds, numeric)?,
+            DATA_TYPE_ALPHA => decode_alpha(data, ds, alpha)?,
+            DATA_TYPE_BYTE => decode_byte(data, ds, byte)?,
+            DATA_TYPE_KANJI => decode_kanji(data, ds, kanji)?,
+            DATA_TYPE_ECI => decode_eci(data, ds)?,
             _ => break,
         };
 
+        if let Some(segments) = segments.as_mut() {
+            let content = &data.payload[start as usize..data.payload_len as usize];
+            segments.push(Segment {
+                mode: type_,
+                eci: data.eci,
+                bit_offset,
+                count: if type_ == DATA_TYPE_KANJI {
+                    content.len() as i32 / 2
+                } else {
+                    content.len() as i32
+                },
+                data: content.to_vec(),
+            });
+        }
+
         if type_ & (type_ - 1) == 0 && (type_ > data.data_type) {
             data.data_type = type_;
         }
     }
 
     // Add nul terminator to all payloads
-    if data.payload_len as usize >= ::std::mem::size_of::<[u8; 8896]>() {
+    if data.payload_len as usize >= ::core::mem::size_of::<[u8; 8896]>() {
         data.payload_len -= 1;
     }
     data.payload[data.payload_len as usize] = 0;
@@ -794,6 +732,141 @@ fn decode_payload(data: &mut QuircData, ds: &mut DataStream) -> Result<()> {
 
 /// Decode a QR-code, returning the payload data.
 pub fn quirc_decode(code: &QuircCode) -> Result<QuircData> {
+    decode(code, None, None)
+}
+
+/// Decode a QR-code, returning the payload data along with the
+/// sequence of segments it was assembled from.
+pub fn quirc_decode_segments(code: &QuircCode) -> Result<(QuircData, Vec<Segment>)> {
+    let mut segments = Vec::new();
+    let data = decode(code, None, Some(&mut segments))?;
+    Ok((data, segments))
+}
+
+/// Decode a QR-code, returning the payload data along with its model,
+/// `MODEL_1` or `MODEL_2`.
+pub fn quirc_decode_model(code: &QuircCode) -> Result<(QuircData, i32)> {
+    let mut data = QuircData::default();
+    let mut ds = DataStream::default();
+
+    let model = decode_with(code, &mut data, &mut ds, None, None)?;
+    Ok((data, model))
+}
+
+/// Decode a QR-code, returning the payload data along with the error
+/// correction statistics of each RS block.
+pub fn quirc_decode_ecc(code: &QuircCode) -> Result<(QuircData, Vec<BlockEcc>)> {
+    let mut stats = Vec::new();
+    let data = decode(code, Some(&mut stats), None)?;
+    Ok((data, stats))
+}
+
+/// Decode a QR-code, returning the payload data, the error correction
+/// statistics of each RS block and the model.
+pub(crate) fn decode_ecc_model(code: &QuircCode) -> Result<(QuircData, Vec<BlockEcc>, i32)> {
+    let mut data = QuircData::default();
+    let mut ds = DataStream::default();
+    let mut stats = Vec::new();
+
+    let model = decode_with(code, &mut data, &mut ds, Some(&mut stats), None)?;
+    Ok((data, stats, model))
+}
+
+/// Positions of the rows and columns of the alignment patterns of a
+/// version, from 1 to `QUIRC_MAX_VERSION`. Version 1 has none.
+///
+/// This and the other version tables are only for encoding test codes,
+/// and not part of the stable API.
+#[cfg(feature = "synth")]
+pub fn alignment_positions(version: i32) -> Vec<i32> {
+    VERSION_DB[version as usize]
+        .apat
+        .iter()
+        .cloned()
+        .take_while(|&p| p != 0)
+        .collect()
+}
+
+/// Error correction blocks of a version and ECC level (one of the
+/// `ECC_LEVEL_*` constants), as (data words, total words) pairs.
+#[cfg(feature = "synth")]
+pub fn ecc_blocks(version: i32, ecc_level: i32) -> Vec<(usize, usize)> {
+    let ver = &VERSION_DB[version as usize];
+    ver.ecc[ecc_level as usize].blocks(ver.data_bytes)
+}
+
+/// Error correction blocks of a Model 1 code, like `ecc_blocks()`.
+#[cfg(feature = "synth")]
+pub fn model1_ecc_blocks(version: i32, ecc_level: i32) -> Vec<(usize, usize)> {
+    let ver = &MODEL1_VERSION_DB[version as usize];
+    ver.ecc[ecc_level as usize].blocks(ver.data_bytes)
+}
+
+/// Buffers for `quirc_extract_decode()`, and for trying whether a grid
+/// decodes while identifying codes, which would otherwise take up about
+/// 30 KiB of stack.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Scratch {
+    code: QuircCode,
+    stream: DataStream,
+    data: QuircData,
+}
+
+impl Scratch {
+    /// Whether the grid `qr` read from `image` decodes as a code of its
+    /// model.
+    pub(crate) fn decodes(&mut self, image: &Image, qr: &Grid) -> bool {
+        extract_grid(image, qr, &mut self.code);
+        decode_with(&self.code, &mut self.data, &mut self.stream, None, None) == Ok(qr.model)
+    }
+}
+
+/// Extract and decode the QR-code specified by the given index into
+/// `data`.
+///
+/// The code and its codewords are kept in buffers owned by `q`. With a
+/// `Quirc` made by `Quirc::from_workspace()` this doesn't allocate.
+pub fn quirc_extract_decode(q: &mut Quirc, index: i32, data: &mut QuircData) -> Result<()> {
+    let mut scratch = q.scratch.take().unwrap_or_default();
+
+    let result = if quirc_extract_into(q, index, &mut scratch.code) {
+        decode_with(&scratch.code, data, &mut scratch.stream, None, None).map(|_| ())
+    } else {
+        Err(DecodeError::InvalidGridSize)
+    };
+
+    q.scratch = Some(scratch);
+    result
+}
+
+fn decode(
+    code: &QuircCode,
+    stats: Option<&mut Vec<BlockEcc>>,
+    segments: Option<&mut Vec<Segment>>,
+) -> Result<QuircData> {
+    let mut data = QuircData::default();
+    let mut ds = DataStream::default();
+
+    decode_with(code, &mut data, &mut ds, stats, segments)?;
+    Ok(data)
+}
+
+/// Decode `code` into `data`, returning its model.
+fn decode_with(
+    code: &QuircCode,
+    data: &mut QuircData,
+    ds: &mut DataStream,
+    mut stats: Option<&mut Vec<BlockEcc>>,
+    segments: Option<&mut Vec<Segment>>,
+) -> Result<i32> {
+    data.version = 0;
+    data.ecc_level = 0;
+    data.mask = 0;
+    data.data_type = 0;
+    data.payload.iter_mut().for_each(|b| *b = 0);
+    data.payload_len = 0;
+    data.eci = 0;
+
     if (code.size - 17) % 4 != 0 {
         return Err(DecodeError::InvalidGridSize);
     }
@@ -804,18 +877,96 @@ pub fn quirc_decode(code: &QuircCode) -> Result<QuircData> {
         return Err(DecodeError::InvalidVersion);
     }
 
-    let mut data = QuircData {
-        version,
-        ..Default::default()
-    };
+    data.version = version;
 
     // Read format information -- try both locations
-    read_format(code, &mut data, 0).or_else(|_| read_format(code, &mut data, 1))?;
-
-    let mut ds = read_data(code, &data);
-    codestream_ecc(&mut data, &mut ds)?;
+    read_format(code, data, 0).or_else(|_| read_format(code, data, 1))?;
 
-    decode_payload(&mut data, &mut ds)?;
+    read_data(code, data, ds);
+    let ver: &VersionInfo = &VERSION_DB[data.version as usize];
+    let stats_len = stats.as_ref().map_or(0, |stats| stats.len());
+    let mut model = MODEL_2;
+    if let Err(err) = codestream_ecc(
+        ver.data_bytes,
+        &ver.ecc[data.ecc_level as usize],
+        ds,
+        stats.as_deref_mut(),
+    ) {
+        // The format information of both models is the same, so a code
+        // which doesn't correct as Model 2 might be Model 1 instead. If it
+        // isn't either, it's reported as Model 2.
+        if data.version > MODEL1_MAX_VERSION as i32 {
+            return Err(err);
+        }
+        if let Some(stats) = stats.as_mut() {
+            stats.truncate(stats_len);
+        }
+
+        read_data_model1(code, data, ds);
+        let ver: &VersionInfo = &MODEL1_VERSION_DB[data.version as usize];
+        codestream_ecc(ver.data_bytes, &ver.ecc[data.ecc_level as usize], ds, stats)
+            .map_err(|_| err)?;
+        model = MODEL_1;
+    }
+
+    decode_payload(data, ds, &Modes::qr(data.version), segments)?;
+    Ok(model)
+}
 
-    Ok(data)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The last data word of each long block comes after those of all
+    /// the short blocks, so reading it from anywhere else costs a
+    /// correction.
+    #[test]
+    fn test_long_blocks() {
+        // Version 5-Q has two blocks of 15 data words, then two of 16
+        let ver = &VERSION_DB[5];
+        let sb_ecc = &ver.ecc[ECC_LEVEL_Q as usize];
+        let rs = ReedSolomon::new(&GF256, 0, sb_ecc.bs - sb_ecc.dw).unwrap();
+        let mut data = Vec::new();
+        let blocks: Vec<Vec<u8>> = (0..4)
+            .map(|i| {
+                let dw = if i < sb_ecc.ns {
+                    sb_ecc.dw
+                } else {
+                    sb_ecc.dw + 1
+                };
+                let mut block: Vec<u8> = (0..dw).map(|j| (i * 40 + j) as u8).collect();
+                data.extend_from_slice(&block);
+                block.resize((dw + rs.parity()) as usize, 0);
+                rs.encode(&mut block).unwrap();
+                block
+            })
+            .collect();
+
+        // A word of each block in turn, the data words first
+        let mut ds = DataStream::default();
+        let mut raw = Vec::new();
+        for j in 0..=sb_ecc.dw as usize {
+            raw.extend(
+                blocks
+                    .iter()
+                    .filter(|b| j < b.len() - rs.parity() as usize)
+                    .map(|b| b[j]),
+            );
+        }
+        for j in 0..rs.parity() as usize {
+            raw.extend(blocks.iter().map(|b| b[b.len() - rs.parity() as usize + j]));
+        }
+        assert_eq!(raw.len(), ver.data_bytes as usize);
+        ds.raw[..raw.len()].copy_from_slice(&raw);
+
+        let mut stats = Vec::new();
+        codestream_ecc(ver.data_bytes, sb_ecc, &mut ds, Some(&mut stats)).unwrap();
+        assert!(
+            stats.iter().all(|block| block.corrected == 0),
+            "{:?}",
+            stats
+        );
+        assert_eq!(ds.data_bits, data.len() as i32 * 8);
+        assert_eq!(&ds.data[..data.len()], &data[..]);
+    }
 }
@@ -0,0 +1,544 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! EMVCo merchant-presented mode (MPM) payment payloads.
+//!
+//! An MPM payload, as used by PIX, UPI and many other payment schemes, is
+//! a sequence of data objects, each a two digit ID, a two digit length and
+//! a value of that many characters. Merchant account information, the
+//! additional data field and the language template are themselves
+//! templates of data objects. The payload starts with the payload format
+//! indicator and ends with a CRC of everything before the CRC value.
+//!
+//! ```
+//! use quirc_rs::emv::{id, parse_mpm};
+//!
+//! let payload = b"00020126580014br.gov.bcb.pix0136123e4567-e12b-12d1-a456-42665544\
+//!     00005204000053039865802BR5913Fulano de Tal6008BRASILIA62070503***63041D3D";
+//! let mpm = parse_mpm(payload).unwrap();
+//! assert_eq!(mpm.value(id::MERCHANT_NAME), Some("Fulano de Tal"));
+//!
+//! let account = mpm.merchant_accounts().next().unwrap();
+//! assert_eq!(account.value(0), Some("br.gov.bcb.pix"));
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::quirc::{Data, QuircData};
+
+/// IDs of the data objects of a payload and of its templates
+pub mod id {
+    /* IDs of the payload's data objects */
+    pub const PAYLOAD_FORMAT_INDICATOR: u8 = 0;
+    pub const POINT_OF_INITIATION_METHOD: u8 = 1;
+    pub const MERCHANT_CATEGORY_CODE: u8 = 52;
+    pub const TRANSACTION_CURRENCY: u8 = 53;
+    pub const TRANSACTION_AMOUNT: u8 = 54;
+    pub const TIP_OR_CONVENIENCE_INDICATOR: u8 = 55;
+    pub const CONVENIENCE_FEE_FIXED: u8 = 56;
+    pub const CONVENIENCE_FEE_PERCENTAGE: u8 = 57;
+    pub const COUNTRY_CODE: u8 = 58;
+    pub const MERCHANT_NAME: u8 = 59;
+    pub const MERCHANT_CITY: u8 = 60;
+    pub const POSTAL_CODE: u8 = 61;
+    pub const ADDITIONAL_DATA: u8 = 62;
+    pub const CRC: u8 = 63;
+    pub const LANGUAGE_TEMPLATE: u8 = 64;
+
+    /* IDs of the data objects of the additional data field template */
+    pub const BILL_NUMBER: u8 = 1;
+    pub const MOBILE_NUMBER: u8 = 2;
+    pub const STORE_LABEL: u8 = 3;
+    pub const LOYALTY_NUMBER: u8 = 4;
+    pub const REFERENCE_LABEL: u8 = 5;
+    pub const CUSTOMER_LABEL: u8 = 6;
+    pub const TERMINAL_LABEL: u8 = 7;
+    pub const PURPOSE_OF_TRANSACTION: u8 = 8;
+    pub const CONSUMER_DATA_REQUEST: u8 = 9;
+
+    /// ID of the globally unique identifier of a merchant account
+    /// information or other template
+    pub const GLOBALLY_UNIQUE_IDENTIFIER: u8 = 0;
+}
+
+/// This enum describes the ways an MPM payload can be malformed. Offsets
+/// are in bytes from the start of the payload.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EmvError {
+    /// The payload isn't valid UTF-8 from `offset` on.
+    Encoding { offset: usize },
+    /// The data object at `offset` doesn't start with a two digit ID.
+    Id { offset: usize },
+    /// The data object at `offset` doesn't have a two digit length of at
+    /// least 1.
+    Length { offset: usize },
+    /// The value of the data object at `offset` runs past the end of the
+    /// payload or of its template.
+    Truncated { offset: usize, id: u8 },
+    /// The data object at `offset` appears twice in the same template.
+    Duplicate { offset: usize, id: u8 },
+    /// A mandatory data object is missing from the template at `offset`,
+    /// or from the payload if `offset` is 0.
+    Missing { offset: usize, id: u8 },
+    /// The data object at `offset` is out of place: the payload format
+    /// indicator must come first and the CRC last.
+    Misplaced { offset: usize, id: u8 },
+    /// The value of the data object at `offset` isn't valid for its ID.
+    InvalidValue { offset: usize, id: u8 },
+    /// The payload has no merchant account information.
+    NoMerchantAccount,
+    /// The CRC at `offset` doesn't match the payload.
+    Crc {
+        offset: usize,
+        expected: u16,
+        found: u16,
+    },
+}
+
+impl fmt::Display for EmvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EmvError::Encoding { offset } => write!(f, "Invalid UTF-8 at {}", offset),
+            EmvError::Id { offset } => write!(f, "Invalid ID at {}", offset),
+            EmvError::Length { offset } => write!(f, "Invalid length at {}", offset),
+            EmvError::Truncated { offset, id } => {
+                write!(f, "Truncated value of {:02} at {}", id, offset)
+            }
+            EmvError::Duplicate { offset, id } => write!(f, "Duplicate {:02} at {}", id, offset),
+            EmvError::Missing { offset, id } => {
+                write!(f, "Missing {:02} in template at {}", id, offset)
+            }
+            EmvError::Misplaced { offset, id } => write!(f, "Misplaced {:02} at {}", id, offset),
+            EmvError::InvalidValue { offset, id } => {
+                write!(f, "Invalid value of {:02} at {}", id, offset)
+            }
+            EmvError::NoMerchantAccount => f.write_str("No merchant account information"),
+            EmvError::Crc {
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "CRC mismatch at {}: expected {:04X}, found {:04X}",
+                offset, expected, found
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EmvError {}
+
+/// A data object of an MPM payload.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DataObject {
+    pub id: u8,
+
+    /// Offset of the ID in the payload, in bytes
+    pub offset: usize,
+
+    /// The value, including the data objects of a template
+    pub value: String,
+
+    /// The data objects of a template, or nothing for other data objects
+    pub template: Vec<DataObject>,
+}
+
+impl DataObject {
+    /// The data object of this template with the given ID.
+    pub fn get(&self, id: u8) -> Option<&DataObject> {
+        self.template.iter().find(|object| object.id == id)
+    }
+
+    /// The value of the data object of this template with the given ID.
+    pub fn value(&self, id: u8) -> Option<&str> {
+        self.get(id).map(|object| object.value.as_str())
+    }
+}
+
+/// A parsed MPM payload.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mpm {
+    /// The top level data objects, in payload order
+    pub objects: Vec<DataObject>,
+}
+
+impl Mpm {
+    /// The top level data object with the given ID.
+    pub fn get(&self, id: u8) -> Option<&DataObject> {
+        self.objects.iter().find(|object| object.id == id)
+    }
+
+    /// The value of the top level data object with the given ID.
+    pub fn value(&self, id: u8) -> Option<&str> {
+        self.get(id).map(|object| object.value.as_str())
+    }
+
+    /// The merchant account information, IDs 02 to 51. Those from 26 on are
+    /// templates, starting with a globally unique identifier.
+    pub fn merchant_accounts(&self) -> impl Iterator<Item = &DataObject> {
+        self.objects
+            .iter()
+            .filter(|object| is_merchant_account(object.id))
+    }
+
+    /// The additional data field template.
+    pub fn additional_data(&self) -> Option<&DataObject> {
+        self.get(id::ADDITIONAL_DATA)
+    }
+}
+
+fn is_merchant_account(id: u8) -> bool {
+    (2..=51).contains(&id)
+}
+
+/// Whether the data object `id` inside the template `parent`, or at the
+/// top level if `parent` is `None`, is itself a template.
+fn is_template(parent: Option<u8>, id: u8) -> bool {
+    match parent {
+        None => matches!(id, 26..=51 | id::ADDITIONAL_DATA | id::LANGUAGE_TEMPLATE | 80..=99),
+        Some(id::ADDITIONAL_DATA) => (50..=99).contains(&id),
+        Some(_) => false,
+    }
+}
+
+/// CRC-16/CCITT-FALSE, as used by the CRC data object.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+
+    for &b in data {
+        crc ^= u16::from(b) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// Two decimal digits at `pos` of `s`.
+fn two_digits(s: &str, pos: usize) -> Option<u8> {
+    match s.as_bytes().get(pos..pos + 2) {
+        Some(&[a, b]) if a.is_ascii_digit() && b.is_ascii_digit() => {
+            Some((a - b'0') * 10 + b - b'0')
+        }
+        _ => None,
+    }
+}
+
+/// Parse the data objects of `s`, which starts `base` bytes into the
+/// payload, inside the template `parent`.
+fn parse_objects(s: &str, base: usize, parent: Option<u8>) -> Result<Vec<DataObject>, EmvError> {
+    let mut objects: Vec<DataObject> = Vec::new();
+    let mut pos = 0;
+
+    while pos < s.len() {
+        let offset = base + pos;
+        let id = two_digits(s, pos).ok_or(EmvError::Id { offset })?;
+        let len = match two_digits(s, pos + 2) {
+            Some(len) if len > 0 => len as usize,
+            _ => return Err(EmvError::Length { offset }),
+        };
+
+        // The length counts characters, not bytes
+        let start = pos + 4;
+        let end = match s[start..].char_indices().nth(len) {
+            Some((i, _)) => start + i,
+            None if s[start..].chars().count() == len => s.len(),
+            None => return Err(EmvError::Truncated { offset, id }),
+        };
+
+        if objects.iter().any(|object| object.id == id) {
+            return Err(EmvError::Duplicate { offset, id });
+        }
+
+        let value = &s[start..end];
+        let template = if is_template(parent, id) {
+            parse_objects(value, base + start, Some(id))?
+        } else {
+            Vec::new()
+        };
+        objects.push(DataObject {
+            id,
+            offset,
+            value: String::from(value),
+            template,
+        });
+
+        pos = end;
+    }
+
+    Ok(objects)
+}
+
+/// Check that the values of the top level data objects are well formed,
+/// and that the mandatory ones are there.
+fn check_objects(objects: &[DataObject]) -> Result<(), EmvError> {
+    let digits = |s: &str, n: usize| s.len() == n && s.bytes().all(|b| b.is_ascii_digit());
+
+    for object in objects {
+        let v = object.value.as_str();
+        let valid = match object.id {
+            id::PAYLOAD_FORMAT_INDICATOR => v == "01",
+            id::POINT_OF_INITIATION_METHOD => v == "11" || v == "12",
+            id::MERCHANT_CATEGORY_CODE => digits(v, 4),
+            id::TRANSACTION_CURRENCY => digits(v, 3),
+            id::TRANSACTION_AMOUNT => {
+                v.len() <= 13
+                    && v.bytes().any(|b| b.is_ascii_digit())
+                    && v.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+                    && v.bytes().filter(|&b| b == b'.').count() <= 1
+            }
+            id::TIP_OR_CONVENIENCE_INDICATOR => matches!(v, "01" | "02" | "03"),
+            id::COUNTRY_CODE => v.len() == 2,
+            id::MERCHANT_NAME => v.chars().count() <= 25,
+            id::MERCHANT_CITY => v.chars().count() <= 15,
+            26..=51 => {
+                if object.get(id::GLOBALLY_UNIQUE_IDENTIFIER).is_none() {
+                    return Err(EmvError::Missing {
+                        offset: object.offset,
+                        id: id::GLOBALLY_UNIQUE_IDENTIFIER,
+                    });
+                }
+                true
+            }
+            _ => true,
+        };
+
+        if !valid {
+            return Err(EmvError::InvalidValue {
+                offset: object.offset,
+                id: object.id,
+            });
+        }
+    }
+
+    let mandatory = [
+        id::MERCHANT_CATEGORY_CODE,
+        id::TRANSACTION_CURRENCY,
+        id::COUNTRY_CODE,
+        id::MERCHANT_NAME,
+        id::MERCHANT_CITY,
+    ];
+    for &id in &mandatory {
+        if !objects.iter().any(|object| object.id == id) {
+            return Err(EmvError::Missing { offset: 0, id });
+        }
+    }
+    if !objects.iter().any(|object| is_merchant_account(object.id)) {
+        return Err(EmvError::NoMerchantAccount);
+    }
+
+    Ok(())
+}
+
+/// Parse an MPM payload, checking its CRC and the format of its mandatory
+/// data objects.
+pub fn parse_mpm(payload: &[u8]) -> Result<Mpm, EmvError> {
+    let s = core::str::from_utf8(payload).map_err(|e| EmvError::Encoding {
+        offset: e.valid_up_to(),
+    })?;
+    let objects = parse_objects(s, 0, None)?;
+
+    match objects.first() {
+        Some(first) if first.id == id::PAYLOAD_FORMAT_INDICATOR => (),
+        _ => {
+            return Err(EmvError::Missing {
+                offset: 0,
+                id: id::PAYLOAD_FORMAT_INDICATOR,
+            })
+        }
+    }
+    for object in &objects[1..] {
+        if object.id == id::PAYLOAD_FORMAT_INDICATOR {
+            return Err(EmvError::Misplaced {
+                offset: object.offset,
+                id: object.id,
+            });
+        }
+    }
+
+    // The CRC covers everything up to its own value, including its ID and
+    // length
+    let crc = objects
+        .iter()
+        .find(|object| object.id == id::CRC)
+        .ok_or(EmvError::Missing {
+            offset: 0,
+            id: id::CRC,
+        })?;
+    if crc.offset + 8 != payload.len() {
+        return Err(EmvError::Misplaced {
+            offset: crc.offset,
+            id: id::CRC,
+        });
+    }
+    let found = match crc.value.len() {
+        4 if crc.value.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            u16::from_str_radix(&crc.value, 16).unwrap_or(0)
+        }
+        _ => {
+            return Err(EmvError::InvalidValue {
+                offset: crc.offset,
+                id: id::CRC,
+            })
+        }
+    };
+    let expected = crc16(&payload[..crc.offset + 4]);
+    if found != expected {
+        return Err(EmvError::Crc {
+            offset: crc.offset,
+            expected,
+            found,
+        });
+    }
+
+    check_objects(&objects)?;
+    Ok(Mpm { objects })
+}
+
+impl QuircData {
+    /// Parse the payload as an EMVCo merchant-presented mode payload.
+    pub fn payload_mpm(&self) -> Result<Mpm, EmvError> {
+        parse_mpm(&self.payload[..self.payload_len as usize])
+    }
+}
+
+impl Data {
+    /// Parse the payload as an EMVCo merchant-presented mode payload.
+    pub fn payload_mpm(&self) -> Result<Mpm, EmvError> {
+        parse_mpm(&self.payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloc::format;
+
+    /// Append a CRC to `payload`, which ends with the CRC's ID and length.
+    fn with_crc(payload: &str) -> String {
+        format!("{}{:04X}", payload, crc16(payload.as_bytes()))
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+    }
+
+    #[test]
+    fn test_pix() {
+        let payload = "00020126580014br.gov.bcb.pix0136123e4567-e12b-12d1-a456-4266554400005204\
+                       000053039865802BR5913Fulano de Tal6008BRASILIA62070503***63041D3D";
+        let mpm = parse_mpm(payload.as_bytes()).unwrap();
+
+        assert_eq!(mpm.value(id::TRANSACTION_CURRENCY), Some("986"));
+        assert_eq!(mpm.value(id::MERCHANT_CITY), Some("BRASILIA"));
+        let accounts: Vec<_> = mpm.merchant_accounts().collect();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].id, 26);
+        assert_eq!(accounts[0].offset, 6);
+        assert_eq!(
+            accounts[0].value(1),
+            Some("123e4567-e12b-12d1-a456-426655440000")
+        );
+        assert_eq!(accounts[0].get(1).map(|object| object.offset), Some(28));
+        assert_eq!(
+            mpm.additional_data()
+                .and_then(|data| data.value(id::REFERENCE_LABEL)),
+            Some("***")
+        );
+    }
+
+    #[test]
+    fn test_language_template() {
+        // Lengths count characters, not bytes
+        let payload = with_crc(
+            "000201010212021642424242424242425204581253039785802FR5904Caf\u{e9}\
+             6005Paris540510.0064140002FR0104Caf\u{e9}6304",
+        );
+        let mpm = parse_mpm(payload.as_bytes()).unwrap();
+
+        assert_eq!(mpm.value(id::MERCHANT_NAME), Some("Café"));
+        assert_eq!(mpm.value(id::TRANSACTION_AMOUNT), Some("10.00"));
+        let language = mpm.get(id::LANGUAGE_TEMPLATE).unwrap();
+        assert_eq!(language.value(0), Some("FR"));
+        assert_eq!(language.value(1), Some("Café"));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let parse = |payload: &str| parse_mpm(payload.as_bytes());
+        let valid = "000201021642424242424242425204581253039785802FR5905Alice6005Paris";
+
+        assert!(parse(&with_crc(&format!("{}6304", valid))).is_ok());
+        assert_eq!(
+            parse(&format!("{}63040000", valid)),
+            Err(EmvError::Crc {
+                offset: 65,
+                expected: crc16(format!("{}6304", valid).as_bytes()),
+                found: 0
+            })
+        );
+        assert_eq!(
+            parse(&with_crc(
+                "000201021642424242424242425204581253039785802FR59x5Alice6304"
+            )),
+            Err(EmvError::Length { offset: 47 })
+        );
+        assert_eq!(
+            parse("0002010216424242"),
+            Err(EmvError::Truncated { offset: 6, id: 2 })
+        );
+        assert_eq!(
+            parse(&with_crc(&format!("{}62080X0312346304", valid))),
+            Err(EmvError::Id { offset: 69 })
+        );
+        assert_eq!(
+            parse(&with_crc(&format!("{}6002Ly6304", valid))),
+            Err(EmvError::Duplicate { offset: 65, id: 60 })
+        );
+        assert_eq!(
+            parse(&with_crc(&format!("{}26080104abcd6304", valid))),
+            Err(EmvError::Missing { offset: 65, id: 0 })
+        );
+        assert_eq!(
+            parse(&with_crc(&format!("{}5303EUR6304", &valid[..34]))),
+            Err(EmvError::InvalidValue { offset: 34, id: 53 })
+        );
+        assert_eq!(
+            parse(&with_crc(
+                "0002015204581253039785802FR5905Alice6005Paris6304"
+            )),
+            Err(EmvError::NoMerchantAccount)
+        );
+        assert_eq!(
+            parse(&format!("{}630412346102AB", valid)),
+            Err(EmvError::Misplaced { offset: 65, id: 63 })
+        );
+        assert_eq!(
+            parse_mpm(b"000201\xff"),
+            Err(EmvError::Encoding { offset: 6 })
+        );
+    }
+}
@@ -0,0 +1,343 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Decoding codes damaged differently in consecutive frames.
+//!
+//! Glare or motion blur often spoils a different part of a code in each
+//! frame of a video, so that none of them decodes on its own. A `Fusion`
+//! remembers the cells of each code over the last few frames, telling
+//! the sightings of the same code apart from others by their size and
+//! corners. When a frame doesn't decode, the cells of all the remembered
+//! frames are summed and the result is decoded instead.
+//!
+//! ```
+//! use quirc_rs::fusion::{quirc_fuse, Fusion};
+//! use quirc_rs::identify::quirc_identify;
+//! use quirc_rs::quirc::{Image, Quirc};
+//!
+//! let mut fusion = Fusion::new();
+//! for _frame in 0..3 {
+//!     let mut pixels = vec![0xff; 64 * 48];
+//!     let mut q = Quirc::new(Image::new(64, 48, &mut pixels));
+//!     quirc_identify(&mut q);
+//!     for data in quirc_fuse(&q, &mut fusion) {
+//!         println!("{:?}", data.map(|data| data.payload_len));
+//!     }
+//! }
+//! ```
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::decode::quirc_decode;
+use crate::identify::*;
+use crate::quirc::*;
+
+/// The confidence of a cell sampled from the image ranges from
+/// `-SAMPLES` (all white) to `SAMPLES` (all black).
+const SAMPLES: i8 = 9;
+
+/// Sightings of a code in the last few frames.
+struct Symbol {
+    corners: [Point; 4],
+    size: i32,
+
+    /// Confidence of each cell in each frame, the latest last
+    frames: VecDeque<Vec<i8>>,
+
+    /// Number of the frame the code was last seen in
+    last_seen: u32,
+}
+
+impl Symbol {
+    /// Whether a code of `size` at `corners` is this one, moved by at most
+    /// `max_shift` cells.
+    fn matches(&self, corners: &[Point; 4], size: i32, max_shift: i32) -> bool {
+        if size != self.size {
+            return false;
+        }
+
+        let dist2 = |a: &Point, b: &Point| {
+            let (dx, dy) = (i64::from(a.x - b.x), i64::from(a.y - b.y));
+            dx * dx + dy * dy
+        };
+
+        // Compare squared distances to save a square root: a shift of
+        // `max_shift` cells against the width of a cell
+        let side2 = dist2(&corners[0], &corners[1]);
+        let limit = side2 * i64::from(max_shift * max_shift);
+        let size2 = i64::from(size * size);
+
+        corners
+            .iter()
+            .zip(self.corners.iter())
+            .all(|(a, b)| dist2(a, b) * size2 <= limit)
+    }
+
+    /// Cells of the code, each black if the sum of its confidences over
+    /// the remembered frames is positive. Ties are broken by the latest
+    /// frame.
+    fn fused(&self) -> QuircCode {
+        let mut code = QuircCode {
+            corners: self.corners,
+            size: self.size,
+            ..Default::default()
+        };
+
+        let latest = &self.frames[self.frames.len() - 1];
+        for (i, &last) in latest.iter().enumerate() {
+            let sum: i32 = self.frames.iter().map(|f| i32::from(f[i])).sum();
+            if sum > 0 || (sum == 0 && last > 0) {
+                code.cell_bitmap[i >> 3] |= 1 << (i & 7);
+            }
+        }
+
+        code
+    }
+}
+
+/// State kept by `quirc_fuse()` between frames.
+pub struct Fusion {
+    /// Number of frames to remember the cells of each code for. Codes
+    /// not seen in this many frames are forgotten.
+    pub max_frames: u32,
+
+    /// The corners of a code may move this many cells from one frame to
+    /// the next and still be taken for the same code.
+    pub max_shift: i32,
+
+    symbols: Vec<Symbol>,
+    frame: u32,
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Fusion {
+            max_frames: 8,
+            max_shift: 2,
+            symbols: Vec::new(),
+            frame: 0,
+        }
+    }
+}
+
+impl Fusion {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget all codes.
+    pub fn clear(&mut self) {
+        self.symbols.clear();
+    }
+
+    /// Number of codes being remembered.
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    fn begin_frame(&mut self) {
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// Forget the codes which weren't seen for `max_frames` frames.
+    fn end_frame(&mut self) {
+        let (frame, max_frames) = (self.frame, self.max_frames);
+        self.symbols
+            .retain(|s| frame.wrapping_sub(s.last_seen) < max_frames);
+    }
+
+    /// Remember the cells of a code seen in this frame, returning the
+    /// index of its symbol.
+    fn add(&mut self, corners: &[Point; 4], size: i32, cells: Vec<i8>) -> usize {
+        let (frame, max_shift) = (self.frame, self.max_shift);
+        let found = self
+            .symbols
+            .iter()
+            .position(|s| s.last_seen != frame && s.matches(corners, size, max_shift));
+
+        let index = match found {
+            Some(index) => index,
+            None => {
+                self.symbols.push(Symbol {
+                    corners: *corners,
+                    size,
+                    frames: VecDeque::new(),
+                    last_seen: frame,
+                });
+                self.symbols.len() - 1
+            }
+        };
+
+        let symbol = &mut self.symbols[index];
+        symbol.corners = *corners;
+        symbol.last_seen = frame;
+        symbol.frames.push_back(cells);
+        while symbol.frames.len() > self.max_frames.max(1) as usize {
+            symbol.frames.pop_front();
+        }
+
+        index
+    }
+
+    /// Decode `code`, as seen in this frame, falling back to the fused
+    /// cells of its symbol.
+    fn decode(&self, code: &QuircCode, index: usize) -> Result<QuircData> {
+        let symbol = &self.symbols[index];
+
+        match quirc_decode(code) {
+            Err(_) if symbol.frames.len() > 1 => quirc_decode(&symbol.fused()),
+            result => result,
+        }
+    }
+}
+
+/// Sample a cell at nine points, counting black ones as 1 and white ones
+/// as -1.
+fn cell_confidence(image: &Image, grid: &Grid, x: i32, y: i32) -> i8 {
+    const OFFSETS: [f64; 3] = [0.3, 0.5, 0.7];
+    let mut score = 0;
+
+    for v in &OFFSETS {
+        for u in &OFFSETS {
+            let p = perspective_map(&grid.c, f64::from(x) + u, f64::from(y) + v);
+
+            if !(p.y < 0 || p.y >= image.h || p.x < 0 || p.x >= image.w) {
+                if image[(p.y * image.w + p.x) as usize] != 0 {
+                    score += 1;
+                } else {
+                    score -= 1;
+                }
+            }
+        }
+    }
+    score
+}
+
+/// Decode the codes found by `quirc_identify()` (or `quirc_track()`) in a
+/// frame of a video, using the frames seen before by `fusion`.
+///
+/// Each code is decoded as `quirc_decode()` would. If that fails, the
+/// code is decoded again with the cells of its previous sightings added
+/// in, each cell weighted by how many of nine samples taken across it
+/// agree. Returns the result for each code, in the order of
+/// `Quirc::grids`.
+pub fn quirc_fuse(q: &Quirc, fusion: &mut Fusion) -> Vec<Result<QuircData>> {
+    let mut results = Vec::with_capacity(q.grids.len());
+    let mut code = QuircCode::default();
+    fusion.begin_frame();
+
+    for (i, grid) in q.grids.iter().enumerate() {
+        quirc_extract_into(q, i as i32, &mut code);
+
+        let mut cells = Vec::with_capacity((grid.grid_size * grid.grid_size) as usize);
+        for y in 0..grid.grid_size {
+            for x in 0..grid.grid_size {
+                cells.push(cell_confidence(&q.image, grid, x, y));
+            }
+        }
+
+        let index = fusion.add(&code.corners, code.size, cells);
+        results.push(fusion.decode(&code, index));
+    }
+
+    fusion.end_frame();
+    results
+}
+
+/// Like `quirc_fuse()`, but for codes already extracted from a frame.
+/// Each cell of the previous sightings is one vote for black or white.
+pub fn quirc_fuse_codes(codes: &[QuircCode], fusion: &mut Fusion) -> Vec<Result<QuircData>> {
+    let mut results = Vec::with_capacity(codes.len());
+    fusion.begin_frame();
+
+    for code in codes {
+        let cells = (0..(code.size * code.size) as usize)
+            .map(|i| {
+                if code.cell_bitmap[i >> 3] & (1 << (i & 7)) != 0 {
+                    SAMPLES
+                } else {
+                    -SAMPLES
+                }
+            })
+            .collect();
+
+        let index = fusion.add(&code.corners, code.size, cells);
+        results.push(fusion.decode(code, index));
+    }
+
+    fusion.end_frame();
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The corners of a square code, `side` pixels wide, at (x, y).
+    fn square(x: i32, y: i32, side: i32) -> [Point; 4] {
+        [
+            Point { x, y },
+            Point { x: x + side, y },
+            Point {
+                x: x + side,
+                y: y + side,
+            },
+            Point { x, y: y + side },
+        ]
+    }
+
+    fn symbol(frames: &[&[i8]]) -> Symbol {
+        Symbol {
+            corners: square(10, 10, 100),
+            size: 25,
+            frames: frames.iter().map(|f| f.to_vec()).collect(),
+            last_seen: 0,
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let symbol = symbol(&[]);
+
+        // Cells are 4 pixels wide, so 2 cells are 8 pixels
+        assert!(symbol.matches(&square(10, 10, 100), 25, 2));
+        assert!(symbol.matches(&square(18, 10, 100), 25, 2));
+        assert!(symbol.matches(&square(10, 2, 100), 25, 2));
+        assert!(!symbol.matches(&square(19, 10, 100), 25, 2));
+        assert!(!symbol.matches(&square(14, 14, 100), 25, 1));
+        assert!(!symbol.matches(&square(10, 10, 100), 21, 2));
+
+        // One corner too far off is enough
+        let mut corners = square(10, 10, 100);
+        corners[2].y += 9;
+        assert!(!symbol.matches(&corners, 25, 2));
+    }
+
+    #[test]
+    fn test_fused() {
+        let symbol = symbol(&[&[9, -9, 3, -3], &[-5, -1, -3, 3]]);
+        let code = symbol.fused();
+        assert_eq!(code.size, 25);
+
+        // The sums are 4, -10, 0 and 0; the latest frame breaks ties
+        assert_eq!(code.cell_bitmap[0], 0b1001);
+    }
+}
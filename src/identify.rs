@@ -14,12 +14,17 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
+use crate::decode::{self, model1_blocks, Scratch};
+use crate::observer::*;
 use crate::quirc::consts::*;
 use crate::quirc::*;
+use crate::rmqr::{format_info, RmqrVersion, RMQR_VERSIONS};
 use crate::version_db::*;
 
-use line_drawing::Bresenham;
-use std::cmp::max;
+use crate::math::{Bresenham, Sign};
+
+use alloc::vec::Vec;
+use core::cmp::{max, min, Ordering};
 
 /************************************************************************
  * Linear algebra routines
@@ -58,14 +63,15 @@ fn line_intersect(p0: Point, p1: Point, q0: Point, q1: Point, r: &mut Point) ->
 }
 
 fn perspective_setup(rect: &[Point; 4], w: f64, h: f64) -> [f64; 8] {
-    let x0: f64 = f64::from(rect[0].x);
-    let y0: f64 = f64::from(rect[0].y);
-    let x1: f64 = f64::from(rect[1].x);
-    let y1: f64 = f64::from(rect[1].y);
-    let x2: f64 = f64::from(rect[2].x);
-    let y2: f64 = f64::from(rect[2].y);
-    let x3: f64 = f64::from(rect[3].x);
-    let y3: f64 = f64::from(rect[3].y);
+    perspective_setup_exact(&rect.map(|p| (f64::from(p.x), f64::from(p.y))), w, h)
+}
+
+/// Like `perspective_setup()`, with corners between pixels.
+fn perspective_setup_exact(rect: &[(f64, f64); 4], w: f64, h: f64) -> [f64; 8] {
+    let (x0, y0) = rect[0];
+    let (x1, y1) = rect[1];
+    let (x2, y2) = rect[2];
+    let (x3, y3) = rect[3];
     let wden: f64 = w * (x2 * y3 - x3 * y2 + (x3 - x2) * y1 + x1 * (y2 - y3));
     let hden: f64 = h * (x2 * y3 + x1 * (y2 - y3) - x3 * y2 + (x3 - x2) * y1);
     [
@@ -91,12 +97,22 @@ fn perspective_setup(rect: &[Point; 4], w: f64, h: f64) -> [f64; 8] {
     ]
 }
 
-#[allow(clippy::many_single_char_names)]
-fn perspective_map(c: &[f64; consts::PERSPECTIVE_PARAMS], u: f64, v: f64) -> Point {
+/// Map grid coordinates to image coordinates without rounding.
+pub(crate) fn perspective_map_exact(
+    c: &[f64; consts::PERSPECTIVE_PARAMS],
+    u: f64,
+    v: f64,
+) -> (f64, f64) {
     let den: f64 = c[6] * u + c[7] * v + 1.0f64;
     let x: f64 = (c[0] * u + c[1] * v + c[2]) / den;
     let y: f64 = (c[3] * u + c[4] * v + c[5]) / den;
 
+    (x, y)
+}
+
+pub(crate) fn perspective_map(c: &[f64; consts::PERSPECTIVE_PARAMS], u: f64, v: f64) -> Point {
+    let (x, y) = perspective_map_exact(c, u, v);
+
     use crate::math::RoundToNearestFavorEven as _;
     Point {
         x: x.round_to_nearest_favor_even() as i32,
@@ -118,26 +134,48 @@ fn perspective_unmap(c: &[f64; consts::PERSPECTIVE_PARAMS], in_: Point) -> (f64,
     (u, v)
 }
 
-const FLOOD_FILL_MAX_DEPTH: i32 = 4096;
+pub(crate) const FLOOD_FILL_MAX_DEPTH: usize = 4096;
 
-/// Span-based floodfill routine
-fn flood_fill_seed<F>(
+/// One level of the explicit flood-fill stack, standing in for a
+/// recursive call.
+#[derive(Copy, Default)]
+#[repr(C)]
+pub(crate) struct FloodFillVars {
+    y: i32,
+    right: i32,
+    left_up: i32,
+    left_down: i32,
+}
+
+impl Clone for FloodFillVars {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+/// Number of flood-fill stack levels needed for an image. Each level
+/// holds a distinct span of at least one pixel, so small images need
+/// fewer than the maximum.
+pub(crate) fn flood_fill_depth(width: i32, height: i32) -> usize {
+    let pixels = width.max(1) as usize * height.max(1) as usize;
+    pixels.min(FLOOD_FILL_MAX_DEPTH)
+}
+
+/// Fill the span containing (x, y), returning its extent.
+fn flood_fill_line<F>(
     image: &mut Image,
     x: i32,
     y: i32,
     from: i32,
     to: i32,
     func: &mut F,
-    depth: i32,
-) where
+) -> (i32, i32)
+where
     F: FnMut(/*y:*/ i32, /*left:*/ i32, /*right:*/ i32),
 {
     let mut left: i32 = x;
     let mut right: i32 = x;
-    let mut row: usize = (y * image.w) as usize;
-    if depth >= FLOOD_FILL_MAX_DEPTH {
-        return;
-    }
+    let row: usize = (y * image.w) as usize;
 
     while left > 0 && (i32::from(image[row + (left - 1) as usize]) == from) {
         left -= 1;
@@ -148,37 +186,87 @@ fn flood_fill_seed<F>(
     }
 
     // Fill the extent
-    // TODO Use a simple for statement (currently, it causes a stack overflow during tests)
-    let mut i = left;
-    while i <= right {
+    for i in left..=right {
         image[row + i as usize] = to as u8;
-        i += 1;
     }
 
     func(y, left, right);
 
-    // Seed new flood-fills
-    if y > 0 {
-        row = ((y - 1) * image.w) as usize;
+    (left, right)
+}
+
+/// Span-based floodfill routine
+///
+/// The spans are visited in the same order as by the recursive version,
+/// with `stack` taking the place of the call stack. Spans deeper than
+/// `stack.len()` are left unfilled.
+fn flood_fill_seed<F>(
+    image: &mut Image,
+    stack: &mut [FloodFillVars],
+    x: i32,
+    y: i32,
+    from: i32,
+    to: i32,
+    func: &mut F,
+) where
+    F: FnMut(/*y:*/ i32, /*left:*/ i32, /*right:*/ i32),
+{
+    if stack.is_empty() {
+        return;
+    }
+
+    let (left, right) = flood_fill_line(image, x, y, from, to, func);
+    stack[0] = FloodFillVars {
+        y,
+        right,
+        left_up: left,
+        left_down: left,
+    };
+    let mut depth: usize = 0;
+
+    loop {
+        let can_descend = depth + 1 < stack.len();
+        let vars = &mut stack[depth];
+        let mut next: Option<(i32, i32)> = None;
+
+        // Seed new flood-fills
+        if vars.y > 0 {
+            let row = ((vars.y - 1) * image.w) as usize;
 
-        let mut i = left;
-        while i <= right {
-            if i32::from(image[row + i as usize]) == from {
-                flood_fill_seed(image, i, y - 1, from, to, func, depth + 1);
+            while vars.left_up <= vars.right {
+                if i32::from(image[row + vars.left_up as usize]) == from && can_descend {
+                    next = Some((vars.left_up, vars.y - 1));
+                    break;
+                }
+                vars.left_up += 1;
             }
-            i += 1;
         }
-    }
 
-    if y < image.h - 1 {
-        row = ((y + 1) * image.w) as usize;
+        if next.is_none() && vars.y < image.h - 1 {
+            let row = ((vars.y + 1) * image.w) as usize;
+
+            while vars.left_down <= vars.right {
+                if i32::from(image[row + vars.left_down as usize]) == from && can_descend {
+                    next = Some((vars.left_down, vars.y + 1));
+                    break;
+                }
+                vars.left_down += 1;
+            }
+        }
 
-        let mut i = left;
-        while i <= right {
-            if i32::from(image[row + i as usize]) == from {
-                flood_fill_seed(image, i, y + 1, from, to, func, depth + 1);
+        match next {
+            Some((x, y)) => {
+                let (left, right) = flood_fill_line(image, x, y, from, to, func);
+                depth += 1;
+                stack[depth] = FloodFillVars {
+                    y,
+                    right,
+                    left_up: left,
+                    left_down: left,
+                };
             }
-            i += 1;
+            None if depth > 0 => depth -= 1,
+            None => break,
         }
     }
 }
@@ -230,11 +318,74 @@ fn threshold(q: &mut Quirc) {
     }
 }
 
+/// Threshold chosen with Otsu's method: pixels up to and including it
+/// form the darker class.
+fn otsu_threshold(pixels: &[u8]) -> i32 {
+    let mut histogram = [0u32; 256];
+    for &p in pixels {
+        histogram[p as usize] += 1;
+    }
+
+    let total = pixels.len() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, &n)| i as f64 * f64::from(n))
+        .sum();
+
+    let mut sum_dark: f64 = 0.0;
+    let mut weight_dark: f64 = 0.0;
+    let mut best_variance: f64 = 0.0;
+    let mut threshold: i32 = 0;
+
+    for (t, &n) in histogram.iter().enumerate() {
+        weight_dark += f64::from(n);
+        let weight_light = total - weight_dark;
+        if weight_dark == 0.0 {
+            continue;
+        }
+        if weight_light == 0.0 {
+            break;
+        }
+
+        sum_dark += t as f64 * f64::from(n);
+        let mean_dark = sum_dark / weight_dark;
+        let mean_light = (sum - sum_dark) / weight_light;
+
+        // Between-class variance
+        let variance =
+            weight_dark * weight_light * (mean_dark - mean_light) * (mean_dark - mean_light);
+        if variance > best_variance {
+            best_variance = variance;
+            threshold = t as i32;
+        }
+    }
+
+    threshold
+}
+
+/// Global thresholding: pixels below `limit` become black.
+fn threshold_global(image: &mut Image, limit: i32) {
+    for pixel in image.pixels.iter_mut() {
+        *pixel = if i32::from(*pixel) < limit {
+            PIXEL_BLACK as u8
+        } else {
+            PIXEL_WHITE as u8
+        };
+    }
+}
+
 fn area_count(region: &mut Region, left: i32, right: i32) {
     region.count += right - left + 1;
 }
 
-fn region_code(image: &mut Image, regions: &mut Vec<Region>, x: i32, y: i32) -> i32 {
+fn region_code(
+    image: &mut Image,
+    stack: &mut [FloodFillVars],
+    regions: &mut Vec<Region>,
+    x: i32,
+    y: i32,
+) -> i32 {
     if x < 0 || y < 0 || x >= image.w || y >= image.h {
         return -1;
     }
@@ -261,15 +412,9 @@ fn region_code(image: &mut Image, regions: &mut Vec<Region>, x: i32, y: i32) ->
     });
     let r#box: &mut Region = regions.last_mut().unwrap();
 
-    flood_fill_seed(
-        image,
-        x,
-        y,
-        pixel,
-        region,
-        &mut |_, left, right| area_count(r#box, left, right),
-        0,
-    );
+    flood_fill_seed(image, stack, x, y, pixel, region, &mut |_, left, right| {
+        area_count(r#box, left, right)
+    });
 
     region
 }
@@ -324,6 +469,7 @@ fn find_other_corners(psd: &mut PolygonScoreDataCorners, y: i32, left: i32, righ
 
 fn find_region_corners(
     image: &mut Image,
+    stack: &mut [FloodFillVars],
     regions: &mut [Region],
     rcode: i32,
     r#ref: Point,
@@ -338,12 +484,12 @@ fn find_region_corners(
 
     flood_fill_seed(
         image,
+        stack,
         region.seed.x,
         region.seed.y,
         rcode,
         PIXEL_BLACK,
         &mut |y, left, right| find_one_corner(&mut psd, y, left, right),
-        0,
     );
 
     psd.r#ref.x = psd.corners[0].x - psd.r#ref.x;
@@ -362,24 +508,26 @@ fn find_region_corners(
 
     flood_fill_seed(
         image,
+        stack,
         region.seed.x,
         region.seed.y,
         PIXEL_BLACK,
         rcode,
         &mut |y, left, right| find_other_corners(&mut psd, y, left, right),
-        0,
     );
 }
 
 fn record_capstone(
     image: &mut Image,
+    stack: &mut [FloodFillVars],
     capstones: &mut Vec<Capstone>,
     regions: &mut [Region],
     ring: i32,
     stone: i32,
-) {
+    score: f64,
+) -> bool {
     if capstones.len() >= MAX_CAPSTONES {
-        return;
+        return false;
     }
 
     let cs_index = capstones.len();
@@ -387,6 +535,7 @@ fn record_capstone(
         qr_grid: -1,
         ring,
         stone,
+        score,
         ..Default::default()
     });
     let capstone = capstones.last_mut().unwrap();
@@ -396,6 +545,7 @@ fn record_capstone(
     // Find the corners of the ring
     find_region_corners(
         image,
+        stack,
         regions,
         ring,
         regions[stone as usize].seed,
@@ -405,29 +555,179 @@ fn record_capstone(
     // Set up the perspective transform and find the center
     capstone.c = perspective_setup(&capstone.corners, 7.0f64, 7.0f64);
     capstone.center = perspective_map(&capstone.c, 3.5f64, 3.5f64);
+    true
+}
+
+/// Whether `pb` are the lengths of runs in the 1:1:3:1:1 ratio of a
+/// finder pattern.
+fn finder_ratio(pb: &[i32; 5]) -> bool {
+    const CHECK: [i32; 5] = [1, 1, 3, 1, 1];
+
+    let avg = (pb[0] + pb[1] + pb[3] + pb[4]) / 4;
+    let err = avg * 3 / 4;
+
+    for i in 0..5 {
+        if pb[i] < CHECK[i] * avg - err || pb[i] > CHECK[i] * avg + err {
+            return false;
+        }
+    }
+    true
+}
+
+/// Like `finder_ratio()`, but in quarters of a pixel, so that the short
+/// runs along the lines of the cross-check aren't rounded off.
+fn cross_ratio(pb: &[i32; 5]) -> bool {
+    const CHECK: [i32; 5] = [1, 1, 3, 1, 1];
+
+    let avg = pb[0] + pb[1] + pb[3] + pb[4];
+    let err = avg * 3 / 4;
+
+    for i in 0..5 {
+        if pb[i] * 4 < CHECK[i] * avg - err || pb[i] * 4 > CHECK[i] * avg + err {
+            return false;
+        }
+    }
+    true
 }
 
-fn test_capstone(q: &mut Quirc, x: i32, y: i32, pb: &[i32; 5]) {
-    let ring_right: i32 = region_code(&mut q.image, &mut q.regions, x - pb[4], y);
-    let stone: i32 = region_code(&mut q.image, &mut q.regions, x - pb[4] - pb[3] - pb[2], y);
+/// How far the runs `pb` are off the 1:1:3:1:1 ratio, on average, as a
+/// fraction of what `finder_ratio()` tolerates.
+fn finder_error(pb: &[i32; 5]) -> f64 {
+    const CHECK: [i32; 5] = [1, 1, 3, 1, 1];
+
+    let avg = pb[0] + pb[1] + pb[3] + pb[4];
+    let err = f64::from(avg * 3 / 4);
+
+    let total: f64 = (0..5)
+        .map(|i| f64::from((pb[i] * 4 - CHECK[i] * avg).abs()) / err)
+        .sum();
+    (total / 5.0).min(1.0)
+}
+
+/// Whether the stone is in the middle of the ring along a line crossing
+/// them in `pb`: the two sides may differ by a module at most.
+fn concentric(pb: &[i32; 5]) -> bool {
+    let avg = (pb[0] + pb[1] + pb[3] + pb[4]) / 4;
+
+    (pb[0] + pb[1] - pb[3] - pb[4]).abs() <= avg.max(1)
+}
+
+/// Count the steps from (x, y), inside the stone, in direction (dx, dy)
+/// through the rest of the stone, the gap and the ring. Returns None if
+/// the ring isn't reached within `limit` steps, or the image ends first.
+#[allow(clippy::too_many_arguments)]
+fn cross_side(
+    image: &Image,
+    ring: i32,
+    stone: i32,
+    mut x: i32,
+    mut y: i32,
+    dx: i32,
+    dy: i32,
+    limit: i32,
+) -> Option<[i32; 3]> {
+    let mut runs = [0; 3];
+    let mut part = 0;
+
+    for _ in 0..limit {
+        x += dx;
+        y += dy;
+
+        let pixel = if x < 0 || y < 0 || x >= image.w || y >= image.h {
+            -1
+        } else {
+            i32::from(image[(y * image.w + x) as usize])
+        };
+
+        // A hole in the stone, such as a logo, is part of the stone
+        if part == 1 && pixel == stone {
+            runs[0] += runs[1];
+            runs[1] = 0;
+            part = 0;
+        }
+        if part == 0 && pixel != stone {
+            part = 1;
+        }
+        if part == 1 && pixel == ring {
+            part = 2;
+        }
+        if part == 2 && pixel != ring {
+            return Some(runs);
+        }
+        if pixel < 0 {
+            return None;
+        }
+        runs[part] += 1;
+    }
+    None
+}
+
+/// Runs of the ring, the gap, the stone, the gap and the ring along the
+/// line through (x, y) in direction (dx, dy), in steps, and the number of
+/// steps from (x, y) to the middle of the stone.
+#[allow(clippy::too_many_arguments)]
+fn cross_runs(
+    image: &Image,
+    ring: i32,
+    stone: i32,
+    x: i32,
+    y: i32,
+    dx: i32,
+    dy: i32,
+    limit: i32,
+) -> Option<([i32; 5], i32)> {
+    let forward = cross_side(image, ring, stone, x, y, dx, dy, limit)?;
+    let back = cross_side(image, ring, stone, x, y, -dx, -dy, limit)?;
+
+    let runs = [
+        back[2],
+        back[1],
+        back[0] + 1 + forward[0],
+        forward[1],
+        forward[2],
+    ];
+    Some((runs, (forward[0] - back[0]) / 2))
+}
+
+fn test_capstone(q: &mut Quirc, obs: &mut dyn Observer, x: i32, y: i32, pb: &[i32; 5]) {
+    let mut reject = |reason| obs.event(&Event::CapstoneRejected { x, y, reason });
+
+    let ring_right: i32 = region_code(
+        &mut q.image,
+        &mut q.flood_fill_vars,
+        &mut q.regions,
+        x - pb[4],
+        y,
+    );
+    let stone: i32 = region_code(
+        &mut q.image,
+        &mut q.flood_fill_vars,
+        &mut q.regions,
+        x - pb[4] - pb[3] - pb[2],
+        y,
+    );
     let ring_left: i32 = region_code(
         &mut q.image,
+        &mut q.flood_fill_vars,
         &mut q.regions,
         x - pb[4] - pb[3] - pb[2] - pb[1] - pb[0],
         y,
     );
 
     if ring_left < 0 || ring_right < 0 || stone < 0 {
+        reject(CapstoneRejection::NoRegion);
         return;
     }
 
     // Left and ring of ring should be connected
     if ring_left != ring_right {
+        reject(CapstoneRejection::RingSplit);
         return;
     }
 
     // Ring should be disconnected from stone
     if ring_left == stone {
+        reject(CapstoneRejection::RingIsStone);
         return;
     }
 
@@ -436,58 +736,108 @@ fn test_capstone(q: &mut Quirc, x: i32, y: i32, pb: &[i32; 5]) {
 
     // Already detected
     if stone_reg.capstone >= 0 || ring_reg.capstone >= 0 {
+        reject(CapstoneRejection::AlreadyDetected);
         return;
     }
 
     // Ratio should ideally be 37.5
     let ratio = stone_reg.count * 100 / ring_reg.count;
     if ratio < 10 || ratio > 70 {
+        reject(CapstoneRejection::Ratio(ratio));
         return;
     }
+    let ratio_error = if ratio * 2 < 75 {
+        (37.5 - f64::from(ratio)) / 27.5
+    } else {
+        (f64::from(ratio) - 37.5) / 32.5
+    };
+    let mut run_error = finder_error(pb);
+
+    // Lines through the centre of the stone should cross the ring and the
+    // stone in the same ratio as the row did, and the stone should be in
+    // the middle of the ring along each of them. The row needn't pass
+    // through the centre, so move to the middle of the stone along each
+    // line before checking the next. A smudge or a perspective that
+    // squeezes the pattern can spoil one of the lines, so only a majority
+    // of them has to pass.
+    if q.cross_check {
+        let mut cx = x - pb[4] - pb[3] - (pb[2] + 1) / 2;
+        let mut cy = y;
+        let limit = pb.iter().sum::<i32>() * 2;
+        let mut passed = 0;
+        let mut failure = None;
+
+        const DIRECTIONS: [(i32, i32); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+        for &(dx, dy) in &DIRECTIONS {
+            let (runs, offset) = match cross_runs(&q.image, ring_left, stone, cx, cy, dx, dy, limit)
+            {
+                Some((runs, offset)) if cross_ratio(&runs) => (runs, offset),
+                _ => {
+                    failure = failure.or(Some(CapstoneRejection::CrossCheck { dx, dy }));
+                    continue;
+                }
+            };
+
+            if !concentric(&runs) {
+                failure = failure.or(Some(CapstoneRejection::NotConcentric { dx, dy }));
+                continue;
+            }
 
-    record_capstone(
+            run_error += finder_error(&runs);
+            passed += 1;
+            cx += dx * offset;
+            cy += dy * offset;
+        }
+
+        if passed * 2 <= DIRECTIONS.len() {
+            reject(failure.unwrap());
+            return;
+        }
+        run_error /= (passed + 1) as f64;
+    }
+
+    let score = 1.0 - (run_error + ratio_error) / 2.0;
+
+    if record_capstone(
         &mut q.image,
+        &mut q.flood_fill_vars,
         &mut q.capstones,
         &mut q.regions,
         ring_left,
         stone,
-    );
+        score,
+    ) {
+        let capstone = q.capstones.len() as i32 - 1;
+        obs.event(&Event::CapstoneAccepted { x, y, capstone });
+    } else {
+        reject(CapstoneRejection::TooManyCapstones);
+        if !q.identify_errors.contains(&IdentifyError::TooManyCapstones) {
+            q.identify_errors.push(IdentifyError::TooManyCapstones);
+        }
+    }
 }
 
-fn finder_scan(q: &mut Quirc, y: i32) {
+/// Look for finder patterns in row `y`, between columns `left` and
+/// `right` (exclusive).
+pub(crate) fn finder_scan(q: &mut Quirc, obs: &mut dyn Observer, y: i32, left: i32, right: i32) {
     let row: usize = (y * q.image.w) as usize;
     let mut last_color: i32 = 0;
     let mut run_length: i32 = 0;
     let mut run_count: i32 = 0;
     let mut pb: [i32; 5] = [0; 5];
 
-    for x in 0..q.image.w {
+    for x in left..right {
         let color: i32 = if q.image[row + x as usize] != 0 { 1 } else { 0 };
 
-        if x != 0 && (color != last_color) {
+        if x != left && (color != last_color) {
             pb.copy_within(1.., 0);
             pb[4] = run_length;
             run_length = 0;
             run_count += 1;
 
-            if color == 0 && (run_count >= 5) {
-                const CHECK: [i32; 5] = [1, 1, 3, 1, 1];
-                let mut ok: i32 = 1;
-
-                let avg = (pb[0] + pb[1] + pb[3] + pb[4]) / 4;
-                let err = avg * 3 / 4;
-
-                for i in 0..5 {
-                    if pb[i as usize] < CHECK[i as usize] * avg - err
-                        || pb[i as usize] > CHECK[i as usize] * avg + err
-                    {
-                        ok = 0;
-                    }
-                }
-
-                if ok != 0 {
-                    test_capstone(q, x, y, &pb);
-                }
+            if color == 0 && (run_count >= 5) && finder_ratio(&pb) {
+                obs.event(&Event::FinderRun { x, y, runs: pb });
+                test_capstone(q, obs, x, y, &pb);
             }
         }
         run_length += 1;
@@ -498,6 +848,7 @@ fn finder_scan(q: &mut Quirc, y: i32) {
 #[allow(clippy::many_single_char_names)]
 fn find_alignment_pattern(
     image: &mut Image,
+    stack: &mut [FloodFillVars],
     regions: &mut Vec<Region>,
     capstones: &mut [Capstone],
     qr: &mut Grid,
@@ -528,7 +879,7 @@ fn find_alignment_pattern(
         const DY_MAP: [i32; 4] = [0, -1, 0, 1];
 
         for _ in 0..step_size {
-            let code: i32 = region_code(image, regions, b.x, b.y);
+            let code: i32 = region_code(image, stack, regions, b.x, b.y);
             if code >= 0 {
                 let reg = &mut regions[code as usize];
                 if reg.count >= size_estimate / 2 && (reg.count <= size_estimate * 2) {
@@ -638,26 +989,24 @@ enum Cell {
 /// Read a cell from a grid using the currently set perspective
 /// transform. Returns +/- 1 for black/white, 0 for cells which are
 /// out of image bounds.
-fn read_cell(q: &Quirc, index: i32, x: i32, y: i32) -> Cell {
-    let qr: &Grid = &q.grids[index as usize];
-
+fn read_cell(image: &Image, qr: &Grid, x: i32, y: i32) -> Cell {
     let p = perspective_map(&qr.c, f64::from(x) + 0.5f64, f64::from(y) + 0.5f64);
-    if p.y < 0 || p.y >= q.image.h || p.x < 0 || p.x >= q.image.w {
+    if p.y < 0 || p.y >= image.h || p.x < 0 || p.x >= image.w {
         Cell::OutOfBounds
-    } else if q.image[(p.y * q.image.w + p.x) as usize] != 0 {
+    } else if image[(p.y * image.w + p.x) as usize] != 0 {
         Cell::Black
     } else {
         Cell::White
     }
 }
 
-fn fitness_cell(image: &Image, qr: &mut Grid, x: i32, y: i32) -> i32 {
+fn fitness_cell(image: &Image, c: &[f64; PERSPECTIVE_PARAMS], x: i32, y: i32) -> i32 {
     let mut score: i32 = 0;
 
     const OFFSETS: [f64; 3] = [0.3, 0.5, 0.7];
     for v in &OFFSETS {
         for u in &OFFSETS {
-            let p = perspective_map(&qr.c, f64::from(x) + u, f64::from(y) + v);
+            let p = perspective_map(c, f64::from(x) + u, f64::from(y) + v);
 
             if !(p.y < 0 || p.y >= image.h || p.x < 0 || p.x >= image.w) {
                 if image[(p.y * image.w + p.x) as usize] != 0 {
@@ -671,52 +1020,84 @@ fn fitness_cell(image: &Image, qr: &mut Grid, x: i32, y: i32) -> i32 {
     score
 }
 
-fn fitness_ring(image: &Image, grid: &mut Grid, cx: i32, cy: i32, radius: i32) -> i32 {
+fn fitness_ring(
+    image: &Image,
+    c: &[f64; PERSPECTIVE_PARAMS],
+    cx: i32,
+    cy: i32,
+    radius: i32,
+) -> i32 {
     let mut score: i32 = 0;
 
     for i in 0..radius * 2 {
-        score += fitness_cell(image, grid, cx - radius + i, cy - radius);
-        score += fitness_cell(image, grid, cx - radius, cy + radius - i);
-        score += fitness_cell(image, grid, cx + radius, cy - radius + i);
-        score += fitness_cell(image, grid, cx + radius - i, cy + radius);
+        score += fitness_cell(image, c, cx - radius + i, cy - radius);
+        score += fitness_cell(image, c, cx - radius, cy + radius - i);
+        score += fitness_cell(image, c, cx + radius, cy - radius + i);
+        score += fitness_cell(image, c, cx + radius - i, cy + radius);
     }
     score
 }
 
-fn fitness_apat(image: &Image, grid: &mut Grid, cx: i32, cy: i32) -> i32 {
-    fitness_cell(image, grid, cx, cy) - fitness_ring(image, grid, cx, cy, 1)
-        + fitness_ring(image, grid, cx, cy, 2)
+fn fitness_apat(image: &Image, c: &[f64; PERSPECTIVE_PARAMS], cx: i32, cy: i32) -> i32 {
+    fitness_cell(image, c, cx, cy) - fitness_ring(image, c, cx, cy, 1)
+        + fitness_ring(image, c, cx, cy, 2)
 }
 
-fn fitness_capstone(image: &Image, grid: &mut Grid, mut x: i32, mut y: i32) -> i32 {
+fn fitness_capstone(image: &Image, c: &[f64; PERSPECTIVE_PARAMS], mut x: i32, mut y: i32) -> i32 {
     x += 3;
     y += 3;
 
-    fitness_cell(image, grid, x, y) + fitness_ring(image, grid, x, y, 1)
-        - fitness_ring(image, grid, x, y, 2)
-        + fitness_ring(image, grid, x, y, 3)
+    fitness_cell(image, c, x, y) + fitness_ring(image, c, x, y, 1) - fitness_ring(image, c, x, y, 2)
+        + fitness_ring(image, c, x, y, 3)
 }
 
 /// Compute a fitness score for the currently configured perspective
 /// transform, using the features we expect to find by scanning the
 /// grid.
-fn fitness_all(image: &Image, qr: &mut Grid) -> i32 {
-    let version: i32 = (qr.grid_size - 17) / 4;
+pub(crate) fn fitness_all(image: &Image, qr: &mut Grid) -> i32 {
+    fitness_qr(image, &qr.c, qr.grid_size, qr.model)
+}
+
+fn fitness_qr(image: &Image, c: &[f64; PERSPECTIVE_PARAMS], grid_size: i32, model: i32) -> i32 {
+    let version: i32 = (grid_size - 17) / 4;
     let mut score: i32 = 0;
 
     // Check the timing pattern
-    for i in 0..qr.grid_size - 14 {
+    for i in 0..grid_size - 14 {
         let expect: i32 = if i & 1 != 0 { 1 } else { -1 };
-        score += fitness_cell(image, qr, i + 7, 6) * expect;
-        score += fitness_cell(image, qr, 6, i + 7) * expect;
+        score += fitness_cell(image, c, i + 7, 6) * expect;
+        score += fitness_cell(image, c, 6, i + 7) * expect;
     }
 
     // Check capstones
-    score += fitness_capstone(image, qr, 0, 0);
-    score += fitness_capstone(image, qr, qr.grid_size - 7, 0);
-    score += fitness_capstone(image, qr, 0, qr.grid_size - 7);
+    score += fitness_capstone(image, c, 0, 0);
+    score += fitness_capstone(image, c, grid_size - 7, 0);
+    score += fitness_capstone(image, c, 0, grid_size - 7);
+
+    if model == MODEL_1 {
+        // Check the quiet zone along the right and bottom edges, which
+        // nothing else anchors
+        for i in 0..grid_size {
+            score -= fitness_cell(image, c, grid_size, i);
+            score -= fitness_cell(image, c, i, grid_size);
+        }
 
-    if version < 0 || version > QUIRC_MAX_VERSION as i32 {
+        // Check extension patterns
+        model1_blocks(version, &mut |x, y, vertical, extension| {
+            if extension {
+                for i in 0..4 {
+                    if vertical {
+                        score +=
+                            fitness_cell(image, c, x, y - i) - fitness_cell(image, c, x - 1, y - i);
+                    } else {
+                        score +=
+                            fitness_cell(image, c, x - i, y) - fitness_cell(image, c, x - i, y - 1);
+                    }
+                }
+            }
+        });
+        score
+    } else if version < 0 || version > QUIRC_MAX_VERSION as i32 {
         score
     } else {
         let info: &VersionInfo = &VERSION_DB[version as usize];
@@ -728,55 +1109,176 @@ fn fitness_all(image: &Image, qr: &mut Grid) -> i32 {
         }
 
         for i in 1..ap_count as i32 - 1 {
-            score += fitness_apat(image, qr, 6, info.apat[i as usize]);
-            score += fitness_apat(image, qr, info.apat[i as usize], 6);
+            score += fitness_apat(image, c, 6, info.apat[i as usize]);
+            score += fitness_apat(image, c, info.apat[i as usize], 6);
         }
 
         for i in 1..ap_count {
             for j in 1..ap_count {
-                score += fitness_apat(image, qr, info.apat[i], info.apat[j]);
+                score += fitness_apat(image, c, info.apat[i], info.apat[j]);
             }
         }
         score
     }
 }
 
-fn jiggle_perspective(image: &Image, qr: &mut Grid) {
-    let mut best: i32 = fitness_all(image, qr);
-    let mut adjustments: [f64; 8] = [0f64; 8];
+/// The score `fitness_all()` gives to a perfectly sampled grid like `qr`.
+pub(crate) fn fitness_max(qr: &Grid) -> i32 {
+    let version: i32 = (qr.grid_size - 17) / 4;
+    let mut cells: i32 = (qr.grid_size - 14) * 2 + CAPSTONE_CELLS * 3;
+
+    if qr.model == MODEL_1 {
+        cells += qr.grid_size * 2;
+        model1_blocks(version, &mut |_, _, _, extension| {
+            if extension {
+                cells += 8;
+            }
+        });
+    } else if version >= 0 && version <= QUIRC_MAX_VERSION as i32 {
+        let info: &VersionInfo = &VERSION_DB[version as usize];
+        let ap_count = info.apat.iter().take_while(|&&p| p != 0).count() as i32;
 
-    for i in 0..8 {
-        adjustments[i as usize] = qr.c[i as usize] * 0.02;
+        if ap_count > 0 {
+            cells += APAT_CELLS * (2 * max(ap_count - 2, 0) + (ap_count - 1) * (ap_count - 1));
+        }
     }
 
-    for _pass in 0..5 {
+    cells * FITNESS_SAMPLES
+}
+
+/// Samples `fitness_cell()` takes of each cell
+const FITNESS_SAMPLES: i32 = 9;
+const CAPSTONE_CELLS: i32 = 1 + 8 + 16 + 24;
+const APAT_CELLS: i32 = 1 + 8 + 16;
+
+/// Adjust the perspective transform `c` to fit the image better, as
+/// measured by `fitness`, starting with steps of `adjustments` and
+/// halving them after each pass. `report` is called with the best fitness
+/// after each pass. Returns the best fitness.
+fn jiggle(
+    c: &mut [f64; PERSPECTIVE_PARAMS],
+    mut adjustments: [f64; PERSPECTIVE_PARAMS],
+    fitness: &dyn Fn(&[f64; PERSPECTIVE_PARAMS]) -> i32,
+    report: &mut dyn FnMut(i32, i32),
+) -> i32 {
+    let mut best: i32 = fitness(c);
+    report(0, best);
+
+    for pass in 1..=5 {
         for i in 0..16 {
             let j = i >> 1;
-            let old: f64 = qr.c[j];
+            let old: f64 = c[j];
             let step: f64 = adjustments[j];
 
             let new = if i & 1 != 0 { old + step } else { old - step };
 
-            qr.c[j] = new;
-            let test = fitness_all(image, qr);
+            c[j] = new;
+            let test = fitness(c);
 
             if test > best {
                 best = test;
             } else {
-                qr.c[j] = old;
+                c[j] = old;
             }
         }
 
         for adjustment in &mut adjustments {
             *adjustment *= 0.5;
         }
+
+        report(pass, best);
+    }
+
+    best
+}
+
+/// First step of `jiggle_corners()`, as a fraction of the size of a cell
+const CORNER_STEP: f64 = 0.5;
+
+/// Adjust the perspective transform `c` of a `w` by `h` grid by moving
+/// its corners to fit the image better, like `jiggle()`. Unlike the terms
+/// adjusted by `jiggle_perspective()`, the corners move independently of
+/// each other, so a skewed or affine grid can be fixed.
+fn jiggle_corners(
+    c: &mut [f64; PERSPECTIVE_PARAMS],
+    w: f64,
+    h: f64,
+    fitness: &dyn Fn(&[f64; PERSPECTIVE_PARAMS]) -> i32,
+    report: &mut dyn FnMut(i32, i32),
+) -> i32 {
+    let cell = (c[0].fabs() + c[1].fabs() + c[3].fabs() + c[4].fabs()) * 0.5;
+    let rect =
+        [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)].map(|(u, v)| perspective_map_exact(c, u, v));
+    let mut corners = [0.0; PERSPECTIVE_PARAMS];
+    for (k, &(x, y)) in rect.iter().enumerate() {
+        corners[k * 2] = x;
+        corners[k * 2 + 1] = y;
     }
+
+    let setup = |corners: &[f64; PERSPECTIVE_PARAMS]| {
+        let rect = [0, 1, 2, 3].map(|k| (corners[k * 2], corners[k * 2 + 1]));
+        perspective_setup_exact(&rect, w, h)
+    };
+    let best = jiggle(
+        &mut corners,
+        [cell * CORNER_STEP; PERSPECTIVE_PARAMS],
+        &|corners| fitness(&setup(corners)),
+        report,
+    );
+    *c = setup(&corners);
+    best
+}
+
+/// Adjust the perspective transform of `qr` to fit the image better,
+/// returning its `fitness_all()` score.
+pub(crate) fn jiggle_perspective(image: &Image, qr: &mut Grid, obs: &mut dyn Observer) -> i32 {
+    let (caps, grid_size, model) = (qr.caps, qr.grid_size, qr.model);
+    let max = fitness_max(qr);
+    let fitness = |c: &[f64; PERSPECTIVE_PARAMS]| fitness_qr(image, c, grid_size, model);
+    let mut report = |pass, fitness| {
+        obs.event(&Event::JiggleFitness {
+            caps,
+            pass,
+            fitness,
+            max,
+        })
+    };
+
+    let adjustments = qr.c.map(|c| c * 0.02);
+    let best = jiggle(&mut qr.c, adjustments, &fitness, &mut report);
+
+    // Without alignment patterns, only the extension patterns and quiet
+    // zone hold the far corner, so let it move apart from the others
+    if model == MODEL_1 {
+        let size = f64::from(grid_size);
+        return jiggle_corners(&mut qr.c, size, size, &fitness, &mut report);
+    }
+    best
+}
+
+/// See `Grid::score`.
+pub(crate) fn grid_score(qr: &Grid, fitness: i32) -> f64 {
+    let fitness = f64::from(fitness) / f64::from(fitness_max(qr));
+
+    let timing = if qr.hscan < 0 || qr.vscan < 0 {
+        0.0
+    } else {
+        let (h, v) = (qr.hscan * 2 + 13, qr.vscan * 2 + 13);
+        f64::from(min(h, v)) / f64::from(max(h, v))
+    };
+
+    (fitness.clamp(0.0, 1.0) + timing) / 2.0
 }
 
 /// Once the capstones are in place and an alignment point has been
 /// chosen, we call this function to set up a grid-reading perspective
 /// transform.
-fn setup_qr_perspective(image: &Image, capstones: &[Capstone], qr: &mut Grid) {
+fn setup_qr_perspective(
+    image: &Image,
+    capstones: &[Capstone],
+    qr: &mut Grid,
+    obs: &mut dyn Observer,
+) -> i32 {
     // Set up the perspective map for reading the grid
     let rect: [Point; 4] = [
         capstones[qr.caps[1] as usize].corners[0],
@@ -790,7 +1292,130 @@ fn setup_qr_perspective(image: &Image, capstones: &[Capstone], qr: &mut Grid) {
         f64::from(qr.grid_size - 7),
     );
 
-    jiggle_perspective(image, qr);
+    jiggle_perspective(image, qr, obs)
+}
+
+/// A grid whose alignment pattern nearest the bottom right corner scores
+/// at least this fraction of the best possible is taken to be Model 2.
+const MIN_ALIGNMENT_FITNESS: f64 = 0.75;
+
+/// Most bit errors in the version information of a grid taken as read
+const VERSION_INFO_MAX_ERROR: u32 = 3;
+
+/// The version information of a Model 2 code of `version`: the version,
+/// then the remainder of its BCH code.
+fn version_info(version: i32) -> u32 {
+    let mut rem = (version as u32) << 12;
+    for i in (12..18).rev() {
+        if rem & 1 << i != 0 {
+            rem ^= 0x1f25 << (i - 12);
+        }
+    }
+    (version as u32) << 12 | rem
+}
+
+/// Model 1 codes have no alignment patterns, so the region taken for one
+/// in `qr` may be anything. If the grid could be a Model 1 code, there
+/// doesn't seem to be an alignment pattern and it doesn't decode, set up
+/// Model 1 grids from the `estimate` of where the alignment pattern would
+/// be or, failing that, from the parallelogram spanned by the capstones,
+/// and keep the first that decodes as Model 1. `qr` is left as it was
+/// otherwise.
+fn try_model1(
+    image: &Image,
+    capstones: &[Capstone],
+    obs: &mut dyn Observer,
+    qr: &mut Grid,
+    estimate: Point,
+    scratch: &mut Option<Box<Scratch>>,
+) {
+    let version: i32 = (qr.grid_size - 17) / 4;
+    if version < 2 || version > MODEL1_MAX_VERSION as i32 {
+        return;
+    }
+
+    // Without the alignment pattern, an inferred capstone leaves too
+    // little of the grid anchored
+    if qr.caps.iter().any(|&i| capstones[i as usize].ring < 0) {
+        return;
+    }
+
+    let info: &VersionInfo = &VERSION_DB[version as usize];
+    let last = info.apat.iter().cloned().take_while(|&p| p != 0).last();
+    if let Some(p) = last {
+        let fitness = fitness_apat(image, &qr.c, p, p);
+        if f64::from(fitness) >= MIN_ALIGNMENT_FITNESS * f64::from(APAT_CELLS * FITNESS_SAMPLES) {
+            return;
+        }
+    }
+
+    // Both models have the format information, and Model 2 codes from
+    // version 7 also the version information, next to the capstones. Read
+    // them with the capstones' own perspective, as the grid may be off
+    // anywhere without the alignment pattern.
+    let size = qr.grid_size;
+    let dark = |x: i32, y: i32| {
+        let far = size - 7;
+        let (i, u, v) = if y >= size / 2 {
+            (0, x, y - far)
+        } else if x >= size / 2 {
+            (2, x - far, y)
+        } else {
+            (1, x, y)
+        };
+        fitness_cell(image, &capstones[qr.caps[i] as usize].c, u, v) > 0
+    };
+
+    // Jiggling is costly, so don't go on if the format information doesn't
+    // read, or the version information does
+    if decode::format_info(0, size, &dark).is_err() && decode::format_info(1, size, &dark).is_err()
+    {
+        return;
+    }
+    if version >= 7 {
+        let read = |cell: &dyn Fn(i32) -> (i32, i32)| {
+            (0..18).fold(0, |info: u32, i| {
+                let (x, y) = cell(i);
+                info | u32::from(dark(x, y)) << i
+            })
+        };
+        let expect = version_info(version);
+        let info = [
+            read(&|i| (size - 11 + i % 3, i / 3)),
+            read(&|i| (i / 3, size - 11 + i % 3)),
+        ];
+        if info
+            .iter()
+            .any(|&info| (info ^ expect).count_ones() <= VERSION_INFO_MAX_ERROR)
+        {
+            return;
+        }
+    }
+
+    let scratch = scratch.get_or_insert_with(Default::default);
+    if scratch.decodes(image, qr) {
+        return;
+    }
+
+    let corner = |i: usize| capstones[qr.caps[i] as usize].corners[0];
+    let parallelogram = Point {
+        x: corner(0).x + corner(2).x - corner(1).x,
+        y: corner(0).y + corner(2).y - corner(1).y,
+    };
+    for &align in &[estimate, parallelogram] {
+        let mut model1 = Grid {
+            align_region: -1,
+            align,
+            model: MODEL_1,
+            ..*qr
+        };
+        let fitness = setup_qr_perspective(image, capstones, &mut model1, obs);
+        model1.score = grid_score(&model1, fitness);
+        if scratch.decodes(image, &model1) {
+            *qr = model1;
+            return;
+        }
+    }
 }
 
 /// Rotate the capstone with so that corner 0 is the leftmost with respect
@@ -812,11 +1437,15 @@ fn rotate_capstone(cap: &mut Capstone, h0: Point, hd: Point) {
     cap.c = perspective_setup(&cap.corners, 7.0, 7.0);
 }
 
-fn record_qr_grid(q: &mut Quirc, mut a: i32, b: i32, mut c: i32) {
-    if q.grids.len() >= MAX_GRIDS {
-        return;
-    }
-
+/// Set up a grid from capstones A, B and C, B being the corner, rotating
+/// the capstones to suit it. The grid is scored, but not recorded.
+fn setup_grid(
+    q: &mut Quirc,
+    obs: &mut dyn Observer,
+    mut a: i32,
+    b: i32,
+    mut c: i32,
+) -> core::result::Result<Candidate, IdentifyError> {
     // Construct the hypotenuse line from A to C. B should be to
     // the left of this line.
     let h0 = q.capstones[a as usize].center;
@@ -830,13 +1459,11 @@ fn record_qr_grid(q: &mut Quirc, mut a: i32, b: i32, mut c: i32) {
         + (q.capstones[b as usize].center.y - h0.y) * hd.x
         > 0
     {
-        std::mem::swap(&mut a, &mut c);
+        core::mem::swap(&mut a, &mut c);
         hd.x = -hd.x;
         hd.y = -hd.y;
     }
 
-    // Record the grid and its components
-    let qr_index = q.grids.len() as i32;
     let mut qr = Grid {
         caps: [a, b, c],
         align_region: -1,
@@ -848,12 +1475,18 @@ fn record_qr_grid(q: &mut Quirc, mut a: i32, b: i32, mut c: i32) {
     for i in 0..3 {
         let cap = &mut q.capstones[qr.caps[i as usize] as usize];
         rotate_capstone(cap, h0, hd);
-        cap.qr_grid = qr_index;
     }
 
     // Check the timing pattern. This doesn't require a perspective
     // transform.
-    if measure_timing_pattern(&q.image, &q.capstones, &mut qr) >= 0 {
+    let timing = measure_timing_pattern(&q.image, &q.capstones, &mut qr);
+    obs.event(&Event::TimingScan {
+        caps: qr.caps,
+        hscan: qr.hscan,
+        vscan: qr.vscan,
+    });
+
+    if timing >= 0 {
         // Make an estimate based for the alignment pattern based on extending
         // lines from capstones A and C.
         if line_intersect(
@@ -864,10 +1497,18 @@ fn record_qr_grid(q: &mut Quirc, mut a: i32, b: i32, mut c: i32) {
             &mut qr.align,
         ) != 0
         {
+            let estimate = qr.align;
+
             // On V2+ grids, we should use the alignment pattern.
             if qr.grid_size > 21 {
                 // Try to find the actual location of the alignment pattern.
-                find_alignment_pattern(&mut q.image, &mut q.regions, &mut q.capstones, &mut qr);
+                find_alignment_pattern(
+                    &mut q.image,
+                    &mut q.flood_fill_vars,
+                    &mut q.regions,
+                    &mut q.capstones,
+                    &mut qr,
+                );
 
                 // Find the point of the alignment pattern closest to the
                 // top-left of the QR grid.
@@ -885,36 +1526,89 @@ fn record_qr_grid(q: &mut Quirc, mut a: i32, b: i32, mut c: i32) {
 
                     flood_fill_seed(
                         &mut q.image,
+                        &mut q.flood_fill_vars,
                         reg.seed.x,
                         reg.seed.y,
                         qr.align_region,
                         PIXEL_BLACK,
                         &mut |_, _, _| (),
-                        0,
                     );
                     flood_fill_seed(
                         &mut q.image,
+                        &mut q.flood_fill_vars,
                         reg.seed.x,
                         reg.seed.y,
                         PIXEL_BLACK,
                         qr.align_region,
                         &mut |y, left, right| find_leftmost_to_line(&mut psd, y, left, right),
-                        0,
                     );
                 }
             }
 
-            setup_qr_perspective(&q.image, &q.capstones, &mut qr);
-            q.grids.push(qr);
-            return;
+            let fitness = setup_qr_perspective(&q.image, &q.capstones, &mut qr, obs);
+            qr.score = grid_score(&qr, fitness);
+            try_model1(
+                &q.image,
+                &q.capstones,
+                obs,
+                &mut qr,
+                estimate,
+                &mut q.scratch,
+            );
+            obs.event(&Event::GridScore {
+                caps: qr.caps,
+                score: qr.score,
+            });
+
+            if qr.score < q.min_grid_score {
+                return Err(IdentifyError::LowScore { caps: qr.caps });
+            }
+
+            return Ok(Candidate {
+                grid: qr,
+                h0,
+                hd,
+                ..Default::default()
+            });
         }
+
+        Err(IdentifyError::AlignmentEstimate { caps: qr.caps })
+    } else {
+        Err(IdentifyError::TimingPattern { caps: qr.caps })
     }
+}
 
-    // We've been unable to complete setup for this grid. Undo what we've
-    // recorded and pretend it never happened.
-    for i in 0..3 {
-        q.capstones[qr.caps[i as usize] as usize].qr_grid = -1;
+/// Record the grid of a candidate chosen by `choose_grids()`.
+fn record_qr_grid(q: &mut Quirc, obs: &mut dyn Observer, candidate: &Candidate) {
+    if q.grids.len() >= MAX_GRIDS {
+        if !q.identify_errors.contains(&IdentifyError::TooManyGrids) {
+            q.identify_errors.push(IdentifyError::TooManyGrids);
+        }
+        return;
+    }
+
+    let qr = &candidate.grid;
+    let qr_index = q.grids.len() as i32;
+
+    // Other candidates may have rotated the capstones since
+    for &i in &qr.caps {
+        let cap = &mut q.capstones[i as usize];
+        rotate_capstone(cap, candidate.h0, candidate.hd);
+        cap.qr_grid = qr_index;
     }
+
+    // On V2+ grids the alignment pattern should have been found, unless
+    // it's a Model 1 code
+    if qr.grid_size > 21 && qr.align_region < 0 && qr.model != MODEL_1 {
+        q.identify_errors
+            .push(IdentifyError::AlignmentPattern { grid: qr_index });
+    }
+
+    obs.event(&Event::Recorded {
+        caps: qr.caps,
+        grid: qr_index,
+    });
+    q.grids.push(*qr);
 }
 
 #[derive(Copy)]
@@ -930,119 +1624,956 @@ impl Clone for Neighbour {
     }
 }
 
-fn test_neighbours(q: &mut Quirc, i: i32, hlist: &[Neighbour], vlist: &[Neighbour]) {
-    let mut best_score: f64 = 0.0;
-    let mut best_h: i32 = -1;
-    let mut best_v: i32 = -1;
+/// Grids are set up from this many pairs of neighbours of each capstone
+/// at most, the nearest ones.
+const CANDIDATES_PER_CAPSTONE: usize = 4;
+
+pub(crate) const MAX_CANDIDATES: usize = MAX_CAPSTONES * CANDIDATES_PER_CAPSTONE;
+
+/// A grid set up from three capstones, or why it couldn't be. Grids
+/// compete for their capstones with the grids set up from other
+/// groupings, see `choose_grids()`.
+#[derive(Copy)]
+pub(crate) struct Candidate {
+    grid: Grid,
+
+    /// Reference line for `rotate_capstone()`
+    h0: Point,
+    hd: Point,
+
+    /// The capstone whose neighbours the others are
+    corner: i32,
+    error: Option<IdentifyError>,
+
+    /// The grid score, less if the neighbours are at unequal distances
+    weight: f64,
+
+    /// Sum of the weights of this and the following candidates, for
+    /// bounding the search
+    bound: f64,
+
+    /// Position in the order the candidates were found
+    order: usize,
+    taken: bool,
+    chosen: bool,
+}
+
+impl Clone for Candidate {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl Default for Candidate {
+    fn default() -> Self {
+        Candidate {
+            grid: Default::default(),
+            h0: Default::default(),
+            hd: Default::default(),
+            corner: -1,
+            error: None,
+            weight: 0.0,
+            bound: 0.0,
+            order: 0,
+            taken: false,
+            chosen: false,
+        }
+    }
+}
+
+impl Candidate {
+    fn caps_mask(&self) -> u32 {
+        // MAX_CAPSTONES is 32
+        self.grid.caps.iter().fold(0, |mask, &i| mask | 1 << i)
+    }
+}
+
+fn test_neighbours(
+    q: &mut Quirc,
+    obs: &mut dyn Observer,
+    i: i32,
+    hlist: &[Neighbour],
+    vlist: &[Neighbour],
+) {
+    // The nearest acceptable pairs, as (distance, horizontal, vertical,
+    // score)
+    let mut pairs = [(0.0, -1, -1, 0.0); CANDIDATES_PER_CAPSTONE];
+    let mut count = 0;
 
     // Test each possible grouping
     for hn in hlist {
         for vn in vlist {
-            let score: f64 = (1.0 - hn.distance / vn.distance).abs();
+            let score: f64 = (1.0 - hn.distance / vn.distance).fabs();
+            obs.event(&Event::NeighbourScore {
+                capstone: i,
+                horizontal: hn.index,
+                vertical: vn.index,
+                score,
+            });
 
             if score > 2.5 {
                 continue;
             }
 
-            if best_h < 0 || score < best_score {
-                best_h = hn.index;
-                best_v = vn.index;
-                best_score = score;
+            let pair = (hn.distance + vn.distance, hn.index, vn.index, score);
+            if count < pairs.len() {
+                pairs[count] = pair;
+                count += 1;
+            } else if pair.0 < pairs[count - 1].0 {
+                pairs[count - 1] = pair;
+            } else {
+                continue;
             }
+            pairs[..count]
+                .sort_unstable_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
         }
     }
 
-    if best_h < 0 || best_v < 0 {
+    if count == 0 {
+        obs.event(&Event::NotGrouped {
+            capstone: i,
+            horizontal: hlist.len() as i32,
+            vertical: vlist.len() as i32,
+        });
         return;
     }
 
-    record_qr_grid(q, best_h, i, best_v);
+    for &(_, h, v, score) in &pairs[..count] {
+        obs.event(&Event::Grouped {
+            caps: [h, i, v],
+            score,
+        });
+
+        let candidate = match setup_grid(q, obs, h, i, v) {
+            Ok(candidate) => Candidate {
+                weight: candidate.grid.score / (1.0 + score),
+                ..candidate
+            },
+            Err(error) => Candidate {
+                error: Some(error),
+                weight: -1.0,
+                ..Default::default()
+            },
+        };
+
+        q.candidates.push(Candidate {
+            corner: i,
+            order: q.candidates.len(),
+            ..candidate
+        });
+    }
 }
 
-fn test_grouping(q: &mut Quirc, i: usize) {
+fn test_grouping(q: &mut Quirc, obs: &mut dyn Observer, i: usize) {
     let c1 = &q.capstones[i];
 
-    if c1.qr_grid >= 0 {
-        return;
-    }
-
-    let mut hlist = Vec::new();
-    let mut vlist = Vec::new();
+    const NONE: Neighbour = Neighbour {
+        index: -1,
+        distance: 0.0,
+    };
+    let mut hlist = [NONE; MAX_CAPSTONES];
+    let mut vlist = [NONE; MAX_CAPSTONES];
+    let mut hcount: usize = 0;
+    let mut vcount: usize = 0;
 
     // Look for potential neighbours by examining the relative gradients
     // from this capstone to others.
     for (j, c2) in q.capstones.iter().enumerate() {
-        if i == j || c2.qr_grid >= 0 {
+        if i == j {
             continue;
         }
 
         let (mut u, mut v) = perspective_unmap(&c1.c, c2.center);
 
-        u = (u - 3.5).abs();
-        v = (v - 3.5).abs();
+        u = (u - 3.5).fabs();
+        v = (v - 3.5).fabs();
 
         if u < 0.2 * v {
-            hlist.push(Neighbour {
+            hlist[hcount] = Neighbour {
                 index: j as i32,
                 distance: v,
-            });
+            };
+            hcount += 1;
         }
 
         if v < 0.2 * u {
-            vlist.push(Neighbour {
+            vlist[vcount] = Neighbour {
                 index: j as i32,
                 distance: u,
-            });
+            };
+            vcount += 1;
         }
     }
 
-    if !(!hlist.is_empty() && (!vlist.is_empty())) {
+    if !(hcount != 0 && (vcount != 0)) {
+        obs.event(&Event::NotGrouped {
+            capstone: i as i32,
+            horizontal: hcount as i32,
+            vertical: vcount as i32,
+        });
         return;
     }
 
-    test_neighbours(q, i as i32, &hlist, &vlist);
+    test_neighbours(q, obs, i as i32, &hlist[..hcount], &vlist[..vcount]);
+}
+
+/// Choose the grids which share no capstones and have the greatest total
+/// weight, marking them `chosen`. A greedy choice can go wrong where
+/// codes are packed tightly: a grid made of capstones of neighbouring
+/// codes may take capstones from the right ones.
+fn choose_grids(candidates: &mut [Candidate]) {
+    // Give up on finding the best choice after trying this many
+    // candidates, and go with the best found so far
+    const MAX_STEPS: u32 = 10000;
+
+    // Try the heaviest first, so that the first choice found is the
+    // greedy one, and the bound prunes early. Failed candidates, weighing
+    // -1, go last and aren't considered.
+    candidates.sort_unstable_by(|a, b| b.weight.partial_cmp(&a.weight).unwrap_or(Ordering::Equal));
+    let n = candidates.iter().take_while(|c| c.error.is_none()).count();
+
+    let mut bound = 0.0;
+    for c in candidates[..n].iter_mut().rev() {
+        bound += c.weight;
+        c.bound = bound;
+    }
+
+    // Depth first search, taking each candidate if it fits and then
+    // leaving it out
+    let mut best = -1.0;
+    let mut total = 0.0;
+    let mut used: u32 = 0;
+    let mut k = 0;
+    let mut steps = 0;
+
+    loop {
+        if k < n && total + candidates[k].bound > best {
+            let c = &mut candidates[k];
+            c.taken = c.caps_mask() & used == 0;
+            if c.taken {
+                used |= c.caps_mask();
+                total += c.weight;
+            }
+            k += 1;
+            continue;
+        }
+
+        if k == n && total > best {
+            best = total;
+            for c in candidates[..n].iter_mut() {
+                c.chosen = c.taken;
+            }
+        }
+
+        steps += 1;
+        if steps >= MAX_STEPS {
+            break;
+        }
+
+        // Back up to the last candidate taken, and leave it out instead
+        while k > 0 && !candidates[k - 1].taken {
+            k -= 1;
+        }
+        if k == 0 {
+            break;
+        }
+        let c = &mut candidates[k - 1];
+        c.taken = false;
+        used &= !c.caps_mask();
+        total -= c.weight;
+    }
+
+    candidates.sort_unstable_by_key(|c| c.order);
+}
+
+/// Grids set up from two capstones by `recover_grid()` must score at
+/// least this.
+const MIN_RECOVERED_SCORE: f64 = 0.7;
+
+/// A capstone shaped like `cap` but centred on `center`, standing in for
+/// one that couldn't be found in the image. Its ring and stone are -1.
+fn infer_capstone(cap: &Capstone, center: Point) -> Capstone {
+    let (dx, dy) = (center.x - cap.center.x, center.y - cap.center.y);
+    let mut corners = cap.corners;
+    for p in &mut corners {
+        p.x += dx;
+        p.y += dy;
+    }
+
+    Capstone {
+        ring: -1,
+        stone: -1,
+        corners,
+        center,
+        c: perspective_setup(&corners, 7.0, 7.0),
+        qr_grid: -1,
+        score: 0.0,
+    }
+}
+
+/// Whether the format information of `qr` reads from the image, either
+/// copy of it.
+fn format_readable(image: &Image, qr: &Grid) -> bool {
+    let dark = |x: i32, y: i32| read_cell(image, qr, x, y) == Cell::Black;
+
+    decode::format_info(0, qr.grid_size, &dark).is_ok()
+        || decode::format_info(1, qr.grid_size, &dark).is_ok()
+}
+
+/// Try to set up a grid from capstones I and J alone, the third finder
+/// pattern being covered or damaged. Each place the third capstone could
+/// be is tried, and the grid fitting the image best is recorded if it
+/// scores at least `MIN_RECOVERED_SCORE` and its format information
+/// reads. Two capstones are much more easily found by chance than three.
+fn recover_grid(q: &mut Quirc, obs: &mut dyn Observer, i: usize, j: usize) -> bool {
+    let (ci, cj) = (q.capstones[i], q.capstones[j]);
+    let (u, v) = perspective_unmap(&ci.c, cj.center);
+    let (u, v) = ((u - 3.5).fabs(), (v - 3.5).fabs());
+
+    // Capstones of a grid are at least 14 modules apart
+    if u.max(v) < 10.0 {
+        return false;
+    }
+
+    // `p` moved by `w` turned a quarter turn either way
+    let turn = |p: Point, w: Point, sign: i32| Point {
+        x: p.x - sign * w.y,
+        y: p.y + sign * w.x,
+    };
+    let (i, j) = (i as i32, j as i32);
+    let w = Point {
+        x: cj.center.x - ci.center.x,
+        y: cj.center.y - ci.center.y,
+    };
+
+    // Capstones A, B (the corner) and C, the missing one being -1, and
+    // where that one should be
+    let mut hypotheses = [([-1; 3], Point::default()); 4];
+    let count = if u < 0.2 * v || v < 0.2 * u {
+        // Neighbours along an edge of the grid: either may be the
+        // corner, with the missing capstone to either side of it
+        hypotheses = [
+            ([j, i, -1], turn(ci.center, w, 1)),
+            ([j, i, -1], turn(ci.center, w, -1)),
+            ([i, j, -1], turn(cj.center, w, 1)),
+            ([i, j, -1], turn(cj.center, w, -1)),
+        ];
+        4
+    } else if (u - v).fabs() < 0.2 * u.max(v) {
+        // Opposite ends of the hypotenuse, the corner missing on either
+        // side of it. The grid is then set up using the alignment
+        // pattern, if there is one.
+        let middle = Point {
+            x: (ci.center.x + cj.center.x) / 2,
+            y: (ci.center.y + cj.center.y) / 2,
+        };
+        let half = Point {
+            x: w.x / 2,
+            y: w.y / 2,
+        };
+        hypotheses[0] = ([i, -1, j], turn(middle, half, 1));
+        hypotheses[1] = ([i, -1, j], turn(middle, half, -1));
+        2
+    } else {
+        return false;
+    };
+
+    let index = q.capstones.len() as i32;
+    let mut best: Option<(Candidate, Capstone)> = None;
+
+    for &(caps, center) in &hypotheses[..count] {
+        q.capstones.push(infer_capstone(&ci, center));
+        let [a, b, c] = caps.map(|k| if k < 0 { index } else { k });
+
+        if let Ok(candidate) = setup_grid(q, obs, a, b, c) {
+            let better = match best {
+                Some((best, _)) => candidate.grid.score > best.grid.score,
+                None => true,
+            };
+            if better {
+                best = Some((candidate, q.capstones[index as usize]));
+            }
+        }
+        q.capstones.pop();
+    }
+
+    // Each attempt rotated I and J to suit its grid
+    q.capstones[i as usize] = ci;
+    q.capstones[j as usize] = cj;
+
+    match best {
+        Some((candidate, inferred))
+            if candidate.grid.score >= MIN_RECOVERED_SCORE
+                && format_readable(&q.image, &candidate.grid) =>
+        {
+            q.capstones.push(inferred);
+            obs.event(&Event::CapstoneInferred {
+                capstone: index,
+                caps: candidate.grid.caps,
+            });
+            record_qr_grid(q, obs, &candidate);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Set up grids from pairs of capstones left ungrouped, see
+/// `recover_grid()`.
+fn recover_grids(q: &mut Quirc, obs: &mut dyn Observer) {
+    let count = q.capstones.len();
+
+    for i in 0..count {
+        for j in i + 1..count {
+            if q.grids.len() >= MAX_GRIDS || q.capstones.len() >= MAX_CAPSTONES {
+                return;
+            }
+
+            if q.capstones[i].qr_grid < 0
+                && q.capstones[j].qr_grid < 0
+                && !rmqr_capstone(q, i)
+                && !rmqr_capstone(q, j)
+            {
+                recover_grid(q, obs, i, j);
+            }
+        }
+    }
+}
+
+/// rMQR grids must score at least this, whatever `Quirc::min_grid_score`.
+const MIN_RMQR_SCORE: f64 = 0.5;
+
+/// The sub-finder pattern of an rMQR code must score at least this
+/// fraction of the best possible before a grid is set up from it.
+const MIN_SUB_FINDER_FITNESS: f64 = 0.5;
+
+/// Adjust the perspective transform `c` of an rMQR grid of version `ver`
+/// to fit the image better, returning its `fitness_rmqr()` score.
+fn jiggle_rmqr(image: &Image, c: &mut [f64; PERSPECTIVE_PARAMS], ver: &RmqrVersion) -> i32 {
+    jiggle_corners(
+        c,
+        f64::from(ver.width),
+        f64::from(ver.height),
+        &|c| fitness_rmqr(image, c, ver).0,
+        &mut |_, _| (),
+    )
+}
+
+/// Adjust the perspective transform `c` of the finder pattern of an rMQR
+/// code, 7 cells square, to fit it, its separator and the start of the
+/// timing pattern better before reading the format information.
+fn jiggle_rmqr_finder(image: &Image, c: &mut [f64; PERSPECTIVE_PARAMS]) -> i32 {
+    jiggle_corners(
+        c,
+        7.0,
+        7.0,
+        &|c| {
+            let mut score = fitness_capstone(image, c, 0, 0);
+            for y in 0..7 {
+                score -= fitness_cell(image, c, 7, y);
+            }
+            for x in 8..12 {
+                score += fitness_cell(image, c, x, 0) * if x & 1 != 0 { -1 } else { 1 };
+            }
+            score
+        },
+        &mut |_, _| (),
+    )
+}
+
+/// Cells of an rMQR grid, from the top right corner, searched for its
+/// corner pattern
+const CORNER_SEARCH: i32 = 3;
+
+/// Compute a fitness score for an rMQR grid of version `ver`, returning
+/// it and the score of a perfectly sampled grid.
+fn fitness_rmqr(image: &Image, c: &[f64; PERSPECTIVE_PARAMS], ver: &RmqrVersion) -> (i32, i32) {
+    let (w, h) = (ver.width, ver.height);
+    let apat = || ver.apat.iter().cloned().take_while(|&cx| cx != 0);
+    let expect = |i: i32| if i & 1 != 0 { -1 } else { 1 };
+    let mut score: i32 = 0;
+    let mut cells: i32 = 0;
+
+    // Check the timing patterns along the edges, skipping the alignment
+    // patterns
+    let bottom = if h == 7 { 8 } else { 2 };
+    for x in 8..w - 2 {
+        if apat().all(|cx| (x - cx).abs() > 1) {
+            score += fitness_cell(image, c, x, 0) * expect(x);
+            cells += 1;
+        }
+    }
+    for x in bottom..w - 5 {
+        if apat().all(|cx| (x - cx).abs() > 1) {
+            score += fitness_cell(image, c, x, h - 1) * expect(x);
+            cells += 1;
+        }
+    }
+    for y in 2..h - 5 {
+        score += fitness_cell(image, c, w - 1, y) * expect(y);
+        cells += 1;
+    }
+    for y in 8..h - 2 {
+        score += fitness_cell(image, c, 0, y) * expect(y);
+        cells += 1;
+    }
+
+    // Check the alignment patterns and the timing patterns between them
+    for cx in apat() {
+        for y in 3..h - 3 {
+            score += fitness_cell(image, c, cx, y) * expect(y);
+            cells += 1;
+        }
+        for &cy in &[1, h - 2] {
+            score += fitness_ring(image, c, cx, cy, 1) - fitness_cell(image, c, cx, cy);
+            cells += 9;
+        }
+    }
+
+    // Check the finder and sub-finder patterns
+    score += fitness_capstone(image, c, 0, 0);
+    score += fitness_apat(image, c, w - 3, h - 3);
+    cells += CAPSTONE_CELLS + APAT_CELLS;
+
+    (score, cells * FITNESS_SAMPLES)
+}
+
+/// Call `func` with each span of region `rcode`.
+fn region_spans<F>(
+    image: &mut Image,
+    stack: &mut [FloodFillVars],
+    regions: &[Region],
+    rcode: i32,
+    func: &mut F,
+) where
+    F: FnMut(/*y:*/ i32, /*left:*/ i32, /*right:*/ i32),
+{
+    let seed = regions[rcode as usize].seed;
+
+    flood_fill_seed(
+        image,
+        stack,
+        seed.x,
+        seed.y,
+        rcode,
+        PIXEL_BLACK,
+        &mut |_, _, _| (),
+    );
+    flood_fill_seed(image, stack, seed.x, seed.y, PIXEL_BLACK, rcode, func);
+}
+
+/// The centre of region `rcode`, in pixels.
+fn region_centroid(
+    image: &mut Image,
+    stack: &mut [FloodFillVars],
+    regions: &[Region],
+    rcode: i32,
+) -> (f64, f64) {
+    let (mut sum_x, mut sum_y, mut count) = (0i64, 0i64, 0i64);
+
+    region_spans(image, stack, regions, rcode, &mut |y, left, right| {
+        let n = i64::from(right - left + 1);
+        sum_x += i64::from(left + right) * n;
+        sum_y += i64::from(y) * 2 * n;
+        count += 2 * n;
+    });
+
+    (sum_x as f64 / count as f64, sum_y as f64 / count as f64)
+}
+
+/// The pixel of region `rcode` furthest in direction `dir`.
+fn region_extreme(
+    image: &mut Image,
+    stack: &mut [FloodFillVars],
+    regions: &[Region],
+    rcode: i32,
+    dir: (f64, f64),
+) -> Point {
+    let mut best = regions[rcode as usize].seed;
+    let mut best_score = f64::MIN;
+
+    region_spans(image, stack, regions, rcode, &mut |y, left, right| {
+        for &x in &[left, right] {
+            let score = f64::from(x) * dir.0 + f64::from(y) * dir.1;
+            if score > best_score {
+                best_score = score;
+                best = Point { x, y };
+            }
+        }
+    });
+
+    best
+}
+
+/// The outer corners of the ring of capstone `cap`, half a pixel out from
+/// the corner pixels found by `find_region_corners()`. A perspective
+/// transform set up from these can be extended well past the capstone.
+fn capstone_edges(cap: &Capstone) -> [(f64, f64); 4] {
+    let half = |p: i32, centre: i32| 0.5 * f64::from((p - centre).signum());
+
+    cap.corners.map(|p| {
+        (
+            f64::from(p.x) + half(p.x, cap.center.x),
+            f64::from(p.y) + half(p.y, cap.center.y),
+        )
+    })
+}
+
+/// Set up a grid of version `ver` from the outer corners `edges` of the
+/// finder pattern, rotated so that corner 0 is the top left of the code,
+/// the centre `sub` of the sub-finder pattern and, if it's been found, the
+/// outer corner `corner` of the top right corner pattern. Without it the
+/// grid is affine, for `jiggle()` to refine.
+fn setup_rmqr_perspective(
+    edges: &[(f64, f64); 4],
+    sub: (f64, f64),
+    corner: Option<(f64, f64)>,
+    ver: &RmqrVersion,
+) -> [f64; PERSPECTIVE_PARAMS] {
+    let (w, h) = (f64::from(ver.width), f64::from(ver.height));
+    let (x0, y0) = edges[0];
+    let left = ((edges[3].0 - x0) / 7.0, (edges[3].1 - y0) / 7.0);
+    let top_right = corner.unwrap_or_else(|| {
+        let top = (
+            (sub.0 - x0 - (h - 2.5) * left.0) / (w - 2.5),
+            (sub.1 - y0 - (h - 2.5) * left.1) / (w - 2.5),
+        );
+        (x0 + w * top.0, y0 + w * top.1)
+    });
+
+    // The vertical edge on the right, going through the sub-finder
+    // pattern
+    let top = ((top_right.0 - x0) / w, (top_right.1 - y0) / w);
+    let right = (
+        (sub.0 - top_right.0 + 2.5 * top.0) / (h - 2.5),
+        (sub.1 - top_right.1 + 2.5 * top.1) / (h - 2.5),
+    );
+
+    let rect = [
+        edges[0],
+        top_right,
+        (top_right.0 + h * right.0, top_right.1 + h * right.1),
+        (x0 + h * left.0, y0 + h * left.1),
+    ];
+    perspective_setup_exact(&rect, w, h)
+}
+
+/// Try to set up an rMQR grid around ungrouped capstone I, taking it for
+/// the finder pattern. The version is read from the format information
+/// next to it, in whichever rotation reads it with the fewest errors, and
+/// the sub-finder pattern is looked for where that version puts it. The
+/// grid is recorded if it scores at least `MIN_RMQR_SCORE`.
+fn test_rmqr(q: &mut Quirc, obs: &mut dyn Observer, i: usize) -> bool {
+    if q.rmqr_grids.len() >= MAX_GRIDS {
+        return false;
+    }
+
+    let edges = capstone_edges(&q.capstones[i]);
+    let mut best: Option<([(f64, f64); 4], u32)> = None;
+    let mut best_errors = 0;
+    for r in 0..4 {
+        let mut rotated = edges;
+        rotated.rotate_left(r);
+        let mut c = perspective_setup_exact(&rotated, 7.0, 7.0);
+        let image = &q.image;
+        jiggle_rmqr_finder(image, &mut c);
+        for (k, &(u, v)) in [(0.0, 0.0), (7.0, 0.0), (7.0, 7.0), (0.0, 7.0)]
+            .iter()
+            .enumerate()
+        {
+            rotated[k] = perspective_map_exact(&c, u, v);
+        }
+
+        if let Some((data, errors)) =
+            format_info(0, 0, 0, &|x, y| fitness_cell(image, &c, x, y) > 0)
+        {
+            if best.is_none() || errors < best_errors {
+                best = Some((rotated, data));
+                best_errors = errors;
+            }
+        }
+    }
+
+    let (edges, format) = match best {
+        Some(best) => best,
+        None => return false,
+    };
+    let version = (format & 0x1f) as i32;
+    let ver = &RMQR_VERSIONS[version as usize];
+    let (w, h) = (f64::from(ver.width), f64::from(ver.height));
+
+    // Estimate the centre of the sub-finder pattern by extending the
+    // edges of the finder pattern, and the area of a cell
+    let (x0, y0) = edges[0];
+    let hvec = ((edges[1].0 - x0) / 7.0, (edges[1].1 - y0) / 7.0);
+    let vvec = ((edges[3].0 - x0) / 7.0, (edges[3].1 - y0) / 7.0);
+    use crate::math::RoundToNearestFavorEven as _;
+    let mut b = Point {
+        x: (x0 + (w - 2.5) * hvec.0 + (h - 2.5) * vvec.0).round_to_nearest_favor_even() as i32,
+        y: (y0 + (w - 2.5) * hvec.1 + (h - 2.5) * vvec.1).round_to_nearest_favor_even() as i32,
+    };
+    let size_estimate = (hvec.0 * vvec.1 - hvec.1 * vvec.0)
+        .fabs()
+        .round_to_nearest_favor_even() as i32;
+
+    // Spiral outwards from the estimate point, trying each region roughly
+    // the size of a cell as the centre of the sub-finder pattern. The
+    // error of the estimate grows with the width of the code.
+    let radius = 10 + ver.width / 8;
+    let mut step_size: i32 = 1;
+    let mut dir: usize = 0;
+    let mut tried = -1;
+    let mut grid: Option<([f64; PERSPECTIVE_PARAMS], (f64, f64))> = None;
+
+    'search: while step_size * step_size < size_estimate * radius * radius {
+        const DX_MAP: [i32; 4] = [1, 0, -1, 0];
+        const DY_MAP: [i32; 4] = [0, -1, 0, 1];
+
+        for _ in 0..step_size {
+            let code = region_code(
+                &mut q.image,
+                &mut q.flood_fill_vars,
+                &mut q.regions,
+                b.x,
+                b.y,
+            );
+            if code >= 0 && code != tried {
+                tried = code;
+                let reg = q.regions[code as usize];
+                if reg.capstone < 0
+                    && reg.count >= size_estimate / 2
+                    && reg.count <= size_estimate * 2
+                {
+                    let sub =
+                        region_centroid(&mut q.image, &mut q.flood_fill_vars, &q.regions, code);
+                    let c = setup_rmqr_perspective(&edges, sub, None, ver);
+                    let fitness = fitness_apat(&q.image, &c, ver.width - 3, ver.height - 3);
+                    if f64::from(fitness)
+                        >= MIN_SUB_FINDER_FITNESS * f64::from(APAT_CELLS * FITNESS_SAMPLES)
+                    {
+                        grid = Some((c, sub));
+                        break 'search;
+                    }
+                }
+            }
+            b.x += DX_MAP[dir];
+            b.y += DY_MAP[dir];
+        }
+        dir = (dir + 1) % 4;
+        if dir & 1 == 0 {
+            step_size += 1;
+        }
+    }
+
+    let (mut c, sub) = match grid {
+        Some(grid) => grid,
+        None => return false,
+    };
+
+    let image = &q.image;
+    let max = fitness_rmqr(image, &c, ver).1;
+    let mut fitness = jiggle_rmqr(image, &mut c, ver);
+
+    // Find the outer corner of the top right corner pattern: the pixel
+    // furthest towards the top right of the regions around the top right
+    // cells. Set up a perspective grid using it if it's within a few cells
+    // of where the affine grid puts it.
+    let w = ver.width;
+    let (x1, y1) = perspective_map_exact(&c, f64::from(w), 0.0);
+    let (x2, y2) = perspective_map_exact(&c, f64::from(w - 1), 1.0);
+    let dir = (x1 - x2, y1 - y2);
+    let mut corner: Option<Point> = None;
+    let mut tried = -1;
+
+    for x in w - CORNER_SEARCH..w {
+        for y in 0..CORNER_SEARCH {
+            let p = perspective_map(&c, f64::from(x) + 0.5, f64::from(y) + 0.5);
+            let code = region_code(
+                &mut q.image,
+                &mut q.flood_fill_vars,
+                &mut q.regions,
+                p.x,
+                p.y,
+            );
+            if code < 0 || code == tried || q.regions[code as usize].capstone >= 0 {
+                continue;
+            }
+            tried = code;
+
+            let p = region_extreme(&mut q.image, &mut q.flood_fill_vars, &q.regions, code, dir);
+            let further = |a: Point, b: Point| {
+                f64::from(a.x - b.x) * dir.0 + f64::from(a.y - b.y) * dir.1 > 0.0
+            };
+            corner = match corner {
+                Some(best) if !further(p, best) => Some(best),
+                _ => Some(p),
+            };
+        }
+    }
+
+    if let Some(p) = corner {
+        let corner = (
+            f64::from(p.x) + 0.5 * dir.0.signum(),
+            f64::from(p.y) + 0.5 * dir.1.signum(),
+        );
+        let (dx, dy) = (corner.0 - x1, corner.1 - y1);
+
+        if dx * dx + dy * dy <= f64::from(size_estimate * CORNER_SEARCH * CORNER_SEARCH) {
+            let mut perspective = setup_rmqr_perspective(&edges, sub, Some(corner), ver);
+            let test = jiggle_rmqr(&q.image, &mut perspective, ver);
+            if test > fitness {
+                fitness = test;
+                c = perspective;
+            }
+        }
+    }
+
+    let score = (f64::from(fitness) / f64::from(max)).clamp(0.0, 1.0);
+    obs.event(&Event::RmqrScore {
+        capstone: i as i32,
+        version,
+        score,
+    });
+
+    if score < MIN_RMQR_SCORE.max(q.min_grid_score) {
+        return false;
+    }
+
+    q.rmqr_grids.push(RmqrGrid {
+        cap: i as i32,
+        version,
+        width: ver.width,
+        height: ver.height,
+        c,
+        score,
+    });
+    true
+}
+
+/// Whether capstone I is the finder pattern of an rMQR grid.
+fn rmqr_capstone(q: &Quirc, i: usize) -> bool {
+    q.rmqr_grids.iter().any(|grid| grid.cap == i as i32)
 }
 
 pub fn quirc_identify(q: &mut Quirc) {
-    threshold(q);
+    quirc_identify_observed(q, &mut |_: &Event| ());
+}
+
+/// Like `quirc_identify()`, but reports each step to `obs`.
+pub fn quirc_identify_observed(q: &mut Quirc, obs: &mut dyn Observer) {
+    binarize(q);
 
     for i in 0..q.image.h {
-        finder_scan(q, i);
+        finder_scan(q, obs, i, 0, q.image.w);
     }
 
+    group_capstones(q, obs);
+}
+
+/// Turn the image black and white, as chosen by `q.binarizer`.
+pub(crate) fn binarize(q: &mut Quirc) {
+    match q.binarizer {
+        Binarizer::Adaptive => threshold(q),
+        Binarizer::Otsu => {
+            let limit = otsu_threshold(q.image.pixels) + 1;
+            threshold_global(&mut q.image, limit);
+        }
+        Binarizer::Fixed(limit) => threshold_global(&mut q.image, i32::from(limit)),
+    }
+}
+
+/// Group the capstones found by `finder_scan()` into grids.
+pub(crate) fn group_capstones(q: &mut Quirc, obs: &mut dyn Observer) {
+    if q.capstones.is_empty() {
+        q.identify_errors.push(IdentifyError::NoCapstones);
+    }
+
+    q.candidates.clear();
+    for i in 0..q.capstones.len() {
+        test_grouping(q, obs, i);
+    }
+
+    choose_grids(&mut q.candidates);
+    for i in 0..q.candidates.len() {
+        if q.candidates[i].chosen {
+            let candidate = q.candidates[i];
+            record_qr_grid(q, obs, &candidate);
+        }
+    }
+
+    // Capstones left out may be the finder patterns of rMQR codes
     for i in 0..q.capstones.len() {
-        test_grouping(q, i);
+        if q.capstones[i].qr_grid < 0 {
+            test_rmqr(q, obs, i);
+        }
+    }
+
+    if q.recover_grids {
+        recover_grids(q, obs);
+    }
+
+    // For capstones left out, report why the grid set up from their
+    // nearest neighbours failed
+    let mut reported = -1;
+    for c in &q.candidates {
+        if let Some(error) = c.error {
+            let corner = c.corner as usize;
+            if c.corner != reported && q.capstones[corner].qr_grid < 0 && !rmqr_capstone(q, corner)
+            {
+                q.identify_errors.push(error);
+                reported = c.corner;
+            }
+        }
+    }
+
+    for (i, cap) in q.capstones.iter().enumerate() {
+        if cap.qr_grid < 0 && !rmqr_capstone(q, i) {
+            q.identify_errors
+                .push(IdentifyError::Ungrouped { capstone: i as i32 });
+        }
     }
 }
 
 /// Extract the QR-code specified by the given index.
 pub fn quirc_extract(q: &mut Quirc, index: i32) -> Option<QuircCode> {
-    let qr = &q.grids[index as usize];
+    let mut code = QuircCode::default();
 
-    if index < 0 || index > q.grids.len() as i32 {
-        return None;
+    if quirc_extract_into(q, index, &mut code) {
+        Some(code)
+    } else {
+        None
     }
+}
 
-    let mut code = QuircCode {
-        corners: [
-            perspective_map(&qr.c, 0.0, 0.0),
-            perspective_map(&qr.c, f64::from(qr.grid_size), 0.0),
-            perspective_map(&qr.c, f64::from(qr.grid_size), f64::from(qr.grid_size)),
-            perspective_map(&qr.c, 0.0, f64::from(qr.grid_size)),
-        ],
-        size: qr.grid_size,
-        ..Default::default()
-    };
+/// Extract the QR-code specified by the given index into `code`.
+/// Returns false if there's no such code.
+pub(crate) fn quirc_extract_into(q: &Quirc, index: i32, code: &mut QuircCode) -> bool {
+    if index < 0 || index >= q.grids.len() as i32 {
+        return false;
+    }
+
+    extract_grid(&q.image, &q.grids[index as usize], code);
+    true
+}
+
+/// Read the cells of `qr` from `image` into `code`.
+pub(crate) fn extract_grid(image: &Image, qr: &Grid, code: &mut QuircCode) {
+    code.corners = [
+        perspective_map(&qr.c, 0.0, 0.0),
+        perspective_map(&qr.c, f64::from(qr.grid_size), 0.0),
+        perspective_map(&qr.c, f64::from(qr.grid_size), f64::from(qr.grid_size)),
+        perspective_map(&qr.c, 0.0, f64::from(qr.grid_size)),
+    ];
+    code.size = qr.grid_size;
+    code.cell_bitmap.iter_mut().for_each(|b| *b = 0);
 
     let mut i: i32 = 0;
     for y in 0..qr.grid_size {
         for x in 0..qr.grid_size {
-            if read_cell(q, index, x, y) == Cell::Black {
+            if read_cell(image, qr, x, y) == Cell::Black {
                 code.cell_bitmap[(i >> 3) as usize] |= 1 << (i & 7);
             }
             i += 1;
         }
     }
-
-    Some(code)
 }
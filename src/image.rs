@@ -0,0 +1,158 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Integration with the `image` crate, enabled by the `image` feature.
+//!
+//! The functions here accept anything implementing `GenericImageView`
+//! with 8-bit channels, which includes `GrayImage`, `RgbImage`,
+//! `DynamicImage` and sub-images of those.
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use image::{GenericImageView, GrayImage, ImageBuffer, Luma, Pixel, RgbImage};
+
+use crate::identify::*;
+use crate::quirc::*;
+use crate::render::Canvas;
+
+/// Convert an image to the grayscale pixels expected by `Image::new()`.
+pub fn to_gray<I>(img: &I) -> GrayImage
+where
+    I: GenericImageView,
+    I::Pixel: Pixel<Subpixel = u8>,
+{
+    let (width, height) = img.dimensions();
+    let mut gray = GrayImage::new(width, height);
+
+    for (x, y, pixel) in img.pixels() {
+        gray.put_pixel(x, y, pixel.to_luma());
+    }
+
+    gray
+}
+
+/// Find and decode all the QR-codes in an image. Each code found is
+/// returned along with the result of decoding it.
+pub fn quirc_decode_image<I>(img: &I) -> Vec<(Code, Result<Data>)>
+where
+    I: GenericImageView,
+    I::Pixel: Pixel<Subpixel = u8>,
+{
+    let mut gray = to_gray(img);
+    let (width, height) = gray.dimensions();
+
+    let mut q = Quirc::new(Image::new(width, height, &mut gray));
+    quirc_identify(&mut q);
+
+    (0..quirc_count(&q))
+        .filter_map(|i| quirc_extract(&mut q, i))
+        .filter_map(|code| Code::try_from(&code).ok())
+        .map(|code| {
+            let data = code.decode();
+            (code, data)
+        })
+        .collect()
+}
+
+/// Draw the cells of a code in black and white, `scale` pixels per cell,
+/// inside a quiet zone of `quiet_zone` cells (the standard asks for 4).
+///
+/// The corners of the code are ignored: the result is always upright. A
+/// code with an invalid `size` is drawn as an empty quiet zone.
+pub fn quirc_render_code(code: &QuircCode, scale: u32, quiet_zone: u32) -> GrayImage {
+    let code = Code::try_from(code).unwrap_or_default();
+    let size = code.size as u32;
+    let side = (size + 2 * quiet_zone) * scale;
+
+    ImageBuffer::from_fn(side, side, |x, y| {
+        let (u, v) = (x / scale, y / scale);
+        let inside = (quiet_zone..quiet_zone + size).contains(&u)
+            && (quiet_zone..quiet_zone + size).contains(&v);
+
+        if inside && code.cell((u - quiet_zone) as i32, (v - quiet_zone) as i32) {
+            Luma([0])
+        } else {
+            Luma([0xff])
+        }
+    })
+}
+
+impl From<Canvas> for RgbImage {
+    fn from(canvas: Canvas) -> Self {
+        let (width, height) = (canvas.width(), canvas.height());
+        RgbImage::from_raw(width, height, canvas.into_pixels()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(name: &str) -> image::DynamicImage {
+        let path = format!("{}/tests/images/{}", env!("CARGO_MANIFEST_DIR"), name);
+        image::open(path).unwrap()
+    }
+
+    fn payloads<I>(img: &I) -> Vec<Vec<u8>>
+    where
+        I: GenericImageView,
+        I::Pixel: Pixel<Subpixel = u8>,
+    {
+        quirc_decode_image(img)
+            .into_iter()
+            .map(|(_, data)| data.unwrap().payload)
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_image() {
+        let img = open("Moe_Epsilon_QR_code.png");
+        let expected = vec![b"http://en.wikipedia.org/wiki/User:Moe_Epsilon".to_vec()];
+
+        assert_eq!(payloads(&img), expected);
+        assert_eq!(payloads(&img.to_rgb8()), expected);
+        assert_eq!(payloads(&img.to_luma8()), expected);
+    }
+
+    #[test]
+    fn test_render_code() {
+        let img = open("Qr-code-ver-10.png");
+        let (code, data) = quirc_decode_image(&img).pop().unwrap();
+
+        let rendered = quirc_render_code(&QuircCode::try_from(&code).unwrap(), 3, 4);
+        assert_eq!(rendered.dimensions(), (65 * 3, 65 * 3));
+        assert_eq!(rendered.get_pixel(0, 0), &Luma([0xff]));
+        assert_eq!(rendered.get_pixel(12, 12), &Luma([0]));
+
+        let (again, again_data) = quirc_decode_image(&rendered).pop().unwrap();
+        assert_eq!(again.cell_bitmap, code.cell_bitmap);
+        assert_eq!(again_data.unwrap().payload, data.unwrap().payload);
+    }
+
+    #[test]
+    fn test_render_invalid_code() {
+        for &size in &[-1, 178] {
+            let code = QuircCode {
+                size,
+                ..Default::default()
+            };
+            let rendered = quirc_render_code(&code, 2, 4);
+            assert_eq!(rendered.dimensions(), (16, 16));
+            assert!(rendered.pixels().all(|p| p == &Luma([0xff])));
+        }
+    }
+}
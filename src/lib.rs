@@ -1,7 +1,26 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![allow(non_snake_case)]
 
+extern crate alloc;
+
+#[cfg(feature = "capi")]
+pub mod capi;
 pub mod decode;
+pub mod emv;
+pub mod fusion;
 pub mod identify;
+#[cfg(feature = "image")]
+pub mod image;
 mod math;
+pub mod observer;
+#[cfg(feature = "std")]
+pub mod quality;
 pub mod quirc;
+pub mod render;
+pub mod rmqr;
+pub mod rs;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod text;
+pub mod track;
 mod version_db;
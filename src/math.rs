@@ -1,18 +1,36 @@
-use std::{f32, f64};
+use core::{f32, f64};
 
 pub trait RoundToNearestFavorEven {
     // TODO Remove when Rust grows this rounding mode.
     fn round_to_nearest_favor_even(self) -> Self;
 }
 
+/// `abs()` and `copysign()` live in `std`, so these work on the bits
+/// directly.
+pub trait Sign {
+    fn fabs(self) -> Self;
+    fn fcopysign(self, sign: Self) -> Self;
+}
+
 macro_rules! impl_round_to_nearest_favor_even {
-    ($t:tt) => {
+    ($t:tt, $bits:ty) => {
+        impl Sign for $t {
+            fn fabs(self) -> Self {
+                $t::from_bits(self.to_bits() & !(1 << (<$bits>::BITS - 1)))
+            }
+
+            fn fcopysign(self, sign: Self) -> Self {
+                let mask: $bits = 1 << (<$bits>::BITS - 1);
+                $t::from_bits((self.to_bits() & !mask) | (sign.to_bits() & mask))
+            }
+        }
+
         impl RoundToNearestFavorEven for $t {
             fn round_to_nearest_favor_even(self) -> Self {
                 let k = 1.0 / $t::EPSILON;
-                let a = self.abs();
+                let a = self.fabs();
                 if a < k {
-                    ((a + k) - k).copysign(self)
+                    ((a + k) - k).fcopysign(self)
                 } else {
                     self
                 }
@@ -21,8 +39,114 @@ macro_rules! impl_round_to_nearest_favor_even {
     };
 }
 
-impl_round_to_nearest_favor_even!(f32);
-impl_round_to_nearest_favor_even!(f64);
+impl_round_to_nearest_favor_even!(f32, u32);
+impl_round_to_nearest_favor_even!(f64, u64);
+
+/// Integer Bresenham line from `start` to `end`, both inclusive.
+///
+/// The points are visited in the same order as by the `line_drawing`
+/// crate, which isn't available without `std`.
+pub struct Bresenham {
+    point: (i32, i32),
+    end_x: i32,
+    delta_x: i32,
+    delta_y: i32,
+    error: i32,
+    octant: u8,
+}
+
+impl Bresenham {
+    pub fn new(start: (i32, i32), end: (i32, i32)) -> Self {
+        let octant = Self::octant(start, end);
+        let start = Self::to_octant(octant, start);
+        let end = Self::to_octant(octant, end);
+
+        let delta_x = end.0 - start.0;
+        let delta_y = end.1 - start.1;
+
+        Bresenham {
+            point: start,
+            end_x: end.0,
+            delta_x,
+            delta_y,
+            error: delta_y - delta_x,
+            octant,
+        }
+    }
+
+    fn octant(start: (i32, i32), end: (i32, i32)) -> u8 {
+        let mut octant = 0;
+        let mut dx = end.0 - start.0;
+        let mut dy = end.1 - start.1;
+
+        if dy < 0 {
+            dx = -dx;
+            dy = -dy;
+            octant += 4;
+        }
+
+        if dx < 0 {
+            let tmp = dx;
+            dx = dy;
+            dy = -tmp;
+            octant += 2;
+        }
+
+        if dx < dy {
+            octant += 1;
+        }
+
+        octant
+    }
+
+    fn to_octant(octant: u8, p: (i32, i32)) -> (i32, i32) {
+        match octant {
+            0 => (p.0, p.1),
+            1 => (p.1, p.0),
+            2 => (p.1, -p.0),
+            3 => (-p.0, p.1),
+            4 => (-p.0, -p.1),
+            5 => (-p.1, -p.0),
+            6 => (-p.1, p.0),
+            _ => (p.0, -p.1),
+        }
+    }
+
+    fn from_octant(octant: u8, p: (i32, i32)) -> (i32, i32) {
+        match octant {
+            0 => (p.0, p.1),
+            1 => (p.1, p.0),
+            2 => (-p.1, p.0),
+            3 => (-p.0, p.1),
+            4 => (-p.0, -p.1),
+            5 => (-p.1, -p.0),
+            6 => (p.1, -p.0),
+            _ => (p.0, -p.1),
+        }
+    }
+}
+
+impl Iterator for Bresenham {
+    type Item = (i32, i32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.point.0 > self.end_x {
+            return None;
+        }
+
+        let point = Self::from_octant(self.octant, self.point);
+
+        if self.error >= 0 {
+            self.point.1 += 1;
+            self.error -= self.delta_x;
+        }
+
+        self.point.0 += 1;
+        self.error += self.delta_y;
+
+        Some(point)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -75,6 +199,19 @@ mod tests {
         assert_eq!((-3.6).round_to_nearest_favor_even(), -4f64);
     }
 
+    #[test]
+    fn test_bresenham() {
+        for &(x0, y0) in &[(0, 0), (5, 3), (-2, 7)] {
+            for x1 in -6..=6 {
+                for y1 in -6..=6 {
+                    let ours: Vec<_> = Bresenham::new((x0, y0), (x1, y1)).collect();
+                    let theirs: Vec<_> = line_drawing::Bresenham::new((x0, y0), (x1, y1)).collect();
+                    assert_eq!(ours, theirs);
+                }
+            }
+        }
+    }
+
     #[test]
     #[ignore] // Ignored because it's a long running test
     fn test_round_to_nearest_favor_even_exhaustive() {
@@ -0,0 +1,160 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Tracing of the decisions made by `quirc_identify()`.
+//!
+//! Pass an `Observer` to `quirc_identify_observed()` to find out why a
+//! code wasn't found. Any `FnMut(&Event)` closure is an observer:
+//!
+//! ```
+//! use quirc_rs::identify::quirc_identify_observed;
+//! use quirc_rs::observer::Event;
+//! use quirc_rs::quirc::{Image, Quirc};
+//!
+//! let mut pixels = vec![0xff; 64 * 48];
+//! let mut q = Quirc::new(Image::new(64, 48, &mut pixels));
+//! let mut rejected = 0;
+//! quirc_identify_observed(&mut q, &mut |event: &Event| {
+//!     if let Event::CapstoneRejected { .. } = event {
+//!         rejected += 1;
+//!     }
+//! });
+//! ```
+
+/// Why a finder run wasn't recorded as a capstone.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CapstoneRejection {
+    /// The run lies on a white pixel, or there are too many regions
+    NoRegion,
+
+    /// The left and right ends of the run belong to different regions
+    RingSplit,
+
+    /// The ring and the stone are the same region
+    RingIsStone,
+
+    /// The ring or the stone is part of a capstone already
+    AlreadyDetected,
+
+    /// The area of the stone as a percentage of the area of the ring is
+    /// outside of 10-70 (ideally it's 37.5)
+    Ratio(i32),
+
+    /// The line through the centre of the stone in direction (dx, dy)
+    /// doesn't cross the ring and the stone in the 1:1:3:1:1 ratio
+    CrossCheck { dx: i32, dy: i32 },
+
+    /// Along the line through the centre of the stone in direction
+    /// (dx, dy), the stone is off the middle of the ring by more than
+    /// half a module
+    NotConcentric { dx: i32, dy: i32 },
+
+    /// `MAX_CAPSTONES` have been recorded already
+    TooManyCapstones,
+}
+
+/// A step taken by `quirc_identify()`, in the order they happen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A row of runs in the 1:1:3:1:1 ratio of a finder pattern, the last
+    /// of which ends just before (x, y).
+    FinderRun { x: i32, y: i32, runs: [i32; 5] },
+
+    /// The finder run ending at (x, y) was recorded as a capstone.
+    CapstoneAccepted { x: i32, y: i32, capstone: i32 },
+
+    CapstoneRejected {
+        x: i32,
+        y: i32,
+        reason: CapstoneRejection,
+    },
+
+    /// A pair of capstones was tested as the horizontal and vertical
+    /// neighbours of `capstone`. Pairs scoring above 2.5 are dropped, and
+    /// grids are set up from the nearest few of the others.
+    NeighbourScore {
+        capstone: i32,
+        horizontal: i32,
+        vertical: i32,
+        score: f64,
+    },
+
+    /// `capstone` had `horizontal` and `vertical` candidate neighbours,
+    /// but no acceptable pair of them.
+    NotGrouped {
+        capstone: i32,
+        horizontal: i32,
+        vertical: i32,
+    },
+
+    /// Capstones were grouped, and a grid will be set up from them to
+    /// compete with other groupings for the capstones. `caps` are the
+    /// horizontal neighbour, the capstone itself and the vertical
+    /// neighbour; the other events list them clockwise.
+    Grouped { caps: [i32; 3], score: f64 },
+
+    /// Transitions counted along the timing patterns of a grid, -1 if a
+    /// scan left the image. The grid size is estimated from the larger
+    /// count.
+    TimingScan {
+        caps: [i32; 3],
+        hscan: i32,
+        vscan: i32,
+    },
+
+    /// Fitness of the grid perspective before jiggling (pass 0) and after
+    /// each pass of adjustments, out of a possible `max`.
+    JiggleFitness {
+        caps: [i32; 3],
+        pass: i32,
+        fitness: i32,
+        max: i32,
+    },
+
+    /// The grid was scored, see `Grid::score`. Grids scoring below
+    /// `Quirc::min_grid_score` are dropped.
+    GridScore { caps: [i32; 3], score: f64 },
+
+    /// Of the grids sharing capstones, the ones with the greatest total
+    /// score won, and this one was recorded as `grid`.
+    Recorded { caps: [i32; 3], grid: i32 },
+
+    /// A finder pattern was missing, and `capstone` was put where it fits
+    /// the other two of `caps` and the image best.
+    CapstoneInferred { capstone: i32, caps: [i32; 3] },
+
+    /// The format information next to ungrouped `capstone` read as an rMQR
+    /// code of `version`, and the grid set up from it and the sub-finder
+    /// pattern was scored, see `RmqrGrid::score`. rMQR grids scoring below
+    /// 0.5 or `Quirc::min_grid_score` are dropped.
+    RmqrScore {
+        capstone: i32,
+        version: i32,
+        score: f64,
+    },
+}
+
+/// Receives the events of `quirc_identify_observed()`.
+pub trait Observer {
+    fn event(&mut self, event: &Event);
+}
+
+impl<F: FnMut(&Event)> Observer for F {
+    fn event(&mut self, event: &Event) {
+        self(event)
+    }
+}
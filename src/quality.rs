@@ -0,0 +1,370 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Print quality metrics loosely following ISO/IEC 15415.
+//!
+//! The measurements are taken from the sampled grid rather than from a
+//! calibrated scan, so the grades are indicative only.
+
+use std::fmt;
+
+use crate::decode::*;
+use crate::identify::*;
+use crate::quirc::*;
+
+/// This enum describes the errors which may occur while measuring the
+/// print quality of a symbol.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QualityError {
+    /// The grayscale image doesn't have one byte per pixel of the image
+    /// the symbol was identified in.
+    ImageSize { expected: usize, found: usize },
+    /// The symbol couldn't be extracted or decoded.
+    Decode(DecodeError),
+}
+
+impl From<DecodeError> for QualityError {
+    fn from(err: DecodeError) -> QualityError {
+        QualityError::Decode(err)
+    }
+}
+
+impl fmt::Display for QualityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QualityError::ImageSize { expected, found } => write!(
+                f,
+                "Grayscale image has {} bytes, expected {}",
+                found, expected
+            ),
+            QualityError::Decode(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for QualityError {}
+
+/// Letter grade of a single quality parameter, from worst to best.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Grade {
+    F,
+    D,
+    C,
+    B,
+    A,
+}
+
+impl Grade {
+    /// Grade a value where higher is better. `limits` are the lower
+    /// bounds for A, B, C and D.
+    fn at_least(value: f64, limits: [f64; 4]) -> Grade {
+        const GRADES: [Grade; 4] = [Grade::A, Grade::B, Grade::C, Grade::D];
+        GRADES
+            .iter()
+            .zip(limits.iter())
+            .find(|(_, &limit)| value >= limit)
+            .map_or(Grade::F, |(&grade, _)| grade)
+    }
+
+    /// Grade a value where lower is better. `limits` are the upper
+    /// bounds for A, B, C and D.
+    fn at_most(value: f64, limits: [f64; 4]) -> Grade {
+        Grade::at_least(-value, [-limits[0], -limits[1], -limits[2], -limits[3]])
+    }
+}
+
+impl fmt::Display for Grade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let letter = match self {
+            Grade::A => "A",
+            Grade::B => "B",
+            Grade::C => "C",
+            Grade::D => "D",
+            Grade::F => "F",
+        };
+        f.write_str(letter)
+    }
+}
+
+/// Quality measurements of a single decoded symbol.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quality {
+    /// Error correction statistics of each RS block
+    pub blocks: Vec<BlockEcc>,
+
+    /// Fraction of error correction capacity left unused by the worst
+    /// block (1.0 = no errors)
+    pub unused_ecc: f64,
+
+    /// Estimated fraction of damaged cells in the finder, timing and
+    /// alignment patterns
+    pub fixed_pattern_damage: f64,
+
+    /// Difference between the lightest and darkest cell, as a fraction
+    /// of the full intensity range
+    pub symbol_contrast: f64,
+
+    /// Lowest cell modulation relative to the symbol contrast
+    pub modulation: f64,
+
+    /// Relative difference between the horizontal and vertical cell
+    /// pitch
+    pub axial_nonuniformity: f64,
+
+    /// Largest deviation of a grid intersection from its ideal
+    /// position, in cells
+    pub grid_nonuniformity: f64,
+}
+
+/// Letter grades of each quality parameter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Grades {
+    pub unused_ecc: Grade,
+    pub fixed_pattern_damage: Grade,
+    pub symbol_contrast: Grade,
+    pub modulation: Grade,
+    pub axial_nonuniformity: Grade,
+    pub grid_nonuniformity: Grade,
+
+    /// The lowest of the grades above
+    pub overall: Grade,
+}
+
+impl Quality {
+    pub fn grades(&self) -> Grades {
+        let unused_ecc = Grade::at_least(self.unused_ecc, [0.62, 0.50, 0.37, 0.25]);
+        let fixed_pattern_damage =
+            Grade::at_most(self.fixed_pattern_damage, [0.02, 0.05, 0.10, 0.15]);
+        let symbol_contrast = Grade::at_least(self.symbol_contrast, [0.70, 0.55, 0.40, 0.20]);
+        let modulation = Grade::at_least(self.modulation, [0.50, 0.40, 0.30, 0.20]);
+        let axial_nonuniformity =
+            Grade::at_most(self.axial_nonuniformity, [0.06, 0.08, 0.10, 0.12]);
+        let grid_nonuniformity = Grade::at_most(self.grid_nonuniformity, [0.38, 0.50, 0.63, 0.75]);
+
+        let overall = *[
+            unused_ecc,
+            fixed_pattern_damage,
+            symbol_contrast,
+            modulation,
+            axial_nonuniformity,
+            grid_nonuniformity,
+        ]
+        .iter()
+        .min()
+        .unwrap();
+
+        Grades {
+            unused_ecc,
+            fixed_pattern_damage,
+            symbol_contrast,
+            modulation,
+            axial_nonuniformity,
+            grid_nonuniformity,
+            overall,
+        }
+    }
+}
+
+/// Number of misdecode protection codewords, which can't be used for
+/// error correction (ISO/IEC 18004, table 9). Of Model 1 codes, only
+/// version 1 has the same RS blocks as the Model 2 codes listed, and is
+/// given the same.
+fn misdecode_protection(version: i32, ecc_level: i32, model: i32) -> i32 {
+    use crate::quirc::consts::*;
+
+    match (model, version, ecc_level) {
+        (_, 1, ECC_LEVEL_L) => 3,
+        (_, 1, ECC_LEVEL_M) => 2,
+        (_, 1, _) => 1,
+        (MODEL_2, 2, ECC_LEVEL_L) => 2,
+        (MODEL_2, 3, ECC_LEVEL_L) => 1,
+        _ => 0,
+    }
+}
+
+fn unused_ecc(data: &QuircData, blocks: &[BlockEcc], model: i32) -> f64 {
+    let p = misdecode_protection(data.version, data.ecc_level, model);
+
+    blocks
+        .iter()
+        .map(|b| 1.0 - f64::from(2 * b.corrected) / f64::from(b.parity - p))
+        .fold(1.0, f64::min)
+        .max(0.0)
+}
+
+/// Sample the original grayscale image at the centre of each cell.
+fn cell_samples(gray: &[u8], w: i32, h: i32, grid: &Grid) -> Vec<u8> {
+    let mut samples = Vec::with_capacity((grid.grid_size * grid.grid_size) as usize);
+
+    for y in 0..grid.grid_size {
+        for x in 0..grid.grid_size {
+            let p = perspective_map(&grid.c, f64::from(x) + 0.5, f64::from(y) + 0.5);
+            if p.x >= 0 && p.y >= 0 && p.x < w && p.y < h {
+                samples.push(gray[(p.y * w + p.x) as usize]);
+            }
+        }
+    }
+
+    samples
+}
+
+fn contrast_and_modulation(samples: &[u8]) -> (f64, f64) {
+    let r_max = samples.iter().copied().max().unwrap_or(0);
+    let r_min = samples.iter().copied().min().unwrap_or(0);
+    let sc = f64::from(r_max) - f64::from(r_min);
+
+    if sc <= 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let gt = (f64::from(r_max) + f64::from(r_min)) / 2.0;
+    let modulation = samples
+        .iter()
+        .map(|&s| 2.0 * (f64::from(s) - gt).abs() / sc)
+        .fold(1.0, f64::min);
+
+    (sc / 255.0, modulation)
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+/// Returns the axial and the grid non-uniformity of the perspective
+/// transform.
+fn nonuniformity(grid: &Grid) -> (f64, f64) {
+    let size = f64::from(grid.grid_size);
+    let map = |u: f64, v: f64| perspective_map_exact(&grid.c, u, v);
+
+    let p0 = map(0.0, 0.0);
+    let p1 = map(size, 0.0);
+    let p2 = map(size, size);
+    let p3 = map(0.0, size);
+
+    let x_avg = (distance(p0, p1) + distance(p3, p2)) / (2.0 * size);
+    let y_avg = (distance(p0, p3) + distance(p1, p2)) / (2.0 * size);
+    let pitch = (x_avg + y_avg) / 2.0;
+    let axial = (x_avg - y_avg).abs() / pitch;
+
+    // Compare each intersection against the affine grid spanned by the
+    // top-left, top-right and bottom-left corners.
+    let mut worst: f64 = 0.0;
+    for v in 0..=grid.grid_size {
+        for u in 0..=grid.grid_size {
+            let (fu, fv) = (f64::from(u) / size, f64::from(v) / size);
+            let ideal = (
+                p0.0 + (p1.0 - p0.0) * fu + (p3.0 - p0.0) * fv,
+                p0.1 + (p1.1 - p0.1) * fu + (p3.1 - p0.1) * fv,
+            );
+            worst = worst.max(distance(map(f64::from(u), f64::from(v)), ideal));
+        }
+    }
+
+    (axial, worst / pitch)
+}
+
+/// Measure the print quality of the QR-code specified by the given index.
+///
+/// `gray` must hold the original grayscale image, as `quirc_identify()`
+/// binarizes the image in place.
+pub fn quirc_quality(
+    q: &mut Quirc,
+    index: i32,
+    gray: &[u8],
+) -> core::result::Result<Quality, QualityError> {
+    let (w, h) = (q.image.width(), q.image.height());
+    if (w * h) as usize != gray.len() {
+        return Err(QualityError::ImageSize {
+            expected: (w * h) as usize,
+            found: gray.len(),
+        });
+    }
+
+    let code = quirc_extract(q, index).ok_or(DecodeError::InvalidGridSize)?;
+    let (data, blocks, model) = decode_ecc_model(&code)?;
+
+    let mut grid = q.grids[index as usize];
+    let fitness = f64::from(fitness_all(&q.image, &mut grid)) / f64::from(fitness_max(&grid));
+
+    let samples = cell_samples(gray, w, h, &grid);
+    let (symbol_contrast, modulation) = contrast_and_modulation(&samples);
+    let (axial_nonuniformity, grid_nonuniformity) = nonuniformity(&grid);
+
+    Ok(Quality {
+        unused_ecc: unused_ecc(&data, &blocks, model),
+        blocks,
+        fixed_pattern_damage: ((1.0 - fitness) / 2.0).max(0.0),
+        symbol_contrast,
+        modulation,
+        axial_nonuniformity,
+        grid_nonuniformity,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirc::consts::*;
+
+    #[test]
+    fn test_grades() {
+        let quality = Quality {
+            blocks: vec![],
+            unused_ecc: 1.0,
+            fixed_pattern_damage: 0.0,
+            symbol_contrast: 0.6,
+            modulation: 0.1,
+            axial_nonuniformity: 0.07,
+            grid_nonuniformity: 0.0,
+        };
+        let grades = quality.grades();
+
+        assert_eq!(grades.unused_ecc, Grade::A);
+        assert_eq!(grades.symbol_contrast, Grade::B);
+        assert_eq!(grades.modulation, Grade::F);
+        assert_eq!(grades.axial_nonuniformity, Grade::B);
+        assert_eq!(grades.overall, Grade::F);
+    }
+
+    #[test]
+    fn test_unused_ecc() {
+        let data = QuircData {
+            version: 5,
+            ..Default::default()
+        };
+        let blocks = [
+            BlockEcc {
+                corrected: 0,
+                parity: 24,
+            },
+            BlockEcc {
+                corrected: 3,
+                parity: 24,
+            },
+        ];
+
+        assert!((unused_ecc(&data, &blocks, MODEL_2) - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_misdecode_protection() {
+        assert_eq!(misdecode_protection(1, ECC_LEVEL_L, MODEL_2), 3);
+        assert_eq!(misdecode_protection(1, ECC_LEVEL_L, MODEL_1), 3);
+        assert_eq!(misdecode_protection(2, ECC_LEVEL_L, MODEL_2), 2);
+        assert_eq!(misdecode_protection(2, ECC_LEVEL_L, MODEL_1), 0);
+        assert_eq!(misdecode_protection(3, ECC_LEVEL_L, MODEL_1), 0);
+    }
+}
@@ -14,7 +14,19 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-use std::ops::{Index, IndexMut};
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::ops::{Index, IndexMut};
+
+use crate::decode::{quirc_decode_segments, Scratch, Segment};
+use crate::identify::{flood_fill_depth, Candidate, FloodFillVars, MAX_CANDIDATES};
+use crate::version_db::QUIRC_MAX_GRID_SIZE;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// This structure is used to return information about detected QR codes
 /// in the input image.
@@ -97,7 +109,172 @@ impl Default for QuircData {
     }
 }
 
+/// Heap-allocated counterpart of `QuircCode`, holding only the
+/// `size * size` bits of the cell bitmap.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Code {
+    /// The four corners of the QR-code, from top left, clockwise
+    pub corners: [Point; 4],
+
+    /// The number of cells across in the QR-code
+    pub size: i32,
+
+    /// Cell bitmap, laid out as in `QuircCode::cell_bitmap`
+    pub cell_bitmap: Vec<u8>,
+}
+
+impl Code {
+    /// Returns true if the cell at (x, y) is black, and false if it's
+    /// outside the code.
+    pub fn cell(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.size || y >= self.size {
+            return false;
+        }
+
+        let i = (y * self.size + x) as usize;
+        matches!(self.cell_bitmap.get(i >> 3), Some(&byte) if byte & (1 << (i & 7)) != 0)
+    }
+
+    /// Decode the QR-code, returning the payload data.
+    pub fn decode(&self) -> Result<Data> {
+        quirc_decode_segments(&QuircCode::try_from(self)?).map(|(data, segments)| Data {
+            segments,
+            ..Data::from(&data)
+        })
+    }
+}
+
+impl TryFrom<&QuircCode> for Code {
+    type Error = DecodeError;
+
+    /// Fails with `InvalidGridSize` if `size` is negative or larger than
+    /// `QUIRC_MAX_GRID_SIZE`.
+    fn try_from(code: &QuircCode) -> Result<Self> {
+        if code.size < 0 || code.size as usize > QUIRC_MAX_GRID_SIZE {
+            return Err(DecodeError::InvalidGridSize);
+        }
+
+        let len = ((code.size * code.size + 7) / 8) as usize;
+        Ok(Code {
+            corners: code.corners,
+            size: code.size,
+            cell_bitmap: code.cell_bitmap[..len].to_vec(),
+        })
+    }
+}
+
+impl TryFrom<&Code> for QuircCode {
+    type Error = DecodeError;
+
+    /// Fails with `InvalidGridSize` if the bitmap doesn't fit in
+    /// `MAX_BITMAP` bytes.
+    fn try_from(code: &Code) -> Result<Self> {
+        if code.cell_bitmap.len() > consts::MAX_BITMAP {
+            return Err(DecodeError::InvalidGridSize);
+        }
+
+        let mut c = QuircCode {
+            corners: code.corners,
+            size: code.size,
+            ..Default::default()
+        };
+        c.cell_bitmap[..code.cell_bitmap.len()].copy_from_slice(&code.cell_bitmap);
+        Ok(c)
+    }
+}
+
+/// The cells of an rMQR code, laid out as in `QuircCode` but `width`
+/// cells across and `height` cells down.
+#[derive(Copy)]
+#[repr(C)]
+pub struct RmqrCode {
+    /// The four corners of the code, from top left, clockwise
+    pub corners: [Point; 4],
+    pub width: i32,
+    pub height: i32,
+
+    /// Cell bitmap: the cell at (x, y) is black if bit `y * width + x`
+    /// is set
+    pub cell_bitmap: [u8; consts::RMQR_MAX_BITMAP],
+}
+
+impl Clone for RmqrCode {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl Default for RmqrCode {
+    fn default() -> Self {
+        RmqrCode {
+            corners: [Default::default(); 4],
+            width: 0,
+            height: 0,
+            cell_bitmap: [0; consts::RMQR_MAX_BITMAP],
+        }
+    }
+}
+
+/// Heap-allocated counterpart of `QuircData`, holding only the actual
+/// payload.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Data {
+    pub version: i32,
+    pub ecc_level: i32,
+    pub mask: i32,
+
+    /// The highest-valued data type found in the QR code
+    pub data_type: i32,
+
+    /// Data payload, encoded as in `QuircData::payload` but without the
+    /// nul terminator
+    pub payload: Vec<u8>,
+
+    /// ECI assignment number
+    pub eci: u32,
+
+    /// The segments the payload was assembled from. This is empty if the
+    /// data was converted from a `QuircData`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub segments: Vec<Segment>,
+}
+
+impl From<&QuircData> for Data {
+    fn from(data: &QuircData) -> Self {
+        Data {
+            version: data.version,
+            ecc_level: data.ecc_level,
+            mask: data.mask,
+            data_type: data.data_type,
+            payload: data.payload[..data.payload_len as usize].to_vec(),
+            eci: data.eci,
+            segments: Vec::new(),
+        }
+    }
+}
+
+impl From<&Data> for QuircData {
+    /// The payload is truncated to fit in `MAX_PAYLOAD` bytes along with
+    /// its nul terminator.
+    fn from(data: &Data) -> Self {
+        let len = data.payload.len().min(consts::MAX_PAYLOAD - 1);
+        let mut d = QuircData {
+            version: data.version,
+            ecc_level: data.ecc_level,
+            mask: data.mask,
+            data_type: data.data_type,
+            payload_len: len as i32,
+            eci: data.eci,
+            ..Default::default()
+        };
+        d.payload[..len].copy_from_slice(&data.payload[..len]);
+        d
+    }
+}
+
 /// This structure describes a location in the input image buffer.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Debug, Eq, PartialEq)]
 #[repr(C)]
 pub struct Point {
@@ -117,6 +294,30 @@ impl Default for Point {
     }
 }
 
+/// Method used by `quirc_identify()` to turn the grayscale image into
+/// black and white.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Binarizer {
+    /// Compare each pixel against a running average of its
+    /// neighbourhood. This copes with uneven lighting.
+    Adaptive,
+
+    /// Use a single threshold for the whole image, chosen from its
+    /// histogram with Otsu's method.
+    Otsu,
+
+    /// Pixels darker than the given value are black.
+    Fixed(u8),
+}
+
+// #[default] on variants needs Rust 1.62
+#[allow(clippy::derivable_impls)]
+impl Default for Binarizer {
+    fn default() -> Self {
+        Binarizer::Adaptive
+    }
+}
+
 #[derive(Copy)]
 #[repr(C)]
 pub struct Region {
@@ -141,15 +342,24 @@ impl Default for Region {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Debug, PartialEq)]
 #[repr(C)]
 pub struct Capstone {
+    /// Regions of the finder pattern, -1 if it's covered or damaged and
+    /// the capstone was inferred from two others
     pub ring: i32,
     pub stone: i32,
     pub corners: [Point; 4],
     pub center: Point,
     pub c: [f64; consts::PERSPECTIVE_PARAMS],
     pub qr_grid: i32,
+
+    /// How closely the finder pattern matches an ideal one, from 0 to 1:
+    /// the runs across it against 1:1:3:1:1, and the area of the stone
+    /// against 37.5% of the ring's
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub score: f64,
 }
 
 impl Clone for Capstone {
@@ -167,10 +377,12 @@ impl Default for Capstone {
             center: Default::default(),
             c: [0f64; consts::PERSPECTIVE_PARAMS],
             qr_grid: 0,
+            score: 0.0,
         }
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Debug)]
 #[repr(C)]
 pub struct Grid {
@@ -189,6 +401,21 @@ pub struct Grid {
     /// Grid size and perspective transform
     pub grid_size: i32,
     pub c: [f64; consts::PERSPECTIVE_PARAMS],
+
+    /// How well the grid fits the image, from 0 to 1: the mean of
+    /// `fitness_all()` as a fraction of the best possible, and of how
+    /// closely the sizes read from the two timing patterns agree
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub score: f64,
+
+    /// `MODEL_1` if the code has no alignment patterns, or `MODEL_2`
+    #[cfg_attr(feature = "serde", serde(default = "model_2"))]
+    pub model: i32,
+}
+
+#[cfg(feature = "serde")]
+fn model_2() -> i32 {
+    consts::MODEL_2
 }
 
 impl Clone for Grid {
@@ -208,6 +435,49 @@ impl Default for Grid {
             vscan: 0,
             grid_size: 0,
             c: [0f64; consts::PERSPECTIVE_PARAMS],
+            score: 0.0,
+            model: consts::MODEL_2,
+        }
+    }
+}
+
+/// An rMQR code found by `quirc_identify()`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Debug)]
+#[repr(C)]
+pub struct RmqrGrid {
+    /// Index of the capstone of its finder pattern
+    pub cap: i32,
+
+    /// Version read from the format information next to the finder
+    /// pattern, an index into `rmqr::RMQR_VERSIONS`
+    pub version: i32,
+
+    /// Size in cells, and perspective transform
+    pub width: i32,
+    pub height: i32,
+    pub c: [f64; consts::PERSPECTIVE_PARAMS],
+
+    /// The score of the finder, sub-finder, alignment and timing patterns
+    /// as a fraction of the best possible, from 0 to 1
+    pub score: f64,
+}
+
+impl Clone for RmqrGrid {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl Default for RmqrGrid {
+    fn default() -> Self {
+        RmqrGrid {
+            cap: 0,
+            version: 0,
+            width: 0,
+            height: 0,
+            c: [0f64; consts::PERSPECTIVE_PARAMS],
+            score: 0.0,
         }
     }
 }
@@ -260,23 +530,187 @@ impl<'a> IndexMut<usize> for Image<'a> {
 pub struct Quirc<'a> {
     pub image: Image<'a>,
 
+    /// How `quirc_identify()` binarizes the image
+    pub binarizer: Binarizer,
+
+    /// Grids scoring below this are dropped by `quirc_identify()`, see
+    /// `Grid::score`
+    pub min_grid_score: f64,
+
+    /// Whether `quirc_identify()` also checks that lines through each
+    /// finder pattern, down and along both diagonals, cross it in the
+    /// 1:1:3:1:1 ratio, dropping more false ones. C quirc doesn't, so it's
+    /// off by default.
+    pub cross_check: bool,
+
+    /// Whether `quirc_identify()` sets up grids from two capstones left
+    /// over, putting the third where a covered or damaged finder pattern
+    /// fits best. C quirc doesn't, so it's off by default.
+    pub recover_grids: bool,
+
     /// used by threshold()
     pub row_average: Vec<i32>,
 
     pub regions: Vec<Region>,
     pub capstones: Vec<Capstone>,
     pub grids: Vec<Grid>,
+    pub rmqr_grids: Vec<RmqrGrid>,
+
+    /// Candidates dropped by the last call to `quirc_identify()`
+    pub identify_errors: Vec<IdentifyError>,
+
+    /// Grids competing for the capstones, see `group_capstones()`
+    pub(crate) candidates: Vec<Candidate>,
+
+    /// Explicit stack of the flood fill
+    pub(crate) flood_fill_vars: Vec<FloodFillVars>,
+
+    /// Buffers for `quirc_extract_decode()`
+    pub(crate) scratch: Option<Box<Scratch>>,
 }
 
 impl<'a> Quirc<'a> {
     pub fn new(image: Image<'a>) -> Self {
         let width = image.w;
+        let height = image.h;
         Quirc {
             image,
+            binarizer: Binarizer::default(),
+            min_grid_score: 0.0,
+            cross_check: false,
+            recover_grids: false,
             row_average: vec![0; width as usize],
             regions: vec![Default::default(); 2],
             capstones: Vec::new(),
             grids: Vec::new(),
+            rmqr_grids: Vec::new(),
+            identify_errors: Vec::new(),
+            candidates: Vec::new(),
+            flood_fill_vars: vec![Default::default(); flood_fill_depth(width, height)],
+            scratch: None,
+        }
+    }
+
+    /// Set up a recognizer using the storage of `ws`, which must have
+    /// been created for the same image dimensions.
+    ///
+    /// Neither `quirc_identify()` nor `quirc_extract_decode()` will
+    /// allocate on such a recognizer. Use `into_workspace()` to get the
+    /// storage back for the next image.
+    pub fn from_workspace(image: Image<'a>, ws: Workspace) -> Self {
+        assert_eq!((image.w, image.h), (ws.width, ws.height));
+
+        let mut q = Quirc {
+            image,
+            binarizer: Binarizer::default(),
+            min_grid_score: 0.0,
+            cross_check: false,
+            recover_grids: false,
+            row_average: ws.row_average,
+            regions: ws.regions,
+            capstones: ws.capstones,
+            grids: ws.grids,
+            rmqr_grids: ws.rmqr_grids,
+            identify_errors: ws.identify_errors,
+            candidates: ws.candidates,
+            flood_fill_vars: ws.flood_fill_vars,
+            scratch: Some(ws.scratch),
+        };
+
+        q.regions.clear();
+        q.regions.resize(2, Default::default());
+        q.capstones.clear();
+        q.grids.clear();
+        q.rmqr_grids.clear();
+        q.identify_errors.clear();
+        q.candidates.clear();
+        q
+    }
+
+    /// Release the storage of a recognizer created by `from_workspace()`.
+    pub fn into_workspace(self) -> Workspace {
+        Workspace {
+            width: self.image.w,
+            height: self.image.h,
+            row_average: self.row_average,
+            regions: self.regions,
+            capstones: self.capstones,
+            grids: self.grids,
+            rmqr_grids: self.rmqr_grids,
+            identify_errors: self.identify_errors,
+            candidates: self.candidates,
+            flood_fill_vars: self.flood_fill_vars,
+            scratch: self.scratch.unwrap_or_default(),
+        }
+    }
+}
+
+/// Caller-owned storage for recognizing codes without heap allocation.
+///
+/// All the memory needed by `quirc_identify()` and
+/// `quirc_extract_decode()` is allocated up front by `new()`, sized
+/// from the image dimensions: about 125 KiB for a 320x200 image and
+/// 140 KiB for a 4000x3000 one. It can be reused for any number of
+/// images of that size:
+///
+/// ```
+/// use quirc_rs::decode::quirc_extract_decode;
+/// use quirc_rs::identify::quirc_identify;
+/// use quirc_rs::quirc::{quirc_count, Image, Quirc, QuircData, Workspace};
+///
+/// let (width, height) = (64, 48);
+/// let mut pixels = vec![0xff; (width * height) as usize];
+/// let mut ws = Workspace::new(width, height);
+/// let mut data = QuircData::default();
+///
+/// for _frame in 0..2 {
+///     let mut q = Quirc::from_workspace(Image::new(width, height, &mut pixels), ws);
+///     quirc_identify(&mut q);
+///     for i in 0..quirc_count(&q) {
+///         let _ = quirc_extract_decode(&mut q, i, &mut data);
+///     }
+///     ws = q.into_workspace();
+/// }
+/// ```
+///
+/// Nothing is recursive and no large buffers live on the stack; the
+/// codeword buffers of `quirc_extract_decode()` are part of the workspace
+/// too.
+pub struct Workspace {
+    width: i32,
+    height: i32,
+    row_average: Vec<i32>,
+    regions: Vec<Region>,
+    capstones: Vec<Capstone>,
+    grids: Vec<Grid>,
+    rmqr_grids: Vec<RmqrGrid>,
+    identify_errors: Vec<IdentifyError>,
+    candidates: Vec<Candidate>,
+    flood_fill_vars: Vec<FloodFillVars>,
+    scratch: Box<Scratch>,
+}
+
+impl Workspace {
+    /// Errors `quirc_identify()` can report: one per capstone for
+    /// failing to set up its grid and one for staying ungrouped, plus
+    /// `TooManyCapstones` and `TooManyGrids` (or `NoCapstones`).
+    const MAX_IDENTIFY_ERRORS: usize = 2 * consts::MAX_CAPSTONES + 2;
+
+    pub fn new(width: u32, height: u32) -> Self {
+        let (w, h) = (width as i32, height as i32);
+
+        Workspace {
+            width: w,
+            height: h,
+            row_average: vec![0; width as usize],
+            regions: Vec::with_capacity(consts::MAX_REGIONS),
+            capstones: Vec::with_capacity(consts::MAX_CAPSTONES),
+            grids: Vec::with_capacity(consts::MAX_GRIDS),
+            rmqr_grids: Vec::with_capacity(consts::MAX_GRIDS),
+            identify_errors: Vec::with_capacity(Self::MAX_IDENTIFY_ERRORS),
+            candidates: Vec::with_capacity(MAX_CANDIDATES),
+            flood_fill_vars: vec![Default::default(); flood_fill_depth(w, h)],
+            scratch: Box::default(),
         }
     }
 }
@@ -294,32 +728,179 @@ pub fn quirc_count(q: &Quirc) -> i32 {
 
 pub type Result<T> = core::result::Result<T, DecodeError>;
 
+/// The stage of the recognition pipeline at which an error was detected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stage {
+    FinderScan,
+    Grouping,
+    TimingPattern,
+    AlignmentPattern,
+    Perspective,
+    Version,
+    Format,
+    DataEcc,
+    Payload,
+}
+
 /// This enum describes the various decoder errors which may occur.
-#[derive(Clone, Copy, Eq, PartialEq)]
-#[repr(i32)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum DecodeError {
-    InvalidGridSize = 1,
+    InvalidGridSize,
     InvalidVersion,
     FormatEcc,
-    DataEcc,
+    /// Error correction failed for one of the RS blocks.
+    DataEcc {
+        /// Index of the failing block, in codestream order
+        block: i32,
+        /// Number of codewords the decoder managed to correct
+        corrected: i32,
+        /// Estimated number of codewords which could not be corrected:
+        /// the errors the locator polynomial claims less those it found,
+        /// and at least one. With more errors than the block can correct
+        /// the locator is unreliable, so this is no exact count.
+        uncorrectable: i32,
+    },
     UnknownDataType,
     DataOverflow,
-    DataUnderflow,
+    /// The bit stream ended in the middle of a segment.
+    DataUnderflow {
+        /// Number of bits left in the stream when more were needed
+        remaining: i32,
+    },
+}
+
+impl DecodeError {
+    /// The error code used by the C library for this error.
+    pub fn code(&self) -> i32 {
+        match self {
+            DecodeError::InvalidGridSize => 1,
+            DecodeError::InvalidVersion => 2,
+            DecodeError::FormatEcc => 3,
+            DecodeError::DataEcc { .. } => 4,
+            DecodeError::UnknownDataType => 5,
+            DecodeError::DataOverflow => 6,
+            DecodeError::DataUnderflow { .. } => 7,
+        }
+    }
+
+    /// The decoding stage which failed.
+    pub fn stage(&self) -> Stage {
+        match self {
+            DecodeError::InvalidGridSize | DecodeError::InvalidVersion => Stage::Version,
+            DecodeError::FormatEcc => Stage::Format,
+            DecodeError::DataEcc { .. } => Stage::DataEcc,
+            DecodeError::UnknownDataType
+            | DecodeError::DataOverflow
+            | DecodeError::DataUnderflow { .. } => Stage::Payload,
+        }
+    }
 }
 
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(quirc_strerror(*self))?;
+        match self {
+            DecodeError::DataEcc {
+                block,
+                corrected,
+                uncorrectable,
+            } => write!(
+                f,
+                " in block {} ({} corrected, ~{} uncorrectable)",
+                block, corrected, uncorrectable
+            ),
+            DecodeError::DataUnderflow { remaining } => {
+                write!(f, " ({} bits remaining)", remaining)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
 /// Return a string error message for an error code.
 pub fn quirc_strerror(err: DecodeError) -> &'static str {
     match err {
         DecodeError::InvalidGridSize => "Invalid grid size",
         DecodeError::InvalidVersion => "Invalid version",
         DecodeError::FormatEcc => "Format data ECC failure",
-        DecodeError::DataEcc => "ECC failure",
+        DecodeError::DataEcc { .. } => "ECC failure",
         DecodeError::UnknownDataType => "Unknown data type",
         DecodeError::DataOverflow => "Data overflow",
-        DecodeError::DataUnderflow => "Data underflow",
+        DecodeError::DataUnderflow { .. } => "Data underflow",
     }
 }
 
+/// This enum describes the reasons why a candidate was dropped during
+/// identification. These are recorded in `Quirc::identify_errors` as the
+/// image is processed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IdentifyError {
+    /// No finder patterns were found in the image.
+    NoCapstones,
+    /// `MAX_CAPSTONES` was reached; further finder patterns were ignored.
+    TooManyCapstones,
+    /// The capstone could not be grouped with two others.
+    Ungrouped { capstone: i32 },
+    /// `MAX_GRIDS` was reached; further groupings were ignored.
+    TooManyGrids,
+    /// Neither timing pattern of the grouped capstones could be scanned.
+    TimingPattern { caps: [i32; 3] },
+    /// The edges of capstones A and C are parallel, so no alignment
+    /// estimate could be made.
+    AlignmentEstimate { caps: [i32; 3] },
+    /// The alignment pattern was not found near its estimate. This is
+    /// not fatal: the grid is still recorded using the estimate.
+    AlignmentPattern { grid: i32 },
+    /// The grid scored below `Quirc::min_grid_score`.
+    LowScore { caps: [i32; 3] },
+}
+
+impl IdentifyError {
+    /// The identification stage which failed.
+    pub fn stage(&self) -> Stage {
+        match self {
+            IdentifyError::NoCapstones | IdentifyError::TooManyCapstones => Stage::FinderScan,
+            IdentifyError::Ungrouped { .. } | IdentifyError::TooManyGrids => Stage::Grouping,
+            IdentifyError::TimingPattern { .. } => Stage::TimingPattern,
+            IdentifyError::AlignmentEstimate { .. } | IdentifyError::AlignmentPattern { .. } => {
+                Stage::AlignmentPattern
+            }
+            IdentifyError::LowScore { .. } => Stage::Perspective,
+        }
+    }
+}
+
+impl fmt::Display for IdentifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IdentifyError::NoCapstones => f.write_str("No capstones found"),
+            IdentifyError::TooManyCapstones => f.write_str("Too many capstones"),
+            IdentifyError::Ungrouped { capstone } => {
+                write!(f, "Capstone {} could not be grouped", capstone)
+            }
+            IdentifyError::TooManyGrids => f.write_str("Too many grids"),
+            IdentifyError::TimingPattern { caps } => {
+                write!(f, "Timing pattern scan failed for capstones {:?}", caps)
+            }
+            IdentifyError::AlignmentEstimate { caps } => {
+                write!(f, "No alignment estimate for capstones {:?}", caps)
+            }
+            IdentifyError::AlignmentPattern { grid } => {
+                write!(f, "Alignment pattern not found for grid {}", grid)
+            }
+            IdentifyError::LowScore { caps } => {
+                write!(f, "Grid of capstones {:?} scored too low", caps)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IdentifyError {}
+
 pub mod consts {
     pub const PIXEL_WHITE: i32 = 0;
     pub const PIXEL_BLACK: i32 = 1;
@@ -338,6 +919,13 @@ pub mod consts {
     pub const MAX_BITMAP: usize = 3917;
     pub const MAX_PAYLOAD: usize = 8896;
 
+    /* Size of the cell bitmap of the largest rMQR code, R17x139 */
+    pub const RMQR_MAX_BITMAP: usize = 296;
+
+    /* QR-code models. Both are read by `quirc_decode()`. */
+    pub const MODEL_1: i32 = 1;
+    pub const MODEL_2: i32 = 2;
+
     /* QR-code ECC types. */
     pub const ECC_LEVEL_M: i32 = 0;
     pub const ECC_LEVEL_L: i32 = 1;
@@ -350,6 +938,9 @@ pub mod consts {
     pub const DATA_TYPE_BYTE: i32 = 4;
     pub const DATA_TYPE_KANJI: i32 = 8;
 
+    /* Mode indicator of ECI designators */
+    pub const DATA_TYPE_ECI: i32 = 7;
+
     /* Common character encodings */
     pub const ECI_ISO_8859_1: i32 = 1;
     pub const ECI_IBM437: i32 = 2;
@@ -361,9 +952,21 @@ pub mod consts {
     pub const ECI_ISO_8859_7: i32 = 9;
     pub const ECI_ISO_8859_8: i32 = 10;
     pub const ECI_ISO_8859_9: i32 = 11;
+    pub const ECI_ISO_8859_10: i32 = 12;
     pub const ECI_WINDOWS_874: i32 = 13;
     pub const ECI_ISO_8859_13: i32 = 15;
+    pub const ECI_ISO_8859_14: i32 = 16;
     pub const ECI_ISO_8859_15: i32 = 17;
+    pub const ECI_ISO_8859_16: i32 = 18;
     pub const ECI_SHIFT_JIS: i32 = 20;
+    pub const ECI_WINDOWS_1250: i32 = 21;
+    pub const ECI_WINDOWS_1251: i32 = 22;
+    pub const ECI_WINDOWS_1252: i32 = 23;
+    pub const ECI_WINDOWS_1256: i32 = 24;
+    pub const ECI_UTF_16BE: i32 = 25;
     pub const ECI_UTF_8: i32 = 26;
+    pub const ECI_US_ASCII: i32 = 27;
+    pub const ECI_BIG5: i32 = 28;
+    pub const ECI_GB18030: i32 = 29;
+    pub const ECI_EUC_KR: i32 = 30;
 }
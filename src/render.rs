@@ -0,0 +1,313 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Debug rendering of what `quirc_identify()` found.
+//!
+//! This draws the same picture as the SDL inspector of the C library, but
+//! into an RGB buffer, so it works without a display.
+
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::identify::perspective_map;
+use crate::math::Bresenham;
+use crate::quirc::consts::*;
+use crate::quirc::*;
+
+/// Red, green and blue components of a pixel.
+pub type Color = [u8; 3];
+
+pub const BLACK: Color = [0, 0, 0];
+pub const WHITE: Color = [0xff, 0xff, 0xff];
+
+/// Regions which are part of a capstone
+pub const CAPSTONE_REGION: Color = [0, 0x80, 0];
+pub const CAPSTONE_EDGE: Color = [0x80, 0, 0x80];
+
+/// The first corner of each capstone, and the alignment pattern
+pub const CORNER: Color = [0, 0, 0xff];
+pub const TIMING_PATTERN: Color = [0xff, 0, 0xff];
+pub const SAMPLE: Color = [0xff, 0, 0];
+
+/// An RGB image with 8 bits per channel, stored row by row.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// Create a white canvas.
+    pub fn new(width: u32, height: u32) -> Self {
+        Canvas {
+            width,
+            height,
+            pixels: vec![0xff; width as usize * height as usize * 3],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The pixels as consecutive red, green and blue bytes.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+
+    fn offset(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        Some((y as usize * self.width as usize + x as usize) * 3)
+    }
+
+    /// Returns the color at (x, y), or `None` if it's outside of the
+    /// canvas.
+    pub fn get(&self, x: i32, y: i32) -> Option<Color> {
+        self.offset(x, y)
+            .map(|i| [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]])
+    }
+
+    /// Set the color at (x, y). Points outside of the canvas are ignored,
+    /// as are those of all the other drawing functions.
+    pub fn set(&mut self, x: i32, y: i32, color: Color) {
+        if let Some(i) = self.offset(x, y) {
+            self.pixels[i..i + 3].copy_from_slice(&color);
+        }
+    }
+
+    pub fn line(&mut self, from: Point, to: Point, color: Color) {
+        for (x, y) in Bresenham::new((from.x, from.y), (to.x, to.y)) {
+            self.set(x, y, color);
+        }
+    }
+
+    /// Fill a 5x5 square centred on `p`.
+    pub fn blob(&mut self, p: Point, color: Color) {
+        for i in -2..=2 {
+            for j in -2..=2 {
+                self.set(p.x + i, p.y + j, color);
+            }
+        }
+    }
+
+    /// Draw a small cross centred on `p`.
+    pub fn mark(&mut self, p: Point, color: Color) {
+        self.set(p.x, p.y, color);
+        self.set(p.x + 1, p.y, color);
+        self.set(p.x - 1, p.y, color);
+        self.set(p.x, p.y + 1, color);
+        self.set(p.x, p.y - 1, color);
+    }
+
+    /// Write `text` on a white background, with its top left corner at
+    /// (x, y). Each font pixel is drawn as a `scale` by `scale` square.
+    ///
+    /// The font only has digits, "A", "B", "C", "?" and "."; other
+    /// characters are left blank.
+    pub fn text(&mut self, x: i32, y: i32, text: &str, scale: i32, color: Color) {
+        let advance = (GLYPH_WIDTH + 1) * scale;
+        let width = text.chars().count() as i32 * advance + scale;
+        let height = (GLYPH_HEIGHT + 2) * scale;
+
+        for j in 0..height {
+            for i in 0..width {
+                self.set(x + i, y + j, WHITE);
+            }
+        }
+
+        for (n, c) in text.chars().enumerate() {
+            let rows = match glyph(c) {
+                Some(rows) => rows,
+                None => continue,
+            };
+            let left = x + scale + n as i32 * advance;
+
+            for (row, bits) in rows.iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                        continue;
+                    }
+                    for j in 0..scale {
+                        for i in 0..scale {
+                            let top = y + scale + row as i32 * scale;
+                            self.set(left + col * scale + i, top + j, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+const GLYPH_WIDTH: i32 = 3;
+const GLYPH_HEIGHT: i32 = 5;
+
+/// Rows of a 3x5 glyph, top to bottom, with the leftmost column in the
+/// highest bit.
+fn glyph(c: char) -> Option<[u8; GLYPH_HEIGHT as usize]> {
+    let rows = match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        '?' => [0b110, 0b001, 0b010, 0b000, 0b010],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+/// Color of a region which isn't part of a capstone. Consecutive labels
+/// get clearly different mid-tones, none of which can be mistaken for
+/// black, white or `CAPSTONE_REGION`.
+fn region_color(label: usize) -> Color {
+    let n = label as u32;
+    [
+        (0x60 + (n * 37) % 0x60) as u8,
+        (0x60 + (n * 71) % 0x60) as u8,
+        (0x60 + (n * 113) % 0x60) as u8,
+    ]
+}
+
+fn draw_frame(canvas: &mut Canvas, q: &Quirc) {
+    let w = q.image.width();
+
+    for (i, &v) in q.image.pixels().iter().enumerate() {
+        let color = match i32::from(v) {
+            PIXEL_WHITE => WHITE,
+            PIXEL_BLACK => BLACK,
+            _ => match q.regions.get(v as usize) {
+                Some(reg) if reg.capstone >= 0 => CAPSTONE_REGION,
+                _ => region_color(v as usize),
+            },
+        };
+        canvas.set(i as i32 % w, i as i32 / w, color);
+    }
+}
+
+fn draw_capstone(canvas: &mut Canvas, q: &Quirc, index: usize, scale: i32) {
+    let cap = &q.capstones[index];
+
+    for j in 0..4 {
+        canvas.line(cap.corners[j], cap.corners[(j + 1) % 4], CAPSTONE_EDGE);
+    }
+    canvas.blob(cap.corners[0], CORNER);
+
+    if cap.qr_grid < 0 {
+        let label = format!("?{}", index);
+        canvas.text(cap.center.x, cap.center.y, &label, scale, BLACK);
+    }
+}
+
+fn draw_grid(canvas: &mut Canvas, q: &Quirc, index: usize, scale: i32) {
+    let qr = &q.grids[index];
+
+    for (i, name) in "ABC".chars().enumerate() {
+        let cap = &q.capstones[qr.caps[i] as usize];
+        let label = format!("{}.{}", index, name);
+        canvas.text(cap.center.x, cap.center.y, &label, scale, BLACK);
+    }
+
+    canvas.line(qr.tpep[0], qr.tpep[1], TIMING_PATTERN);
+    canvas.line(qr.tpep[1], qr.tpep[2], TIMING_PATTERN);
+
+    if qr.align_region >= 0 {
+        canvas.blob(qr.align, CORNER);
+    }
+
+    for y in 0..qr.grid_size {
+        for x in 0..qr.grid_size {
+            let p = perspective_map(&qr.c, f64::from(x) + 0.5, f64::from(y) + 0.5);
+            canvas.mark(p, SAMPLE);
+        }
+    }
+}
+
+/// Draw the state left by `quirc_identify()`: the binarized image with
+/// each region in its own color (capstone regions in green), the edges
+/// and first corner of each capstone, and for each grid the timing
+/// pattern endpoints, the alignment pattern and the cell centres.
+///
+/// Capstones are labelled with "grid.A", "grid.B" and "grid.C", or
+/// "?index" if they weren't grouped into a grid. Labels are scaled up
+/// with the image so they stay legible on large photos.
+pub fn quirc_render(q: &Quirc) -> Canvas {
+    let (w, h) = (q.image.width(), q.image.height());
+    let mut canvas = Canvas::new(w as u32, h as u32);
+    let scale = 1 + w.min(h) / 500;
+
+    draw_frame(&mut canvas, q);
+    for i in 0..q.capstones.len() {
+        draw_capstone(&mut canvas, q, i, scale);
+    }
+    for i in 0..q.grids.len() {
+        draw_grid(&mut canvas, q, i, scale);
+    }
+
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clipping() {
+        let mut canvas = Canvas::new(4, 3);
+        canvas.line(Point { x: -2, y: 1 }, Point { x: 6, y: 1 }, BLACK);
+        canvas.blob(Point { x: 0, y: 0 }, CORNER);
+        canvas.text(3, 2, "?1", 2, BLACK);
+
+        assert_eq!(canvas.get(3, 1), Some(BLACK));
+        assert_eq!(canvas.get(0, 0), Some(CORNER));
+        assert_eq!(canvas.get(3, 0), Some(WHITE));
+        assert_eq!(canvas.get(4, 0), None);
+        assert_eq!(canvas.pixels().len(), 4 * 3 * 3);
+    }
+
+    #[test]
+    fn test_text() {
+        let mut canvas = Canvas::new(8, 7);
+        canvas.text(0, 0, "1", 1, BLACK);
+
+        let row = |y| -> Vec<bool> { (0..5).map(|x| canvas.get(x, y) == Some(BLACK)).collect() };
+        assert_eq!(row(0), [false; 5]);
+        assert_eq!(row(1), [false, false, true, false, false]);
+        assert_eq!(row(5), [false, true, true, true, false]);
+        assert_eq!(canvas.get(5, 0), Some(WHITE));
+    }
+}
@@ -0,0 +1,974 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Rectangular Micro QR codes (rMQR, ISO/IEC 23941).
+//!
+//! An rMQR code is 7 to 17 cells high and 27 to 139 cells wide. It has a
+//! single finder pattern at the top left, a smaller sub-finder pattern at
+//! the bottom right, and timing patterns along all four edges and between
+//! its alignment patterns. `quirc_identify()` looks for them around the
+//! capstones it couldn't group into a QR-code, reading the version from
+//! the format information next to the finder pattern.
+//!
+//! ```
+//! use quirc_rs::identify::quirc_identify;
+//! use quirc_rs::quirc::{Image, Quirc};
+//! use quirc_rs::rmqr::{quirc_decode_rmqr, quirc_extract_rmqr, quirc_rmqr_count};
+//!
+//! let mut pixels = vec![0xff; 64 * 48];
+//! let mut q = Quirc::new(Image::new(64, 48, &mut pixels));
+//! quirc_identify(&mut q);
+//! for i in 0..quirc_rmqr_count(&q) {
+//!     let code = quirc_extract_rmqr(&q, i).unwrap();
+//!     println!("{:?}", quirc_decode_rmqr(&code).map(|data| data.payload_len));
+//! }
+//! ```
+
+use crate::decode::{codestream_ecc, decode_payload, DataStream, Modes};
+use crate::identify::perspective_map;
+use crate::quirc::consts::*;
+use crate::quirc::*;
+use crate::version_db::RsParams;
+
+pub const RMQR_MAX_VERSION: usize = 31;
+pub const RMQR_MAX_ALIGNMENT: usize = 4;
+
+#[derive(Copy)]
+#[repr(C)]
+pub struct RmqrVersion {
+    pub width: i32,
+    pub height: i32,
+
+    /// Total number of codewords
+    pub data_bytes: i32,
+
+    /// Columns of the alignment patterns, followed by zeroes
+    pub apat: [i32; RMQR_MAX_ALIGNMENT],
+
+    /// Error correction blocks at level M and H
+    pub ecc: [RsParams; 2],
+
+    /// Length of the character count indicator of numeric, alphanumeric,
+    /// byte and kanji segments
+    pub count_bits: [i32; 4],
+}
+
+impl Clone for RmqrVersion {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+/// rMQR versions, by the version indicator of their format information.
+pub const RMQR_VERSIONS: [RmqrVersion; RMQR_MAX_VERSION + 1] = [
+    // R7x43
+    RmqrVersion {
+        width: 43,
+        height: 7,
+        data_bytes: 13,
+        apat: [21, 0, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 13,
+                dw: 6,
+                ns: 1,
+            },
+            RsParams {
+                bs: 13,
+                dw: 3,
+                ns: 1,
+            },
+        ],
+        count_bits: [4, 3, 3, 2],
+    },
+    // R7x59
+    RmqrVersion {
+        width: 59,
+        height: 7,
+        data_bytes: 21,
+        apat: [19, 39, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 21,
+                dw: 12,
+                ns: 1,
+            },
+            RsParams {
+                bs: 21,
+                dw: 7,
+                ns: 1,
+            },
+        ],
+        count_bits: [5, 5, 4, 3],
+    },
+    // R7x77
+    RmqrVersion {
+        width: 77,
+        height: 7,
+        data_bytes: 32,
+        apat: [25, 51, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 32,
+                dw: 20,
+                ns: 1,
+            },
+            RsParams {
+                bs: 32,
+                dw: 10,
+                ns: 1,
+            },
+        ],
+        count_bits: [6, 5, 5, 4],
+    },
+    // R7x99
+    RmqrVersion {
+        width: 99,
+        height: 7,
+        data_bytes: 44,
+        apat: [23, 49, 75, 0],
+        ecc: [
+            RsParams {
+                bs: 44,
+                dw: 28,
+                ns: 1,
+            },
+            RsParams {
+                bs: 44,
+                dw: 14,
+                ns: 1,
+            },
+        ],
+        count_bits: [7, 6, 5, 5],
+    },
+    // R7x139
+    RmqrVersion {
+        width: 139,
+        height: 7,
+        data_bytes: 68,
+        apat: [27, 55, 83, 111],
+        ecc: [
+            RsParams {
+                bs: 68,
+                dw: 44,
+                ns: 1,
+            },
+            RsParams {
+                bs: 34,
+                dw: 12,
+                ns: 2,
+            },
+        ],
+        count_bits: [7, 6, 6, 5],
+    },
+    // R9x43
+    RmqrVersion {
+        width: 43,
+        height: 9,
+        data_bytes: 21,
+        apat: [21, 0, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 21,
+                dw: 12,
+                ns: 1,
+            },
+            RsParams {
+                bs: 21,
+                dw: 7,
+                ns: 1,
+            },
+        ],
+        count_bits: [5, 5, 4, 3],
+    },
+    // R9x59
+    RmqrVersion {
+        width: 59,
+        height: 9,
+        data_bytes: 33,
+        apat: [19, 39, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 33,
+                dw: 21,
+                ns: 1,
+            },
+            RsParams {
+                bs: 33,
+                dw: 11,
+                ns: 1,
+            },
+        ],
+        count_bits: [6, 5, 5, 4],
+    },
+    // R9x77
+    RmqrVersion {
+        width: 77,
+        height: 9,
+        data_bytes: 49,
+        apat: [25, 51, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 49,
+                dw: 31,
+                ns: 1,
+            },
+            RsParams {
+                bs: 24,
+                dw: 8,
+                ns: 1,
+            },
+        ],
+        count_bits: [7, 6, 5, 5],
+    },
+    // R9x99
+    RmqrVersion {
+        width: 99,
+        height: 9,
+        data_bytes: 66,
+        apat: [23, 49, 75, 0],
+        ecc: [
+            RsParams {
+                bs: 66,
+                dw: 42,
+                ns: 1,
+            },
+            RsParams {
+                bs: 33,
+                dw: 11,
+                ns: 2,
+            },
+        ],
+        count_bits: [7, 6, 6, 5],
+    },
+    // R9x139
+    RmqrVersion {
+        width: 139,
+        height: 9,
+        data_bytes: 99,
+        apat: [27, 55, 83, 111],
+        ecc: [
+            RsParams {
+                bs: 49,
+                dw: 31,
+                ns: 1,
+            },
+            RsParams {
+                bs: 33,
+                dw: 11,
+                ns: 3,
+            },
+        ],
+        count_bits: [8, 7, 6, 6],
+    },
+    // R11x27
+    RmqrVersion {
+        width: 27,
+        height: 11,
+        data_bytes: 15,
+        apat: [0, 0, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 15,
+                dw: 7,
+                ns: 1,
+            },
+            RsParams {
+                bs: 15,
+                dw: 5,
+                ns: 1,
+            },
+        ],
+        count_bits: [4, 4, 3, 2],
+    },
+    // R11x43
+    RmqrVersion {
+        width: 43,
+        height: 11,
+        data_bytes: 31,
+        apat: [21, 0, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 31,
+                dw: 19,
+                ns: 1,
+            },
+            RsParams {
+                bs: 31,
+                dw: 11,
+                ns: 1,
+            },
+        ],
+        count_bits: [6, 5, 5, 4],
+    },
+    // R11x59
+    RmqrVersion {
+        width: 59,
+        height: 11,
+        data_bytes: 47,
+        apat: [19, 39, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 47,
+                dw: 31,
+                ns: 1,
+            },
+            RsParams {
+                bs: 23,
+                dw: 7,
+                ns: 1,
+            },
+        ],
+        count_bits: [7, 6, 5, 5],
+    },
+    // R11x77
+    RmqrVersion {
+        width: 77,
+        height: 11,
+        data_bytes: 67,
+        apat: [25, 51, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 67,
+                dw: 43,
+                ns: 1,
+            },
+            RsParams {
+                bs: 33,
+                dw: 11,
+                ns: 1,
+            },
+        ],
+        count_bits: [7, 6, 6, 5],
+    },
+    // R11x99
+    RmqrVersion {
+        width: 99,
+        height: 11,
+        data_bytes: 89,
+        apat: [23, 49, 75, 0],
+        ecc: [
+            RsParams {
+                bs: 44,
+                dw: 28,
+                ns: 1,
+            },
+            RsParams {
+                bs: 44,
+                dw: 14,
+                ns: 1,
+            },
+        ],
+        count_bits: [8, 7, 6, 6],
+    },
+    // R11x139
+    RmqrVersion {
+        width: 139,
+        height: 11,
+        data_bytes: 132,
+        apat: [27, 55, 83, 111],
+        ecc: [
+            RsParams {
+                bs: 66,
+                dw: 42,
+                ns: 2,
+            },
+            RsParams {
+                bs: 44,
+                dw: 14,
+                ns: 3,
+            },
+        ],
+        count_bits: [8, 7, 7, 6],
+    },
+    // R13x27
+    RmqrVersion {
+        width: 27,
+        height: 13,
+        data_bytes: 21,
+        apat: [0, 0, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 21,
+                dw: 12,
+                ns: 1,
+            },
+            RsParams {
+                bs: 21,
+                dw: 7,
+                ns: 1,
+            },
+        ],
+        count_bits: [5, 5, 4, 3],
+    },
+    // R13x43
+    RmqrVersion {
+        width: 43,
+        height: 13,
+        data_bytes: 41,
+        apat: [21, 0, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 41,
+                dw: 27,
+                ns: 1,
+            },
+            RsParams {
+                bs: 41,
+                dw: 13,
+                ns: 1,
+            },
+        ],
+        count_bits: [6, 6, 5, 5],
+    },
+    // R13x59
+    RmqrVersion {
+        width: 59,
+        height: 13,
+        data_bytes: 60,
+        apat: [19, 39, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 60,
+                dw: 38,
+                ns: 1,
+            },
+            RsParams {
+                bs: 30,
+                dw: 10,
+                ns: 2,
+            },
+        ],
+        count_bits: [7, 6, 6, 5],
+    },
+    // R13x77
+    RmqrVersion {
+        width: 77,
+        height: 13,
+        data_bytes: 85,
+        apat: [25, 51, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 42,
+                dw: 26,
+                ns: 1,
+            },
+            RsParams {
+                bs: 42,
+                dw: 14,
+                ns: 1,
+            },
+        ],
+        count_bits: [7, 7, 6, 6],
+    },
+    // R13x99
+    RmqrVersion {
+        width: 99,
+        height: 13,
+        data_bytes: 113,
+        apat: [23, 49, 75, 0],
+        ecc: [
+            RsParams {
+                bs: 56,
+                dw: 36,
+                ns: 1,
+            },
+            RsParams {
+                bs: 37,
+                dw: 11,
+                ns: 1,
+            },
+        ],
+        count_bits: [8, 7, 7, 6],
+    },
+    // R13x139
+    RmqrVersion {
+        width: 139,
+        height: 13,
+        data_bytes: 166,
+        apat: [27, 55, 83, 111],
+        ecc: [
+            RsParams {
+                bs: 55,
+                dw: 35,
+                ns: 2,
+            },
+            RsParams {
+                bs: 41,
+                dw: 13,
+                ns: 2,
+            },
+        ],
+        count_bits: [8, 8, 7, 7],
+    },
+    // R15x43
+    RmqrVersion {
+        width: 43,
+        height: 15,
+        data_bytes: 51,
+        apat: [21, 0, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 51,
+                dw: 33,
+                ns: 1,
+            },
+            RsParams {
+                bs: 25,
+                dw: 7,
+                ns: 1,
+            },
+        ],
+        count_bits: [7, 6, 6, 5],
+    },
+    // R15x59
+    RmqrVersion {
+        width: 59,
+        height: 15,
+        data_bytes: 74,
+        apat: [19, 39, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 74,
+                dw: 48,
+                ns: 1,
+            },
+            RsParams {
+                bs: 37,
+                dw: 13,
+                ns: 2,
+            },
+        ],
+        count_bits: [7, 7, 6, 5],
+    },
+    // R15x77
+    RmqrVersion {
+        width: 77,
+        height: 15,
+        data_bytes: 103,
+        apat: [25, 51, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 51,
+                dw: 33,
+                ns: 1,
+            },
+            RsParams {
+                bs: 34,
+                dw: 10,
+                ns: 2,
+            },
+        ],
+        count_bits: [8, 7, 7, 6],
+    },
+    // R15x99
+    RmqrVersion {
+        width: 99,
+        height: 15,
+        data_bytes: 136,
+        apat: [23, 49, 75, 0],
+        ecc: [
+            RsParams {
+                bs: 68,
+                dw: 44,
+                ns: 2,
+            },
+            RsParams {
+                bs: 34,
+                dw: 12,
+                ns: 4,
+            },
+        ],
+        count_bits: [8, 7, 7, 6],
+    },
+    // R15x139
+    RmqrVersion {
+        width: 139,
+        height: 15,
+        data_bytes: 199,
+        apat: [27, 55, 83, 111],
+        ecc: [
+            RsParams {
+                bs: 66,
+                dw: 42,
+                ns: 2,
+            },
+            RsParams {
+                bs: 39,
+                dw: 13,
+                ns: 1,
+            },
+        ],
+        count_bits: [9, 8, 7, 7],
+    },
+    // R17x43
+    RmqrVersion {
+        width: 43,
+        height: 17,
+        data_bytes: 61,
+        apat: [21, 0, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 61,
+                dw: 39,
+                ns: 1,
+            },
+            RsParams {
+                bs: 30,
+                dw: 10,
+                ns: 1,
+            },
+        ],
+        count_bits: [7, 6, 6, 5],
+    },
+    // R17x59
+    RmqrVersion {
+        width: 59,
+        height: 17,
+        data_bytes: 88,
+        apat: [19, 39, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 44,
+                dw: 28,
+                ns: 2,
+            },
+            RsParams {
+                bs: 44,
+                dw: 14,
+                ns: 2,
+            },
+        ],
+        count_bits: [8, 7, 6, 6],
+    },
+    // R17x77
+    RmqrVersion {
+        width: 77,
+        height: 17,
+        data_bytes: 122,
+        apat: [25, 51, 0, 0],
+        ecc: [
+            RsParams {
+                bs: 61,
+                dw: 39,
+                ns: 2,
+            },
+            RsParams {
+                bs: 40,
+                dw: 12,
+                ns: 1,
+            },
+        ],
+        count_bits: [8, 7, 7, 6],
+    },
+    // R17x99
+    RmqrVersion {
+        width: 99,
+        height: 17,
+        data_bytes: 160,
+        apat: [23, 49, 75, 0],
+        ecc: [
+            RsParams {
+                bs: 53,
+                dw: 33,
+                ns: 2,
+            },
+            RsParams {
+                bs: 40,
+                dw: 14,
+                ns: 4,
+            },
+        ],
+        count_bits: [8, 8, 7, 6],
+    },
+    // R17x139
+    RmqrVersion {
+        width: 139,
+        height: 17,
+        data_bytes: 232,
+        apat: [27, 55, 83, 111],
+        ecc: [
+            RsParams {
+                bs: 58,
+                dw: 38,
+                ns: 4,
+            },
+            RsParams {
+                bs: 38,
+                dw: 12,
+                ns: 2,
+            },
+        ],
+        count_bits: [9, 8, 8, 7],
+    },
+];
+
+/// Masks of the format information next to the finder pattern and next
+/// to the sub-finder pattern
+const FORMAT_MASKS: [u32; 2] = [0x1fab2, 0x20a7b];
+
+/// Generator polynomial of the BCH code protecting the format information
+const FORMAT_POLY: u32 = 0x1f25;
+
+const FORMAT_BITS: i32 = 18;
+const FORMAT_MAX_ERROR: u32 = 3;
+
+/// The position of bit `i` of the format information next to the finder
+/// pattern (`which` 0) or the sub-finder pattern (1) of a code of the
+/// given size.
+fn format_cell(which: i32, i: i32, width: i32, height: i32) -> (i32, i32) {
+    if which == 0 {
+        if i < 15 {
+            (8 + i / 5, 1 + i % 5)
+        } else {
+            (11, i - 14)
+        }
+    } else if i < 15 {
+        (width - 8 + i / 5, height - 6 + i % 5)
+    } else {
+        (width - 20 + i, height - 6)
+    }
+}
+
+/// The 6 bits of format information, the ECC level followed by the
+/// version indicator, with the 12 check bits appended.
+fn format_codeword(data: u32) -> u32 {
+    let mut rem = data << 12;
+    for i in (12..FORMAT_BITS as u32).rev() {
+        if rem & (1 << i) != 0 {
+            rem ^= FORMAT_POLY << (i - 12);
+        }
+    }
+    data << 12 | rem
+}
+
+/// Correct the format information `raw`, already unmasked, by finding
+/// the nearest valid codeword. Returns its 6 data bits and the number of
+/// bits corrected.
+fn correct_format(raw: u32) -> Option<(u32, u32)> {
+    (0..64)
+        .map(|data| (data, (format_codeword(data) ^ raw).count_ones()))
+        .min_by_key(|&(_, errors)| errors)
+        .filter(|&(_, errors)| errors <= FORMAT_MAX_ERROR)
+}
+
+/// Whether the cell at (x, y) of a code of version `ver` belongs to a
+/// function pattern or the format information.
+fn reserved_cell(ver: &RmqrVersion, x: i32, y: i32) -> bool {
+    let (w, h) = (ver.width, ver.height);
+
+    // Finder pattern and its separator, sub-finder pattern and corner
+    // finder patterns
+    if (x < 8 && y < 8)
+        || (x >= w - 5 && y >= h - 5)
+        || (x >= w - 2 && y < 2)
+        || (x < 2 && y >= h - 2)
+    {
+        return true;
+    }
+
+    // Timing patterns along the edges
+    if y == 0 || y == h - 1 || x == 0 || x == w - 1 {
+        return true;
+    }
+
+    // Alignment patterns on the top and bottom edges, and the timing
+    // patterns between them
+    for &cx in ver.apat.iter().take_while(|&&cx| cx != 0) {
+        if x == cx || ((x - cx).abs() <= 1 && (y < 3 || y >= h - 3)) {
+            return true;
+        }
+    }
+
+    (0..FORMAT_BITS).any(|i| format_cell(0, i, w, h) == (x, y) || format_cell(1, i, w, h) == (x, y))
+}
+
+/// Read and correct the format information next to the finder pattern
+/// (`which` 0) or the sub-finder pattern (1), `dark` telling whether a
+/// cell is dark. See `correct_format()`.
+pub(crate) fn format_info(
+    which: i32,
+    width: i32,
+    height: i32,
+    dark: &dyn Fn(i32, i32) -> bool,
+) -> Option<(u32, u32)> {
+    let mut format = 0;
+    for i in 0..FORMAT_BITS {
+        let (x, y) = format_cell(which, i, width, height);
+        if dark(x, y) {
+            format |= 1 << i;
+        }
+    }
+
+    correct_format(format ^ FORMAT_MASKS[which as usize])
+}
+
+fn mask_bit(x: i32, y: i32) -> bool {
+    (y / 2 + x / 3) % 2 == 0
+}
+
+fn grid_bit(code: &RmqrCode, x: i32, y: i32) -> bool {
+    let p = y * code.width + x;
+    code.cell_bitmap[(p >> 3) as usize] & (1 << (p & 7)) != 0
+}
+
+fn read_format(code: &RmqrCode, which: i32) -> Result<u32> {
+    format_info(which, code.width, code.height, &|x, y| grid_bit(code, x, y))
+        .map(|(data, _)| data)
+        .ok_or(DecodeError::FormatEcc)
+}
+
+/// Read the codewords, in two-column zigzags from the bottom right
+/// corner, removing the mask.
+fn read_data(code: &RmqrCode, ver: &RmqrVersion, ds: &mut DataStream) {
+    let mut x = code.width - 1;
+    let mut upward = true;
+
+    ds.clear();
+    while x > 0 {
+        for k in 0..code.height {
+            let y = if upward { code.height - 1 - k } else { k };
+            for &cx in &[x, x - 1] {
+                if !reserved_cell(ver, cx, y) {
+                    ds.push_raw(grid_bit(code, cx, y) ^ mask_bit(cx, y));
+                }
+            }
+        }
+        upward = !upward;
+        x -= 2;
+    }
+}
+
+/// The modes of an rMQR code: shorter mode indicators than a QR-code's,
+/// and character counts sized for each version.
+fn modes(ver: &RmqrVersion) -> Modes {
+    let mut types = [0; 16];
+    types[1] = DATA_TYPE_NUMERIC;
+    types[2] = DATA_TYPE_ALPHA;
+    types[3] = DATA_TYPE_BYTE;
+    types[4] = DATA_TYPE_KANJI;
+    types[7] = DATA_TYPE_ECI;
+
+    Modes {
+        indicator_bits: 3,
+        types,
+        count_bits: ver.count_bits,
+    }
+}
+
+/// Return the number of rMQR codes identified in the last processed
+/// image.
+pub fn quirc_rmqr_count(q: &Quirc) -> i32 {
+    q.rmqr_grids.len() as i32
+}
+
+/// Extract the rMQR code specified by the given index.
+pub fn quirc_extract_rmqr(q: &Quirc, index: i32) -> Option<RmqrCode> {
+    let grid = q.rmqr_grids.get(index as usize)?;
+    let (w, h) = (f64::from(grid.width), f64::from(grid.height));
+    let mut code = RmqrCode {
+        corners: [
+            perspective_map(&grid.c, 0.0, 0.0),
+            perspective_map(&grid.c, w, 0.0),
+            perspective_map(&grid.c, w, h),
+            perspective_map(&grid.c, 0.0, h),
+        ],
+        width: grid.width,
+        height: grid.height,
+        ..Default::default()
+    };
+
+    let image = &q.image;
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let p = perspective_map(&grid.c, f64::from(x) + 0.5, f64::from(y) + 0.5);
+            if p.x >= 0
+                && p.y >= 0
+                && p.x < image.w
+                && p.y < image.h
+                && image[(p.y * image.w + p.x) as usize] != 0
+            {
+                let i = y * grid.width + x;
+                code.cell_bitmap[(i >> 3) as usize] |= 1 << (i & 7);
+            }
+        }
+    }
+
+    Some(code)
+}
+
+/// Decode an rMQR code, returning the payload data. `version` is the
+/// index into `RMQR_VERSIONS`, `ecc_level` is `ECC_LEVEL_M` or
+/// `ECC_LEVEL_H`, and `mask` is always 0: rMQR codes have a single mask.
+pub fn quirc_decode_rmqr(code: &RmqrCode) -> Result<QuircData> {
+    let mut data = QuircData::default();
+    let mut ds = DataStream::default();
+
+    // Read format information -- try both locations
+    let format = read_format(code, 0).or_else(|_| read_format(code, 1))?;
+
+    let version = (format & 0x1f) as usize;
+    let ver = &RMQR_VERSIONS[version];
+    if code.width != ver.width || code.height != ver.height {
+        return Err(DecodeError::InvalidGridSize);
+    }
+
+    let level = (format >> 5) as usize;
+    data.version = version as i32;
+    data.ecc_level = if level == 0 { ECC_LEVEL_M } else { ECC_LEVEL_H };
+
+    read_data(code, ver, &mut ds);
+    codestream_ecc(ver.data_bytes, &ver.ecc[level], &mut ds, None)?;
+    decode_payload(&mut data, &mut ds, &modes(ver), None)?;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions() {
+        // The codewords fill the cells left over by the function patterns,
+        // up to a few remainder bits
+        for ver in &RMQR_VERSIONS {
+            let mut cells = 0;
+            for y in 0..ver.height {
+                for x in 0..ver.width {
+                    if !reserved_cell(ver, x, y) {
+                        cells += 1;
+                    }
+                }
+            }
+            assert_eq!(cells / 8, ver.data_bytes, "R{}x{}", ver.height, ver.width);
+
+            for ecc in &ver.ecc {
+                let long = (ver.data_bytes - ecc.bs * ecc.ns) / (ecc.bs + 1);
+                assert_eq!(ecc.bs * ecc.ns + (ecc.bs + 1) * long, ver.data_bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        for data in 0..64 {
+            let codeword = format_codeword(data);
+            assert_eq!(correct_format(codeword), Some((data, 0)));
+            assert_eq!(correct_format(codeword ^ 0x10101), Some((data, 3)));
+        }
+    }
+}
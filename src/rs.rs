@@ -0,0 +1,533 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2010-2012 Daniel Beer <dlbeer@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Reed-Solomon codes over GF(2^m), for m up to 8.
+//!
+//! This is the codec the QR-code decoder corrects its data blocks with,
+//! over `GF256` with a generator base of 0. A `ReedSolomon` code can be set
+//! up over any `GaloisField`, with any generator base and up to
+//! `RS_MAX_PARITY` parity symbols, for use outside of QR-codes. Blocks are
+//! stored highest order coefficient first, data followed by parity, one
+//! symbol per byte.
+//!
+//! ```
+//! use quirc_rs::rs::{GaloisField, ReedSolomon};
+//!
+//! let gf = GaloisField::new(0x11d).unwrap();
+//! let rs = ReedSolomon::new(&gf, 1, 4).unwrap();
+//!
+//! let mut block = *b"framed\0\0\0\0";
+//! rs.encode(&mut block).unwrap();
+//! block[2] ^= 0x55;
+//! block[8] ^= 0x01;
+//! assert_eq!(rs.decode(&mut block), Ok(2));
+//! assert_eq!(&block[..6], b"framed");
+//! ```
+
+use core::cmp::max;
+use core::fmt;
+
+pub(crate) const MAX_POLY: usize = 64;
+
+/// The most parity symbols a `ReedSolomon` code may have.
+pub const RS_MAX_PARITY: i32 = MAX_POLY as i32 - 1;
+
+/*************************************************************************
+ * Galois fields
+ */
+
+/// Exponent and logarithm tables of GF(2^m), built from a primitive
+/// polynomial of degree m.
+#[derive(Copy)]
+pub struct GaloisField {
+    /// Number of non-zero elements, 2^m - 1
+    pub(crate) p: i32,
+    /// Logarithms of the elements, base alpha. The logarithm of 0 is 0.
+    pub(crate) log: [u8; 256],
+    /// Powers of alpha up to alpha^p, which is 1 again
+    pub(crate) exp: [u8; 256],
+}
+
+impl Clone for GaloisField {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+/// Build the tables of the field generated by `poly`, and whether `poly`
+/// is primitive, so that the powers of alpha go through every non-zero
+/// element.
+const fn field(poly: u32) -> (GaloisField, bool) {
+    let mut gf = GaloisField {
+        p: 0,
+        log: [0; 256],
+        exp: [0; 256],
+    };
+    if poly < 1 << 2 || poly >= 1 << 9 {
+        return (gf, false);
+    }
+    let m = 31 - poly.leading_zeros();
+    let p: u32 = (1 << m) - 1;
+    gf.p = p as i32;
+
+    let mut primitive = true;
+    let mut x: u32 = 1;
+    let mut i = 0;
+    while i < p {
+        if i > 0 && x == 1 {
+            primitive = false;
+        }
+        gf.exp[i as usize] = x as u8;
+        gf.log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 1 << m != 0 {
+            x ^= poly;
+        }
+        i += 1;
+    }
+    gf.exp[p as usize] = 1;
+
+    (gf, primitive && x == 1)
+}
+
+/// GF(2^4) with the polynomial x^4 + x + 1, used by the BCH codes of the
+/// format information.
+pub const GF16: GaloisField = field(0x13).0;
+
+/// GF(2^8) with the polynomial x^8 + x^4 + x^3 + x^2 + 1, used by the
+/// Reed-Solomon codes of the data blocks.
+pub const GF256: GaloisField = field(0x11d).0;
+
+impl GaloisField {
+    /// The field generated by `poly`, given with its leading term, such as
+    /// 0x11d for x^8 + x^4 + x^3 + x^2 + 1. Returns `None` unless `poly` is
+    /// primitive and of degree 2 to 8.
+    pub fn new(poly: u32) -> Option<GaloisField> {
+        match field(poly) {
+            (gf, true) => Some(gf),
+            _ => None,
+        }
+    }
+
+    /// Number of elements of the field, 2^m.
+    pub fn size(&self) -> i32 {
+        self.p + 1
+    }
+
+    /// Alpha raised to the power `i`.
+    pub fn exp(&self, i: i32) -> u8 {
+        self.exp[i.rem_euclid(self.p) as usize]
+    }
+
+    /// Logarithm of `x`, which must be non-zero, base alpha.
+    pub fn log(&self, x: u8) -> i32 {
+        i32::from(self.log[x as usize])
+    }
+
+    /// Product of `a` and `b`.
+    pub fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            0
+        } else {
+            self.exp[((self.log(a) + self.log(b)) % self.p) as usize]
+        }
+    }
+}
+
+/************************************************************************
+ * Polynomial operations
+ */
+
+pub(crate) fn poly_add(
+    dst: &mut [u8; MAX_POLY],
+    src: &[u8; MAX_POLY],
+    c: u8,
+    shift: i32,
+    gf: &GaloisField,
+) {
+    if c == 0 {
+        return;
+    }
+
+    let log_c: i32 = i32::from(gf.log[c as usize]);
+    for i in 0..MAX_POLY as i32 {
+        let p = i + shift;
+        let v: u8 = src[i as usize];
+
+        if !(p < 0 || p >= MAX_POLY as i32) && v != 0 {
+            dst[p as usize] ^= gf.exp[((i32::from(gf.log[v as usize]) + log_c) % gf.p) as usize];
+        }
+    }
+}
+
+pub(crate) fn poly_eval(s: &[u8; MAX_POLY], x: u8, gf: &GaloisField) -> u8 {
+    if x == 0 {
+        s[0]
+    } else {
+        let mut sum: u8 = 0;
+        let log_x: u8 = gf.log[x as usize];
+
+        for i in 0..MAX_POLY as i32 {
+            let c: u8 = s[i as usize];
+
+            if c != 0 {
+                sum ^= gf.exp
+                    [((i32::from(gf.log[c as usize]) + i32::from(log_x) * i) % gf.p) as usize];
+            }
+        }
+        sum
+    }
+}
+
+/// Berlekamp-Massey algorithm for finding error locator polynomials.
+pub(crate) fn berlekamp_massey(s: &[u8; MAX_POLY], N: usize, gf: &GaloisField) -> [u8; MAX_POLY] {
+    let mut C: [u8; MAX_POLY] = [0; MAX_POLY];
+    let mut B: [u8; MAX_POLY] = [0; MAX_POLY];
+    let mut L: usize = 0;
+    let mut m: i32 = 1;
+    let mut b: u8 = 1;
+
+    B[0] = 1;
+    C[0] = 1;
+
+    for n in 0..N {
+        let mut d: u8 = s[n];
+
+        for i in 1..=L {
+            if C[i] != 0 && (s[n - i] != 0) {
+                d ^= gf.exp[((i32::from(gf.log[C[i] as usize])
+                    + i32::from(gf.log[s[n - i] as usize]))
+                    % gf.p) as usize];
+            }
+        }
+
+        let mult = gf.exp[((gf.p - i32::from(gf.log[b as usize]) + i32::from(gf.log[d as usize]))
+            % gf.p) as usize];
+
+        if d == 0 {
+            m += 1;
+        } else if L * 2 <= n {
+            let T = C;
+            poly_add(&mut C, &B, mult, m, gf);
+            B = T;
+            L = n + 1 - L;
+            b = d;
+            m = 1;
+        } else {
+            poly_add(&mut C, &B, mult, m, gf);
+            m += 1;
+        }
+    }
+
+    C
+}
+
+/************************************************************************
+ * Reed-Solomon codes
+ */
+
+/// This enum describes the errors which may occur while encoding or
+/// decoding a Reed-Solomon block.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RsError {
+    /// The block is no longer than its parity, or longer than the number
+    /// of non-zero elements of the field.
+    BlockLength { len: usize },
+    /// A symbol doesn't belong to the field.
+    Symbol { index: usize },
+    /// Error correction failed.
+    Uncorrectable {
+        /// Number of symbols the decoder managed to correct
+        corrected: i32,
+        /// Estimated number of symbols which could not be corrected, at
+        /// least one. Beyond the capacity of the code this is a guess.
+        uncorrectable: i32,
+    },
+}
+
+impl fmt::Display for RsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RsError::BlockLength { len } => write!(f, "Invalid block length: {}", len),
+            RsError::Symbol { index } => write!(f, "Invalid symbol at {}", index),
+            RsError::Uncorrectable {
+                corrected,
+                uncorrectable,
+            } => write!(
+                f,
+                "ECC failure ({} corrected, ~{} uncorrectable)",
+                corrected, uncorrectable
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RsError {}
+
+/// A Reed-Solomon code with `npar` parity symbols, whose generator
+/// polynomial has the roots alpha^base to alpha^(base + npar - 1).
+#[derive(Copy)]
+pub struct ReedSolomon<'a> {
+    gf: &'a GaloisField,
+    base: i32,
+    npar: i32,
+}
+
+impl Clone for ReedSolomon<'_> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a> ReedSolomon<'a> {
+    /// A code over `gf` with `npar` parity symbols and generator base
+    /// `base`. Returns `None` unless there are 1 to `RS_MAX_PARITY` parity
+    /// symbols, fewer than the non-zero elements of the field.
+    pub fn new(gf: &'a GaloisField, base: i32, npar: i32) -> Option<ReedSolomon<'a>> {
+        if !(1..=RS_MAX_PARITY).contains(&npar) || npar >= gf.p {
+            return None;
+        }
+
+        Some(ReedSolomon { gf, base, npar })
+    }
+
+    /// Number of parity symbols at the end of each block.
+    pub fn parity(&self) -> i32 {
+        self.npar
+    }
+
+    fn check(&self, block: &[u8]) -> Result<(), RsError> {
+        if block.len() <= self.npar as usize || block.len() > self.gf.p as usize {
+            return Err(RsError::BlockLength { len: block.len() });
+        }
+        match block.iter().position(|&c| i32::from(c) > self.gf.p) {
+            Some(index) => Err(RsError::Symbol { index }),
+            None => Ok(()),
+        }
+    }
+
+    /// Fill in the parity symbols at the end of `block` from the data
+    /// symbols before them.
+    pub fn encode(&self, block: &mut [u8]) -> Result<(), RsError> {
+        self.check(block)?;
+        let gf = self.gf;
+        let npar = self.npar as usize;
+
+        // Generator polynomial (x - a^base)...(x - a^(base + npar - 1)),
+        // lowest order coefficient first
+        let mut generator: [u8; MAX_POLY] = [0; MAX_POLY];
+        generator[0] = 1;
+        for i in 0..npar {
+            let root = gf.exp(self.base + i as i32);
+            for j in (0..=i + 1).rev() {
+                let lower = if j > 0 { generator[j - 1] } else { 0 };
+                generator[j] = lower ^ gf.mul(generator[j], root);
+            }
+        }
+
+        // Divide the data by the generator, shifting the remainder up
+        let (data, parity) = block.split_at_mut(block.len() - npar);
+        parity.iter_mut().for_each(|c| *c = 0);
+        for &c in data.iter() {
+            let factor = c ^ parity[0];
+            parity.copy_within(1.., 0);
+            parity[npar - 1] = 0;
+            for j in 0..npar {
+                parity[j] ^= gf.mul(generator[npar - 1 - j], factor);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Syndromes of `block`, or `None` if they're all zero.
+    fn syndromes(&self, block: &[u8]) -> Option<[u8; MAX_POLY]> {
+        let gf = self.gf;
+        let bs = block.len() as i32;
+        let mut nonzero = false;
+        let mut s: [u8; MAX_POLY] = [0; MAX_POLY];
+
+        for i in 0..self.npar {
+            let root: i32 = (self.base + i).rem_euclid(gf.p);
+            for j in 0..bs {
+                let c: u8 = block[(bs - j - 1) as usize];
+
+                if c != 0 {
+                    s[i as usize] ^=
+                        gf.exp[((i32::from(gf.log[c as usize]) + root * j) % gf.p) as usize];
+                }
+            }
+
+            if s[i as usize] != 0 {
+                nonzero = true;
+            }
+        }
+
+        if nonzero {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
+    /// Correct `block` in place, returning the number of corrected
+    /// symbols. Up to `parity() / 2` symbols can be corrected.
+    pub fn decode(&self, block: &mut [u8]) -> Result<i32, RsError> {
+        self.check(block)?;
+        let gf = self.gf;
+        let npar = self.npar as usize;
+        let bs = block.len() as i32;
+
+        /* Compute syndrome vector */
+        let s = match self.syndromes(block) {
+            Some(s) => s,
+            None => return Ok(0),
+        };
+
+        let sigma = berlekamp_massey(&s, npar, gf);
+
+        /* Compute derivative of sigma */
+        let mut sigma_deriv: [u8; MAX_POLY] = [0; MAX_POLY];
+        for i in (0..MAX_POLY - 1).step_by(2) {
+            sigma_deriv[i] = sigma[i + 1];
+        }
+
+        /* Compute error evaluator polynomial, S(x) sigma(x) mod x^npar */
+        let mut omega: [u8; MAX_POLY] = [0; MAX_POLY];
+        for i in 0..npar {
+            for j in 0..npar - i {
+                omega[i + j] ^= gf.mul(sigma[i], s[j]);
+            }
+        }
+
+        /* Find error locations and magnitudes (Forney) */
+        let shift: i32 = (1 - self.base).rem_euclid(gf.p);
+        let mut corrected: i32 = 0;
+        for i in 0..bs {
+            let xinv: u8 = gf.exp[(gf.p - i) as usize];
+
+            if poly_eval(&sigma, xinv, gf) == 0 {
+                let sd_x: u8 = poly_eval(&sigma_deriv, xinv, gf);
+                let omega_x: u8 = poly_eval(&omega, xinv, gf);
+                if sd_x == 0 || omega_x == 0 {
+                    continue;
+                }
+                let error: u8 =
+                    gf.exp[((shift * i + gf.p - gf.log(sd_x) + gf.log(omega_x)) % gf.p) as usize];
+
+                block[(bs - i - 1) as usize] ^= error;
+                corrected += 1;
+            }
+        }
+
+        if self.syndromes(block).is_some() {
+            // The degree of sigma is the number of errors the locator claims
+            // to have found. Any that weren't roots couldn't be corrected,
+            // though with that many errors the claim itself is unreliable.
+            let degree = sigma.iter().rposition(|&c| c != 0).unwrap_or(0) as i32;
+            Err(RsError::Uncorrectable {
+                corrected,
+                uncorrectable: max(degree - corrected, 1),
+            })
+        } else {
+            Ok(corrected)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        assert_eq!(GF256.exp[8], 0x1d);
+        assert_eq!(GF256.log[0x1d], 8);
+        assert_eq!(GF256.exp[255], 1);
+        assert_eq!(GF16.exp[4], 0x03);
+        assert_eq!(GF16.log[0x09], 14);
+        assert_eq!(GF16.exp[15], 1);
+
+        // x^4 + x^3 + x^2 + x + 1 divides x^5 + 1, so it isn't primitive
+        assert!(GaloisField::new(0x1f).is_none());
+        assert!(GaloisField::new(0x11b).is_none());
+        assert!(GaloisField::new(0x3).is_none());
+        assert!(GaloisField::new(0x201).is_none());
+        assert_eq!(GaloisField::new(0x25).map(|gf| gf.size()), Some(32));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let gf32 = GaloisField::new(0x25).unwrap();
+        for &(gf, base, npar) in &[
+            (&GF256, 0, 10),
+            (&GF256, 1, 7),
+            (&GF16, 1, 4),
+            (&gf32, 5, 6),
+        ] {
+            let rs = ReedSolomon::new(gf, base, npar).unwrap();
+            let len = (gf.p as usize).min(40);
+            let mut block: alloc::vec::Vec<u8> = (0..len)
+                .map(|i| ((i * 7 + 3) % gf.size() as usize) as u8)
+                .collect();
+            rs.encode(&mut block).unwrap();
+            let clean = block.clone();
+            assert_eq!(rs.decode(&mut block), Ok(0));
+
+            // As many errors as can be corrected, including in the parity
+            let t = npar as usize / 2;
+            for k in 0..t {
+                let i = if k + 1 == t { len - 1 } else { k * 3 + 1 };
+                block[i] ^= (k + 1) as u8;
+            }
+            assert_eq!(rs.decode(&mut block), Ok(t as i32), "{} {}", base, npar);
+            assert_eq!(block, clean);
+        }
+    }
+
+    #[test]
+    fn test_uncorrectable() {
+        let rs = ReedSolomon::new(&GF256, 0, 10).unwrap();
+        let mut block = [0x5a; 30];
+        rs.encode(&mut block).unwrap();
+        for i in 0..6 {
+            block[i * 5] ^= 0x81;
+        }
+        assert!(matches!(
+            rs.decode(&mut block),
+            Err(RsError::Uncorrectable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(ReedSolomon::new(&GF16, 0, 15).is_none());
+        assert!(ReedSolomon::new(&GF256, 0, 0).is_none());
+        assert!(ReedSolomon::new(&GF256, 0, RS_MAX_PARITY + 1).is_none());
+
+        let rs = ReedSolomon::new(&GF16, 0, 4).unwrap();
+        assert_eq!(
+            rs.encode(&mut [0; 16]),
+            Err(RsError::BlockLength { len: 16 })
+        );
+        assert_eq!(rs.decode(&mut [0; 4]), Err(RsError::BlockLength { len: 4 }));
+        assert_eq!(
+            rs.encode(&mut [1, 2, 16, 0, 0, 0, 0, 0]),
+            Err(RsError::Symbol { index: 2 })
+        );
+    }
+}
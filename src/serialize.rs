@@ -0,0 +1,214 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Serde support for the types which can't simply derive it, enabled by
+//! the `serde` feature.
+//!
+//! Codes are serialized with their cell bitmap as one string per row,
+//! "#" for black cells and "." for white ones, so `Code` and `QuircCode`
+//! have the same representation. Likewise `QuircData` is serialized as a
+//! `Data` with no segments, its payload trimmed to `payload_len`.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::quirc::consts::*;
+use crate::quirc::*;
+use crate::version_db::QUIRC_MAX_GRID_SIZE;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Code")]
+struct CodeRepr {
+    corners: [Point; 4],
+    size: i32,
+    rows: Vec<String>,
+}
+
+impl From<&Code> for CodeRepr {
+    fn from(code: &Code) -> Self {
+        let rows = (0..code.size)
+            .map(|y| {
+                (0..code.size)
+                    .map(|x| if code.cell(x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect();
+
+        CodeRepr {
+            corners: code.corners,
+            size: code.size,
+            rows,
+        }
+    }
+}
+
+impl CodeRepr {
+    fn into_code<E: Error>(self) -> core::result::Result<Code, E> {
+        if self.size < 0 || self.size as usize > QUIRC_MAX_GRID_SIZE {
+            return Err(E::custom("code is too large"));
+        }
+        if self.rows.len() != self.size as usize {
+            return Err(E::invalid_length(self.rows.len(), &"`size` rows"));
+        }
+
+        let size = self.size as usize;
+        let mut cell_bitmap = vec![0; ((self.size * self.size + 7) / 8) as usize];
+
+        for (y, row) in self.rows.iter().enumerate() {
+            if row.len() != size {
+                return Err(E::invalid_length(row.len(), &"`size` cells in each row"));
+            }
+            for (x, c) in row.bytes().enumerate() {
+                let i = y * size + x;
+                match c {
+                    b'#' => cell_bitmap[i >> 3] |= 1 << (i & 7),
+                    b'.' => (),
+                    _ => return Err(E::custom("cells must be '#' or '.'")),
+                }
+            }
+        }
+
+        Ok(Code {
+            corners: self.corners,
+            size: self.size,
+            cell_bitmap,
+        })
+    }
+}
+
+impl Serialize for Code {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        CodeRepr::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Code {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        CodeRepr::deserialize(deserializer)?.into_code()
+    }
+}
+
+impl Serialize for QuircCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        let code = Code::try_from(self)
+            .map_err(|_| <S::Error as serde::ser::Error>::custom("invalid grid size"))?;
+        CodeRepr::from(&code).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for QuircCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        let code: Code = CodeRepr::deserialize(deserializer)?.into_code()?;
+        QuircCode::try_from(&code).map_err(|_| D::Error::custom("code is too large"))
+    }
+}
+
+impl Serialize for QuircData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        Data::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for QuircData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        let data = Data::deserialize(deserializer)?;
+        if data.payload.len() >= MAX_PAYLOAD {
+            return Err(D::Error::custom("payload is too long"));
+        }
+        Ok(QuircData::from(&data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code() -> Code {
+        let mut code = Code {
+            corners: [
+                Point { x: 1, y: 2 },
+                Point { x: 10, y: 2 },
+                Point { x: 10, y: 11 },
+                Point { x: 1, y: 11 },
+            ],
+            size: 3,
+            cell_bitmap: vec![0; 2],
+        };
+        for &i in &[0, 4, 5, 8] {
+            code.cell_bitmap[i >> 3] |= 1 << (i & 7);
+        }
+        code
+    }
+
+    #[test]
+    fn test_code() {
+        let json = serde_json::to_value(code()).unwrap();
+        assert_eq!(json["rows"], serde_json::json!(["#..", ".##", "..#"]));
+        assert_eq!(json["corners"][1], serde_json::json!({"x": 10, "y": 2}));
+
+        let quirc_code = QuircCode::try_from(&code()).unwrap();
+        assert_eq!(serde_json::to_value(quirc_code).unwrap(), json);
+
+        let back: QuircCode = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(Code::try_from(&back).unwrap(), code());
+
+        let mut bad = json;
+        bad["rows"][2] = serde_json::json!("..x");
+        assert!(serde_json::from_value::<Code>(bad.clone()).is_err());
+        bad["rows"][2] = serde_json::json!("..");
+        assert!(serde_json::from_value::<Code>(bad.clone()).is_err());
+
+        // Rejected before the bitmap is allocated
+        let size = 46341;
+        bad["size"] = serde_json::json!(size);
+        bad["rows"] = serde_json::json!(vec![""; size]);
+        assert!(serde_json::from_value::<Code>(bad.clone()).is_err());
+        assert!(serde_json::from_value::<QuircCode>(bad).is_err());
+    }
+
+    #[test]
+    fn test_data() {
+        let data = Data {
+            version: 1,
+            ecc_level: ECC_LEVEL_M,
+            data_type: DATA_TYPE_BYTE,
+            payload: b"hello".to_vec(),
+            ..Default::default()
+        };
+        let quirc_data = QuircData::from(&data);
+
+        let json = serde_json::to_value(quirc_data).unwrap();
+        assert_eq!(json, serde_json::to_value(&data).unwrap());
+        assert_eq!(json["payload"], serde_json::json!(b"hello"));
+
+        let back: QuircData = serde_json::from_value(json).unwrap();
+        assert_eq!(back.payload_len, 5);
+        assert_eq!(&back.payload[..6], b"hello\0");
+
+        let long = Data {
+            payload: vec![0; MAX_PAYLOAD],
+            ..data
+        };
+        let json = serde_json::to_value(&long).unwrap();
+        assert!(serde_json::from_value::<Data>(json.clone()).is_ok());
+        assert!(serde_json::from_value::<QuircData>(json).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Conversion of decoded payloads to text.
+
+use alloc::string::String;
+use core::fmt;
+
+use encoding_rs::Encoding;
+
+use crate::decode::Segment;
+use crate::quirc::consts::*;
+use crate::quirc::Data;
+
+/// This enum describes the errors which may occur while converting a
+/// payload to text.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextError {
+    /// The ECI assignment number doesn't denote a known character set.
+    UnsupportedEci(u32),
+    /// The payload is not valid in the character set it was declared in.
+    Malformed { charset: &'static str },
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextError::UnsupportedEci(eci) => write!(f, "Unsupported ECI: {}", eci),
+            TextError::Malformed { charset } => write!(f, "Malformed {} text", charset),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TextError {}
+
+/// Upper half of code page 437.
+const IBM437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Charset {
+    Ascii,
+    Latin1,
+    Ibm437,
+    /// An encoding known to the WHATWG encoding standard
+    Whatwg(&'static Encoding),
+    /// A WHATWG single-byte encoding which decodes 0x80-0x9f as C1 control
+    /// characters. ISO-8859-9 only differs from windows-1254 in that range.
+    WhatwgC1(&'static Encoding),
+}
+
+impl Charset {
+    /// Look up the character set designated by an ECI assignment number.
+    pub(crate) fn from_eci(eci: u32) -> Option<Charset> {
+        use encoding_rs::*;
+
+        let charset = match eci as i32 {
+            // ECI 000001 is the GLI equivalent of 000003. So is 000000 of
+            // 000002, but it can't be told apart from no ECI at all.
+            ECI_IBM437 => Charset::Ibm437,
+            3 | ECI_ISO_8859_1 => Charset::Latin1,
+            ECI_ISO_8859_2 => Charset::Whatwg(ISO_8859_2),
+            ECI_ISO_8859_3 => Charset::Whatwg(ISO_8859_3),
+            ECI_ISO_8859_4 => Charset::Whatwg(ISO_8859_4),
+            ECI_ISO_8859_5 => Charset::Whatwg(ISO_8859_5),
+            ECI_ISO_8859_6 => Charset::Whatwg(ISO_8859_6),
+            ECI_ISO_8859_7 => Charset::Whatwg(ISO_8859_7),
+            ECI_ISO_8859_8 => Charset::Whatwg(ISO_8859_8),
+            ECI_ISO_8859_9 => Charset::WhatwgC1(WINDOWS_1254),
+            ECI_ISO_8859_10 => Charset::Whatwg(ISO_8859_10),
+            ECI_WINDOWS_874 => Charset::Whatwg(WINDOWS_874),
+            ECI_ISO_8859_13 => Charset::Whatwg(ISO_8859_13),
+            ECI_ISO_8859_14 => Charset::Whatwg(ISO_8859_14),
+            ECI_ISO_8859_15 => Charset::Whatwg(ISO_8859_15),
+            ECI_ISO_8859_16 => Charset::Whatwg(ISO_8859_16),
+            ECI_SHIFT_JIS => Charset::Whatwg(SHIFT_JIS),
+            ECI_WINDOWS_1250 => Charset::Whatwg(WINDOWS_1250),
+            ECI_WINDOWS_1251 => Charset::Whatwg(WINDOWS_1251),
+            ECI_WINDOWS_1252 => Charset::Whatwg(WINDOWS_1252),
+            ECI_WINDOWS_1256 => Charset::Whatwg(WINDOWS_1256),
+            ECI_UTF_16BE => Charset::Whatwg(UTF_16BE),
+            ECI_UTF_8 => Charset::Whatwg(UTF_8),
+            ECI_US_ASCII => Charset::Ascii,
+            ECI_BIG5 => Charset::Whatwg(BIG5),
+            ECI_GB18030 => Charset::Whatwg(GB18030),
+            ECI_EUC_KR => Charset::Whatwg(EUC_KR),
+            _ => return None,
+        };
+
+        Some(charset)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Charset::Ascii => "US-ASCII",
+            Charset::Latin1 => "ISO-8859-1",
+            Charset::Ibm437 => "IBM437",
+            Charset::Whatwg(encoding) => encoding.name(),
+            Charset::WhatwgC1(_) => "ISO-8859-9",
+        }
+    }
+
+    /// Decode `bytes`, appending the result to `out`.
+    pub(crate) fn decode(self, bytes: &[u8], out: &mut String) -> Result<(), TextError> {
+        let malformed = TextError::Malformed {
+            charset: self.name(),
+        };
+
+        match self {
+            Charset::Ascii => {
+                if !bytes.is_ascii() {
+                    return Err(malformed);
+                }
+                out.extend(bytes.iter().map(|&b| char::from(b)));
+            }
+            Charset::Latin1 => out.extend(bytes.iter().map(|&b| char::from(b))),
+            Charset::Ibm437 => out.extend(bytes.iter().map(|&b| {
+                if b < 0x80 {
+                    char::from(b)
+                } else {
+                    IBM437_HIGH[(b - 0x80) as usize]
+                }
+            })),
+            Charset::Whatwg(encoding) => {
+                let text = encoding
+                    .decode_without_bom_handling_and_without_replacement(bytes)
+                    .ok_or(malformed)?;
+                out.push_str(&text);
+            }
+            Charset::WhatwgC1(encoding) => {
+                for run in bytes.split_inclusive(|b| (0x80..=0x9f).contains(b)) {
+                    let (text, c1) = match run.split_last() {
+                        Some((&b, text)) if (0x80..=0x9f).contains(&b) => (text, Some(b)),
+                        _ => (run, None),
+                    };
+                    Charset::Whatwg(encoding).decode(text, out)?;
+                    out.extend(c1.map(char::from));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Pick the character set of a payload which has no ECI designator: UTF-8
+/// if the bytes are valid UTF-8, ISO-8859-1 otherwise.
+pub(crate) fn guess_charset(bytes: &[u8]) -> Charset {
+    if core::str::from_utf8(bytes).is_ok() {
+        Charset::Whatwg(encoding_rs::UTF_8)
+    } else {
+        Charset::Latin1
+    }
+}
+
+impl Data {
+    /// Decode the payload as text, each segment with its own character
+    /// set (see `segments_text()`).
+    ///
+    /// If the segments aren't known, as when the data was converted from
+    /// a `QuircData`, the payload is taken as a single segment of
+    /// `data_type` with `eci` in effect.
+    pub fn payload_text(&self) -> Result<String, TextError> {
+        if !self.segments.is_empty() || self.payload.is_empty() {
+            return segments_text(&self.segments);
+        }
+
+        segments_text(&[Segment {
+            mode: self.data_type,
+            eci: self.eci,
+            bit_offset: 0,
+            count: 0,
+            data: self.payload.clone(),
+        }])
+    }
+}
+
+/// Decode a sequence of segments as text, using the character set in
+/// effect for each of them.
+///
+/// Numeric and alphanumeric segments are always ASCII, and Kanji
+/// segments always Shift-JIS. Byte segments outside of any ECI are
+/// decoded as UTF-8 or ISO-8859-1, whichever fits.
+pub fn segments_text(segments: &[Segment]) -> Result<String, TextError> {
+    let mut text = String::new();
+
+    for segment in segments {
+        let charset = match segment.mode {
+            DATA_TYPE_ECI => continue,
+            DATA_TYPE_KANJI => Charset::Whatwg(encoding_rs::SHIFT_JIS),
+            DATA_TYPE_BYTE if segment.eci != 0 => {
+                Charset::from_eci(segment.eci).ok_or(TextError::UnsupportedEci(segment.eci))?
+            }
+            DATA_TYPE_BYTE => guess_charset(&segment.data),
+            _ => Charset::Ascii,
+        };
+
+        charset.decode(&segment.data, &mut text)?;
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirc::QuircData;
+
+    fn data(payload: &[u8], data_type: i32, eci: u32) -> Data {
+        let mut data = QuircData {
+            data_type,
+            eci,
+            payload_len: payload.len() as i32,
+            ..Default::default()
+        };
+        data.payload[..payload.len()].copy_from_slice(payload);
+        Data::from(&data)
+    }
+
+    fn segment(mode: i32, eci: u32, data: &[u8]) -> Segment {
+        Segment {
+            mode,
+            eci,
+            bit_offset: 0,
+            count: data.len() as i32,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_payload_text() {
+        let text = |payload: &[u8], data_type, eci| data(payload, data_type, eci).payload_text();
+
+        assert_eq!(text(b"hello", DATA_TYPE_BYTE, 0), Ok("hello".to_owned()));
+        assert_eq!(
+            text("héllo".as_bytes(), DATA_TYPE_BYTE, 0),
+            Ok("héllo".to_owned())
+        );
+        assert_eq!(text(b"h\xe9llo", DATA_TYPE_BYTE, 0), Ok("héllo".to_owned()));
+        assert_eq!(
+            text(b"\x82\xa0", DATA_TYPE_KANJI, 0),
+            Ok("\u{3042}".to_owned())
+        );
+        assert_eq!(
+            text(b"\xb0\x80", DATA_TYPE_BYTE, ECI_IBM437 as u32),
+            Ok("░Ç".to_owned())
+        );
+        assert_eq!(
+            text(b"\xd0\x9f\xfd", DATA_TYPE_BYTE, ECI_ISO_8859_9 as u32),
+            Ok("Ğ\u{9f}ı".to_owned())
+        );
+        assert_eq!(
+            text(b"\xff", DATA_TYPE_BYTE, ECI_UTF_8 as u32),
+            Err(TextError::Malformed { charset: "UTF-8" })
+        );
+        assert_eq!(
+            text(b"x", DATA_TYPE_BYTE, 14),
+            Err(TextError::UnsupportedEci(14))
+        );
+
+        // Each segment is decoded on its own, not the whole payload as
+        // Shift-JIS for being Kanji
+        let mixed = Data {
+            segments: vec![
+                segment(DATA_TYPE_KANJI, 0, b"\x82\xa0"),
+                segment(DATA_TYPE_BYTE, 0, "é".as_bytes()),
+            ],
+            ..data(b"\x82\xa0\xc3\xa9", DATA_TYPE_KANJI, 0)
+        };
+        assert_eq!(mixed.payload_text(), Ok("\u{3042}é".to_owned()));
+    }
+
+    #[test]
+    fn test_segments_text() {
+        let segments = [
+            segment(DATA_TYPE_NUMERIC, 0, b"42"),
+            segment(DATA_TYPE_ECI, ECI_ISO_8859_7 as u32, b""),
+            segment(DATA_TYPE_BYTE, ECI_ISO_8859_7 as u32, b"\xe1"),
+            segment(DATA_TYPE_KANJI, ECI_ISO_8859_7 as u32, b"\x82\xa0"),
+        ];
+
+        assert_eq!(segments_text(&segments), Ok("42α\u{3042}".to_owned()));
+    }
+}
@@ -0,0 +1,336 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Following codes across the frames of a video.
+//!
+//! `quirc_track()` takes the place of `quirc_identify()` for consecutive
+//! frames of the same stream. Between full-frame scans it only looks for
+//! capstones where each code is expected to be, predicted from its last
+//! two sightings, and starts the perspective search from the transform of
+//! the previous frame if that fits better. Each code keeps its track ID
+//! for as long as it's followed.
+//!
+//! ```
+//! use quirc_rs::identify::quirc_extract;
+//! use quirc_rs::quirc::{Image, Quirc};
+//! use quirc_rs::track::{quirc_track, Tracker};
+//!
+//! let mut tracker = Tracker::new();
+//! for _frame in 0..3 {
+//!     let mut pixels = vec![0xff; 64 * 48];
+//!     let mut q = Quirc::new(Image::new(64, 48, &mut pixels));
+//!     quirc_track(&mut q, &mut tracker);
+//!     for (i, &id) in tracker.ids().iter().enumerate() {
+//!         let code = quirc_extract(&mut q, i as i32);
+//!         println!("track {}: {:?}", id, code.is_some());
+//!     }
+//! }
+//! ```
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::identify::*;
+use crate::observer::Event;
+use crate::quirc::consts::*;
+use crate::quirc::*;
+
+/// A code followed from frame to frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Track {
+    /// Stays the same for as long as the code is tracked
+    pub id: u32,
+    pub grid_size: i32,
+
+    /// Perspective transform of the grid when it was last seen
+    pub c: [f64; PERSPECTIVE_PARAMS],
+
+    /// Movement of the centre of the code per frame, in pixels
+    pub velocity: (f64, f64),
+
+    /// Number of frames since the code was last seen
+    pub missed: u32,
+}
+
+/// State kept by `quirc_track()` between frames.
+#[derive(Clone, Debug)]
+pub struct Tracker {
+    /// Every this many frames the whole frame is scanned, to pick up new
+    /// codes. 1 scans every frame in full.
+    pub full_scan_interval: u32,
+
+    /// Tracks are dropped after their code was missed in this many frames
+    /// in a row.
+    pub max_missed: u32,
+
+    tracks: Vec<Track>,
+    ids: Vec<u32>,
+    next_id: u32,
+    since_full_scan: u32,
+    full_scan: bool,
+    lost: bool,
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Tracker {
+            full_scan_interval: 30,
+            max_missed: 5,
+            tracks: Vec::new(),
+            ids: Vec::new(),
+            next_id: 0,
+            since_full_scan: 0,
+            full_scan: false,
+            lost: false,
+        }
+    }
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The codes being followed, including those missed in the last few
+    /// frames.
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Track IDs of the grids found by the last `quirc_track()`, in the
+    /// order of `Quirc::grids`.
+    pub fn ids(&self) -> &[u32] {
+        &self.ids
+    }
+
+    /// Whether the last frame was scanned in full.
+    pub fn full_scan(&self) -> bool {
+        self.full_scan
+    }
+}
+
+/// Shift a perspective transform by (dx, dy) pixels.
+fn translate(c: &[f64; PERSPECTIVE_PARAMS], dx: f64, dy: f64) -> [f64; PERSPECTIVE_PARAMS] {
+    [
+        c[0] + dx * c[6],
+        c[1] + dx * c[7],
+        c[2] + dx,
+        c[3] + dy * c[6],
+        c[4] + dy * c[7],
+        c[5] + dy,
+        c[6],
+        c[7],
+    ]
+}
+
+fn centre(c: &[f64; PERSPECTIVE_PARAMS], grid_size: i32) -> (f64, f64) {
+    let half = f64::from(grid_size) / 2.0;
+    perspective_map_exact(c, half, half)
+}
+
+/// Where a track's code is expected in the current frame.
+fn predict(track: &Track) -> [f64; PERSPECTIVE_PARAMS] {
+    let frames = f64::from(track.missed + 1);
+    translate(
+        &track.c,
+        track.velocity.0 * frames,
+        track.velocity.1 * frames,
+    )
+}
+
+/// Look for capstones around the predicted position of a code: its
+/// bounding box, grown by a quarter of its size on each side.
+fn scan_around(q: &mut Quirc, c: &[f64; PERSPECTIVE_PARAMS], grid_size: i32) {
+    let s = f64::from(grid_size);
+    let corners = [(0.0, 0.0), (s, 0.0), (s, s), (0.0, s)];
+    let (mut x0, mut y0) = (f64::MAX, f64::MAX);
+    let (mut x1, mut y1) = (f64::MIN, f64::MIN);
+
+    for &(u, v) in &corners {
+        let (x, y) = perspective_map_exact(c, u, v);
+        x0 = x0.min(x);
+        y0 = y0.min(y);
+        x1 = x1.max(x);
+        y1 = y1.max(y);
+    }
+
+    let margin = ((x1 - x0).max(y1 - y0) / 4.0).max(8.0);
+    let clamp = |v: f64, limit: i32| v.max(0.0).min(f64::from(limit)) as i32;
+    let left = clamp(x0 - margin, q.image.w);
+    let right = clamp(x1 + margin, q.image.w);
+
+    for y in clamp(y0 - margin, q.image.h)..clamp(y1 + margin, q.image.h) {
+        finder_scan(q, &mut |_: &Event| (), y, left, right);
+    }
+}
+
+/// Start the perspective search of `grid` from `seed` if that fits the
+/// image better than the transform found from its capstones.
+fn reseed(image: &Image, grid: &mut Grid, seed: &[f64; PERSPECTIVE_PARAMS]) {
+    let mut seeded = *grid;
+    seeded.c = *seed;
+
+    if fitness_all(image, &mut seeded) > fitness_all(image, grid) {
+        let fitness = jiggle_perspective(image, &mut seeded, &mut |_: &Event| ());
+        grid.c = seeded.c;
+        grid.score = grid_score(grid, fitness);
+    }
+}
+
+/// Identify the codes in a frame of a video, like `quirc_identify()`,
+/// using what was found in the previous frames.
+///
+/// The whole frame is scanned every `full_scan_interval` frames, when
+/// nothing is being tracked, and after a tracked code was lost. In other
+/// frames only the surroundings of the tracked codes are, so new codes
+/// elsewhere are picked up at the next full scan.
+pub fn quirc_track(q: &mut Quirc, tracker: &mut Tracker) {
+    let full = tracker.tracks.is_empty()
+        || tracker.lost
+        || tracker.since_full_scan + 1 >= tracker.full_scan_interval;
+
+    binarize(q);
+
+    if full {
+        for y in 0..q.image.h {
+            finder_scan(q, &mut |_: &Event| (), y, 0, q.image.w);
+        }
+        tracker.since_full_scan = 0;
+    } else {
+        for track in &tracker.tracks {
+            scan_around(q, &predict(track), track.grid_size);
+        }
+        tracker.since_full_scan += 1;
+    }
+    tracker.full_scan = full;
+
+    group_capstones(q, &mut |_: &Event| ());
+
+    // Match each grid to the nearest unmatched track of the same size
+    // whose predicted centre is within half a code's width of its own
+    let existing = tracker.tracks.len();
+    let mut matched = vec![false; existing];
+    tracker.ids.clear();
+
+    for grid in q.grids.iter_mut() {
+        let (x, y) = centre(&grid.c, grid.grid_size);
+        let mut best: Option<(usize, f64, [f64; PERSPECTIVE_PARAMS])> = None;
+
+        for (i, track) in tracker.tracks[..existing].iter().enumerate() {
+            if matched[i] || track.grid_size != grid.grid_size {
+                continue;
+            }
+
+            let c = predict(track);
+            let (px, py) = centre(&c, track.grid_size);
+            let (ex, ey) = perspective_map_exact(&c, 0.0, 0.0);
+            let radius2 = (px - ex) * (px - ex) + (py - ey) * (py - ey);
+            let distance2 = (x - px) * (x - px) + (y - py) * (y - py);
+
+            let closer = match best {
+                Some((_, d, _)) => distance2 < d,
+                None => true,
+            };
+            if distance2 < radius2 && closer {
+                best = Some((i, distance2, c));
+            }
+        }
+
+        match best {
+            Some((i, _, c)) => {
+                reseed(&q.image, grid, &c);
+
+                let track = &mut tracker.tracks[i];
+                let (ox, oy) = centre(&track.c, track.grid_size);
+                let (nx, ny) = centre(&grid.c, grid.grid_size);
+                let frames = f64::from(track.missed + 1);
+                track.velocity = ((nx - ox) / frames, (ny - oy) / frames);
+                track.c = grid.c;
+                track.missed = 0;
+
+                matched[i] = true;
+                tracker.ids.push(track.id);
+            }
+            None => {
+                tracker.tracks.push(Track {
+                    id: tracker.next_id,
+                    grid_size: grid.grid_size,
+                    c: grid.c,
+                    velocity: (0.0, 0.0),
+                    missed: 0,
+                });
+                tracker.ids.push(tracker.next_id);
+                tracker.next_id = tracker.next_id.wrapping_add(1);
+            }
+        }
+    }
+
+    // Age the tracks which weren't seen, and drop the ones gone for too
+    // long
+    tracker.lost = false;
+    for (i, track) in tracker.tracks[..existing].iter_mut().enumerate() {
+        if !matched[i] {
+            track.missed += 1;
+            tracker.lost = true;
+        }
+    }
+    let max_missed = tracker.max_missed;
+    tracker.tracks.retain(|track| track.missed <= max_missed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A transform with some perspective, so that `translate()` has to
+    /// account for the denominator.
+    const C: [f64; PERSPECTIVE_PARAMS] = [4.0, 0.5, 30.0, -0.3, 3.5, 40.0, 0.002, -0.001];
+
+    fn assert_near(a: (f64, f64), b: (f64, f64)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9,
+            "{:?} {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn test_translate() {
+        let moved = translate(&C, 12.5, -7.0);
+        for &(u, v) in &[(0.0, 0.0), (25.0, 0.0), (3.0, 17.0), (25.0, 25.0)] {
+            let (x, y) = perspective_map_exact(&C, u, v);
+            assert_near(perspective_map_exact(&moved, u, v), (x + 12.5, y - 7.0));
+        }
+    }
+
+    #[test]
+    fn test_predict() {
+        let mut track = Track {
+            id: 0,
+            grid_size: 25,
+            c: C,
+            velocity: (3.0, -2.0),
+            missed: 0,
+        };
+        let (x, y) = centre(&C, 25);
+        assert_near(centre(&predict(&track), 25), (x + 3.0, y - 2.0));
+
+        // Missed frames move the code on at the same speed
+        track.missed = 2;
+        assert_near(centre(&predict(&track), 25), (x + 9.0, y - 6.0));
+    }
+}
@@ -14,11 +14,20 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-///! QR-code version information database
+//! QR-code version information database
+
+#[cfg(feature = "synth")]
+use alloc::{vec, vec::Vec};
 
 pub const QUIRC_MAX_VERSION: usize = 40;
 pub const QUIRC_MAX_ALIGNMENT: usize = 7;
 
+/// Width of the largest QR-code, in cells
+pub const QUIRC_MAX_GRID_SIZE: usize = QUIRC_MAX_VERSION * 4 + 17;
+
+/// Highest version of a Model 1 QR-code
+pub const MODEL1_MAX_VERSION: usize = 14;
+
 #[derive(Copy)]
 #[repr(C)]
 pub struct RsParams {
@@ -38,6 +47,22 @@ impl Clone for RsParams {
     }
 }
 
+#[cfg(feature = "synth")]
+impl RsParams {
+    /// The blocks of a code of `data_bytes` codewords with these short
+    /// blocks, as (data words, total words) pairs: the short blocks
+    /// first, then the long ones, a word longer each.
+    pub fn blocks(&self, data_bytes: i32) -> Vec<(usize, usize)> {
+        let lb_count = (data_bytes - self.bs * self.ns) / (self.bs + 1);
+
+        let small = (self.dw as usize, self.bs as usize);
+        let large = (self.dw as usize + 1, self.bs as usize + 1);
+        let mut blocks = vec![small; self.ns as usize];
+        blocks.extend(vec![large; lb_count as usize]);
+        blocks
+    }
+}
+
 #[derive(Copy)]
 #[repr(C)]
 pub struct VersionInfo {
@@ -605,7 +630,7 @@ pub const VERSION_DB: [VersionInfo; QUIRC_MAX_VERSION + 1] = [
     // Version 21
     VersionInfo {
         data_bytes: 1156,
-        apat: [6, 28, 50, 72, 92, 0, 0],
+        apat: [6, 28, 50, 72, 94, 0, 0],
         ecc: [
             RsParams {
                 bs: 68,
@@ -1143,3 +1168,418 @@ pub const VERSION_DB: [VersionInfo; QUIRC_MAX_VERSION + 1] = [
         ],
     },
 ];
+
+/// Model 1 QR-codes, which have no alignment patterns. Their codewords
+/// are split into blocks like those of Model 2 codes, as laid out in
+/// `VERSION_DB`.
+pub const MODEL1_VERSION_DB: [VersionInfo; MODEL1_MAX_VERSION + 1] = [
+    VersionInfo {
+        data_bytes: 0,
+        apat: [0; 7],
+        ecc: [RsParams {
+            bs: 0,
+            dw: 0,
+            ns: 0,
+        }; 4],
+    },
+    // Version 1
+    VersionInfo {
+        data_bytes: 26,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 26,
+                dw: 16,
+                ns: 1,
+            },
+            RsParams {
+                bs: 26,
+                dw: 19,
+                ns: 1,
+            },
+            RsParams {
+                bs: 26,
+                dw: 9,
+                ns: 1,
+            },
+            RsParams {
+                bs: 26,
+                dw: 13,
+                ns: 1,
+            },
+        ],
+    },
+    // Version 2
+    VersionInfo {
+        data_bytes: 46,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 46,
+                dw: 30,
+                ns: 1,
+            },
+            RsParams {
+                bs: 46,
+                dw: 36,
+                ns: 1,
+            },
+            RsParams {
+                bs: 46,
+                dw: 16,
+                ns: 1,
+            },
+            RsParams {
+                bs: 46,
+                dw: 24,
+                ns: 1,
+            },
+        ],
+    },
+    // Version 3
+    VersionInfo {
+        data_bytes: 72,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 72,
+                dw: 44,
+                ns: 1,
+            },
+            RsParams {
+                bs: 72,
+                dw: 57,
+                ns: 1,
+            },
+            RsParams {
+                bs: 72,
+                dw: 24,
+                ns: 1,
+            },
+            RsParams {
+                bs: 72,
+                dw: 36,
+                ns: 1,
+            },
+        ],
+    },
+    // Version 4
+    VersionInfo {
+        data_bytes: 100,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 100,
+                dw: 60,
+                ns: 1,
+            },
+            RsParams {
+                bs: 100,
+                dw: 80,
+                ns: 1,
+            },
+            RsParams {
+                bs: 50,
+                dw: 17,
+                ns: 2,
+            },
+            RsParams {
+                bs: 100,
+                dw: 50,
+                ns: 1,
+            },
+        ],
+    },
+    // Version 5
+    VersionInfo {
+        data_bytes: 134,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 134,
+                dw: 82,
+                ns: 1,
+            },
+            RsParams {
+                bs: 134,
+                dw: 108,
+                ns: 1,
+            },
+            RsParams {
+                bs: 67,
+                dw: 23,
+                ns: 2,
+            },
+            RsParams {
+                bs: 67,
+                dw: 34,
+                ns: 2,
+            },
+        ],
+    },
+    // Version 6
+    VersionInfo {
+        data_bytes: 170,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 85,
+                dw: 53,
+                ns: 2,
+            },
+            RsParams {
+                bs: 170,
+                dw: 136,
+                ns: 1,
+            },
+            RsParams {
+                bs: 85,
+                dw: 29,
+                ns: 2,
+            },
+            RsParams {
+                bs: 85,
+                dw: 43,
+                ns: 2,
+            },
+        ],
+    },
+    // Version 7
+    VersionInfo {
+        data_bytes: 212,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 106,
+                dw: 66,
+                ns: 2,
+            },
+            RsParams {
+                bs: 212,
+                dw: 170,
+                ns: 1,
+            },
+            RsParams {
+                bs: 70,
+                dw: 24,
+                ns: 1,
+            },
+            RsParams {
+                bs: 106,
+                dw: 54,
+                ns: 2,
+            },
+        ],
+    },
+    // Version 8
+    VersionInfo {
+        data_bytes: 256,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 128,
+                dw: 80,
+                ns: 2,
+            },
+            RsParams {
+                bs: 128,
+                dw: 104,
+                ns: 2,
+            },
+            RsParams {
+                bs: 64,
+                dw: 22,
+                ns: 4,
+            },
+            RsParams {
+                bs: 64,
+                dw: 32,
+                ns: 4,
+            },
+        ],
+    },
+    // Version 9
+    VersionInfo {
+        data_bytes: 306,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 153,
+                dw: 93,
+                ns: 2,
+            },
+            RsParams {
+                bs: 153,
+                dw: 123,
+                ns: 2,
+            },
+            RsParams {
+                bs: 76,
+                dw: 26,
+                ns: 2,
+            },
+            RsParams {
+                bs: 76,
+                dw: 38,
+                ns: 2,
+            },
+        ],
+    },
+    // Version 10
+    VersionInfo {
+        data_bytes: 358,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 89,
+                dw: 55,
+                ns: 2,
+            },
+            RsParams {
+                bs: 179,
+                dw: 145,
+                ns: 2,
+            },
+            RsParams {
+                bs: 89,
+                dw: 31,
+                ns: 2,
+            },
+            RsParams {
+                bs: 89,
+                dw: 45,
+                ns: 2,
+            },
+        ],
+    },
+    // Version 11
+    VersionInfo {
+        data_bytes: 416,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 104,
+                dw: 65,
+                ns: 4,
+            },
+            RsParams {
+                bs: 208,
+                dw: 166,
+                ns: 2,
+            },
+            RsParams {
+                bs: 83,
+                dw: 29,
+                ns: 4,
+            },
+            RsParams {
+                bs: 104,
+                dw: 53,
+                ns: 4,
+            },
+        ],
+    },
+    // Version 12
+    VersionInfo {
+        data_bytes: 476,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 119,
+                dw: 74,
+                ns: 4,
+            },
+            RsParams {
+                bs: 238,
+                dw: 190,
+                ns: 2,
+            },
+            RsParams {
+                bs: 95,
+                dw: 35,
+                ns: 4,
+            },
+            RsParams {
+                bs: 119,
+                dw: 61,
+                ns: 4,
+            },
+        ],
+    },
+    // Version 13
+    VersionInfo {
+        data_bytes: 542,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 135,
+                dw: 83,
+                ns: 2,
+            },
+            RsParams {
+                bs: 180,
+                dw: 142,
+                ns: 1,
+            },
+            RsParams {
+                bs: 67,
+                dw: 23,
+                ns: 2,
+            },
+            RsParams {
+                bs: 90,
+                dw: 46,
+                ns: 4,
+            },
+        ],
+    },
+    // Version 14
+    VersionInfo {
+        data_bytes: 610,
+        apat: [0; 7],
+        ecc: [
+            RsParams {
+                bs: 152,
+                dw: 92,
+                ns: 2,
+            },
+            RsParams {
+                bs: 152,
+                dw: 120,
+                ns: 2,
+            },
+            RsParams {
+                bs: 76,
+                dw: 28,
+                ns: 6,
+            },
+            RsParams {
+                bs: 122,
+                dw: 64,
+                ns: 5,
+            },
+        ],
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alignment patterns are evenly spaced between the second one and
+    /// the last, which is 7 modules from the edge.
+    #[test]
+    fn test_alignment_positions() {
+        assert_eq!(VERSION_DB[21].apat, [6, 28, 50, 72, 94, 0, 0]);
+
+        for (version, info) in VERSION_DB.iter().enumerate().skip(2) {
+            let apat: Vec<i32> = info.apat.iter().cloned().take_while(|&p| p != 0).collect();
+            let size = version as i32 * 4 + 17;
+            assert_eq!(apat[0], 6, "version {}", version);
+            assert_eq!(apat[apat.len() - 1], size - 7, "version {}", version);
+            for w in apat[1..].windows(3) {
+                assert_eq!(w[1] - w[0], w[2] - w[1], "version {}", version);
+            }
+        }
+    }
+}
@@ -14,19 +14,14 @@
  * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
  */
 
-use std::ffi::CStr;
 use std::path::Path;
 
 use image;
-use libc::{c_char, c_void, memcmp, memcpy};
 
-use quirc_rs::decode::*;
-use quirc_rs::identify::*;
+use quirc_rs::image::to_gray;
 use quirc_rs::quirc::consts::*;
 use quirc_rs::quirc::*;
 
-use quirc_wrapper as qw;
-
 fn data_type_str(dt: i32) -> &'static str {
     if dt == DATA_TYPE_KANJI {
         "KANJI"
@@ -42,46 +37,40 @@ fn data_type_str(dt: i32) -> &'static str {
 }
 
 /// Dump decoded information on stdout.
-pub unsafe fn dump_data(data: *const QuircData) {
-    println!("    Version: {}", (*data).version);
+pub fn dump_data(data: &QuircData) {
+    println!("    Version: {}", data.version);
     println!(
         "    ECC level: {}",
-        i32::from((*b"MLHQ\0")[(*data).ecc_level as (usize)])
+        char::from(b"MLHQ"[data.ecc_level as usize])
     );
-    println!("    Mask: {}", (*data).mask);
+    println!("    Mask: {}", data.mask);
     println!(
         "    Data type: {} ({})",
-        (*data).data_type,
-        data_type_str((*data).data_type)
+        data.data_type,
+        data_type_str(data.data_type)
     );
-    println!("    Length: {}", (*data).payload_len);
+    println!("    Length: {}", data.payload_len);
     println!(
         "    Payload: {}",
-        CStr::from_ptr((*data).payload.as_ptr() as *const c_char)
-            .to_str()
-            .unwrap()
+        String::from_utf8_lossy(&data.payload[..data.payload_len as usize])
     );
-    if (*data).eci != 0 {
-        println!("    ECI: {}", (*data).eci);
+    if data.eci != 0 {
+        println!("    ECI: {}", data.eci);
     }
 }
 
 /// Dump a grid cell map on stdout.
-pub unsafe fn dump_cells(code: *const QuircCode) {
-    print!("    {} cells, corners:", (*code).size);
-    for u in 0..4 {
-        print!(
-            " ({},{})",
-            (*code).corners[u as (usize)].x,
-            (*code).corners[u as (usize)].y
-        );
+pub fn dump_cells(code: &QuircCode) {
+    print!("    {} cells, corners:", code.size);
+    for c in &code.corners {
+        print!(" ({},{})", c.x, c.y);
     }
     println!();
-    for v in 0..(*code).size {
+    for v in 0..code.size {
         print!("    ");
-        for u in 0..(*code).size {
-            let p: i32 = v * (*code).size + u;
-            if i32::from((*code).cell_bitmap[(p >> 3i32) as (usize)]) & 1i32 << (p & 7i32) != 0 {
+        for u in 0..code.size {
+            let p = v * code.size + u;
+            if code.cell_bitmap[(p >> 3) as usize] & (1 << (p & 7)) != 0 {
                 print!("[]");
             } else {
                 print!("  ");
@@ -96,156 +85,10 @@ pub unsafe fn dump_cells(code: *const QuircCode) {
 /// Note that you must call quirc_end() if the function returns
 /// successfully (0).
 pub fn load_image(path: &Path) -> (u32, u32, Vec<u8>) {
-    let img = image::open(path).unwrap().grayscale().to_luma();
+    let img = to_gray(&image::open(path).unwrap());
     let (width, height) = img.dimensions();
 
     let img_bytes = img.into_raw();
     assert_eq!(img_bytes.len(), width as usize * height as usize);
     (width, height, img_bytes)
 }
-
-pub unsafe fn validate(decoder: &mut Quirc, image: Image) {
-    let qw_decoder: *mut qw::quirc = qw::quirc_new();
-    assert!(qw::quirc_resize(qw_decoder, image.width(), image.height()) >= 0);
-    let image_bytes = qw::quirc_begin(
-        qw_decoder,
-        std::ptr::null_mut() as (*mut i32),
-        std::ptr::null_mut() as (*mut i32),
-    );
-    memcpy(
-        image_bytes as *mut c_void,
-        image.pixels().as_ptr() as *const c_void,
-        image.pixels().len(),
-    );
-    qw::quirc_end(qw_decoder);
-
-    assert_eq!(
-        decoder.image.pixels(),
-        std::slice::from_raw_parts(
-            (*qw_decoder).pixels,
-            ((*qw_decoder).w * (*qw_decoder).h) as usize
-        )
-    );
-    assert_eq!(
-        decoder.row_average.as_slice(),
-        std::slice::from_raw_parts((*qw_decoder).row_average, decoder.image.width() as usize)
-    );
-    assert_eq!(decoder.image.width(), (*qw_decoder).w);
-    assert_eq!(decoder.image.height(), (*qw_decoder).h);
-    assert_eq!(decoder.regions.len(), (*qw_decoder).num_regions as usize);
-    assert_eq!(
-        memcmp(
-            decoder.regions.as_ptr() as *const c_void,
-            (*qw_decoder).regions.as_ptr() as *const c_void,
-            std::mem::size_of_val(&decoder.regions[0]) * decoder.regions.len()
-        ),
-        0
-    );
-    assert_slice_eq(
-        &decoder.capstones,
-        std::slice::from_raw_parts(
-            (*qw_decoder).capstones.as_ptr(),
-            (*qw_decoder).num_capstones as usize,
-        ),
-        assert_capstone_eq,
-    );
-    assert_slice_eq(
-        &decoder.grids,
-        std::slice::from_raw_parts(
-            (*qw_decoder).grids.as_ptr(),
-            (*qw_decoder).num_grids as usize,
-        ),
-        assert_grid_eq,
-    );
-
-    let id_count = quirc_count(decoder);
-    assert_eq!(id_count, qw::quirc_count(qw_decoder));
-
-    for i in 0..id_count {
-        let code = quirc_extract(decoder, i).unwrap();
-        let decode_result = quirc_decode(&code);
-
-        let mut qw_code: qw::quirc_code = std::mem::uninitialized();
-        let qw_decode_result;
-        let mut qw_data: qw::quirc_data = std::mem::uninitialized();
-        qw::quirc_extract(qw_decoder, i, &mut qw_code);
-        qw_decode_result = qw::quirc_decode(&qw_code, &mut qw_data);
-
-        assert_eq!(
-            memcmp(
-                code.corners.as_ptr() as *mut c_void,
-                qw_code.corners.as_ptr() as *mut c_void,
-                std::mem::size_of_val(&code.corners)
-            ),
-            0
-        );
-        assert_eq!(code.size, qw_code.size);
-        assert_eq!(
-            memcmp(
-                code.cell_bitmap.as_ptr() as *mut c_void,
-                qw_code.cell_bitmap.as_ptr() as *mut c_void,
-                std::mem::size_of_val(&code.cell_bitmap)
-            ),
-            0
-        );
-
-        assert_result_eq(decode_result, qw_decode_result);
-        if let Ok(data) = decode_result {
-            assert_data_eq(&data, &qw_data);
-        }
-    }
-}
-
-fn assert_slice_eq<A, B>(capstones: &[A], qw_capstones: &[B], f: fn(&A, &B)) {
-    assert_eq!(capstones.len(), qw_capstones.len());
-    capstones
-        .iter()
-        .zip(qw_capstones.iter())
-        .for_each(|(c, qw_c)| f(c, qw_c));
-}
-
-fn assert_capstone_eq(capstone: &Capstone, qw_capstone: &qw::quirc_capstone) {
-    assert_eq!(capstone.ring, qw_capstone.ring);
-    assert_eq!(capstone.stone, qw_capstone.stone);
-    assert_slice_eq(&capstone.corners, &qw_capstone.corners, assert_point_eq);
-    assert_point_eq(&capstone.center, &qw_capstone.center);
-    assert_eq!(capstone.c, qw_capstone.c);
-    assert_eq!(capstone.qr_grid, qw_capstone.qr_grid);
-}
-
-fn assert_grid_eq(grid: &Grid, qw_grid: &qw::quirc_grid) {
-    assert_eq!(grid.caps, qw_grid.caps);
-    assert_eq!(grid.align_region, qw_grid.align_region);
-    assert_point_eq(&grid.align, &qw_grid.align);
-    assert_slice_eq(&grid.tpep, &qw_grid.tpep, assert_point_eq);
-    assert_eq!(grid.hscan, qw_grid.hscan);
-    assert_eq!(grid.vscan, qw_grid.vscan);
-    assert_eq!(grid.grid_size, qw_grid.grid_size);
-    assert_eq!(grid.c, qw_grid.c);
-}
-
-#[allow(clippy::trivially_copy_pass_by_ref)]
-fn assert_point_eq(point: &Point, qw_point: &qw::quirc_point) {
-    assert_eq!(point.x, qw_point.x);
-    assert_eq!(point.y, qw_point.y);
-}
-
-fn assert_result_eq<T>(r: Result<T>, qw_r: qw::quirc_decode_error_t) {
-    match r {
-        Ok(_) => assert_eq!(qw::quirc_decode_error_t_QUIRC_SUCCESS, qw_r),
-        Err(e) => assert_eq!(e as u32, qw_r),
-    }
-}
-
-fn assert_data_eq(data: &QuircData, qw_data: &qw::quirc_data) {
-    assert_eq!(data.version, qw_data.version);
-    assert_eq!(data.ecc_level, qw_data.ecc_level);
-    assert_eq!(data.mask, qw_data.mask);
-    assert_eq!(data.data_type, qw_data.data_type);
-    assert_eq!(data.payload_len, qw_data.payload_len);
-    assert_eq!(
-        &data.payload[0..data.payload_len as usize],
-        &(*qw_data).payload[0..((*qw_data).payload_len) as usize],
-    );
-    assert_eq!(data.eci, qw_data.eci);
-}
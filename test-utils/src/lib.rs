@@ -1 +1,4 @@
 pub mod dbgutil;
+#[cfg(feature = "oracle")]
+pub mod oracle;
+pub mod synth;
@@ -0,0 +1,174 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2010-2012 Daniel Beer <dlbeer@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Comparison against C quirc, built from the quirc-wrapper/quirc
+//! submodule.
+
+use libc::{c_void, memcmp, memcpy};
+
+use quirc_rs::decode::*;
+use quirc_rs::identify::*;
+use quirc_rs::quirc::*;
+
+pub use quirc_wrapper as qw;
+
+/// Run C quirc on `image` and assert that it finds the same regions,
+/// capstones, grids and codes as `decoder` did.
+pub unsafe fn validate(decoder: &mut Quirc, image: Image) {
+    let qw_decoder: *mut qw::quirc = qw::quirc_new();
+    assert!(qw::quirc_resize(qw_decoder, image.width(), image.height()) >= 0);
+    let image_bytes = qw::quirc_begin(
+        qw_decoder,
+        std::ptr::null_mut() as (*mut i32),
+        std::ptr::null_mut() as (*mut i32),
+    );
+    memcpy(
+        image_bytes as *mut c_void,
+        image.pixels().as_ptr() as *const c_void,
+        image.pixels().len(),
+    );
+    qw::quirc_end(qw_decoder);
+
+    assert_eq!(
+        decoder.image.pixels(),
+        std::slice::from_raw_parts(
+            (*qw_decoder).pixels,
+            ((*qw_decoder).w * (*qw_decoder).h) as usize
+        )
+    );
+    assert_eq!(
+        decoder.row_average.as_slice(),
+        std::slice::from_raw_parts((*qw_decoder).row_average, decoder.image.width() as usize)
+    );
+    assert_eq!(decoder.image.width(), (*qw_decoder).w);
+    assert_eq!(decoder.image.height(), (*qw_decoder).h);
+    assert_eq!(decoder.regions.len(), (*qw_decoder).num_regions as usize);
+    assert_eq!(
+        memcmp(
+            decoder.regions.as_ptr() as *const c_void,
+            (*qw_decoder).regions.as_ptr() as *const c_void,
+            std::mem::size_of_val(&decoder.regions[0]) * decoder.regions.len()
+        ),
+        0
+    );
+    assert_slice_eq(
+        &decoder.capstones,
+        std::slice::from_raw_parts(
+            (*qw_decoder).capstones.as_ptr(),
+            (*qw_decoder).num_capstones as usize,
+        ),
+        assert_capstone_eq,
+    );
+    assert_slice_eq(
+        &decoder.grids,
+        std::slice::from_raw_parts(
+            (*qw_decoder).grids.as_ptr(),
+            (*qw_decoder).num_grids as usize,
+        ),
+        assert_grid_eq,
+    );
+
+    let id_count = quirc_count(decoder);
+    assert_eq!(id_count, qw::quirc_count(qw_decoder));
+
+    for i in 0..id_count {
+        let code = quirc_extract(decoder, i).unwrap();
+        let decode_result = quirc_decode(&code);
+
+        let mut qw_code: qw::quirc_code = std::mem::uninitialized();
+        let qw_decode_result;
+        let mut qw_data: qw::quirc_data = std::mem::uninitialized();
+        qw::quirc_extract(qw_decoder, i, &mut qw_code);
+        qw_decode_result = qw::quirc_decode(&qw_code, &mut qw_data);
+
+        assert_eq!(
+            memcmp(
+                code.corners.as_ptr() as *mut c_void,
+                qw_code.corners.as_ptr() as *mut c_void,
+                std::mem::size_of_val(&code.corners)
+            ),
+            0
+        );
+        assert_eq!(code.size, qw_code.size);
+        assert_eq!(
+            memcmp(
+                code.cell_bitmap.as_ptr() as *mut c_void,
+                qw_code.cell_bitmap.as_ptr() as *mut c_void,
+                std::mem::size_of_val(&code.cell_bitmap)
+            ),
+            0
+        );
+
+        assert_result_eq(decode_result, qw_decode_result);
+        if let Ok(data) = decode_result {
+            assert_data_eq(&data, &qw_data);
+        }
+    }
+}
+
+fn assert_slice_eq<A, B>(capstones: &[A], qw_capstones: &[B], f: fn(&A, &B)) {
+    assert_eq!(capstones.len(), qw_capstones.len());
+    capstones
+        .iter()
+        .zip(qw_capstones.iter())
+        .for_each(|(c, qw_c)| f(c, qw_c));
+}
+
+fn assert_capstone_eq(capstone: &Capstone, qw_capstone: &qw::quirc_capstone) {
+    assert_eq!(capstone.ring, qw_capstone.ring);
+    assert_eq!(capstone.stone, qw_capstone.stone);
+    assert_slice_eq(&capstone.corners, &qw_capstone.corners, assert_point_eq);
+    assert_point_eq(&capstone.center, &qw_capstone.center);
+    assert_eq!(capstone.c, qw_capstone.c);
+    assert_eq!(capstone.qr_grid, qw_capstone.qr_grid);
+}
+
+fn assert_grid_eq(grid: &Grid, qw_grid: &qw::quirc_grid) {
+    assert_eq!(grid.caps, qw_grid.caps);
+    assert_eq!(grid.align_region, qw_grid.align_region);
+    assert_point_eq(&grid.align, &qw_grid.align);
+    assert_slice_eq(&grid.tpep, &qw_grid.tpep, assert_point_eq);
+    assert_eq!(grid.hscan, qw_grid.hscan);
+    assert_eq!(grid.vscan, qw_grid.vscan);
+    assert_eq!(grid.grid_size, qw_grid.grid_size);
+    assert_eq!(grid.c, qw_grid.c);
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn assert_point_eq(point: &Point, qw_point: &qw::quirc_point) {
+    assert_eq!(point.x, qw_point.x);
+    assert_eq!(point.y, qw_point.y);
+}
+
+fn assert_result_eq<T>(r: Result<T>, qw_r: qw::quirc_decode_error_t) {
+    match r {
+        Ok(_) => assert_eq!(qw::quirc_decode_error_t_QUIRC_SUCCESS, qw_r),
+        Err(e) => assert_eq!(e.code() as u32, qw_r),
+    }
+}
+
+fn assert_data_eq(data: &QuircData, qw_data: &qw::quirc_data) {
+    assert_eq!(data.version, qw_data.version);
+    assert_eq!(data.ecc_level, qw_data.ecc_level);
+    assert_eq!(data.mask, qw_data.mask);
+    assert_eq!(data.data_type, qw_data.data_type);
+    assert_eq!(data.payload_len, qw_data.payload_len);
+    assert_eq!(
+        &data.payload[0..data.payload_len as usize],
+        &(*qw_data).payload[0..((*qw_data).payload_len) as usize],
+    );
+    assert_eq!(data.eci, qw_data.eci);
+}
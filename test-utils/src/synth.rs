@@ -0,0 +1,845 @@
+/* quirc -- QR-code recognition library
+ * Copyright (C) 2019 Szabolcs Berecz <szabolcs.berecz@gmail.com>
+ *
+ * Permission to use, copy, modify, and/or distribute this software for any
+ * purpose with or without fee is hereby granted, provided that the above
+ * copyright notice and this permission notice appear in all copies.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES
+ * WITH REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF
+ * MERCHANTABILITY AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR
+ * ANY SPECIAL, DIRECT, INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES
+ * WHATSOEVER RESULTING FROM LOSS OF USE, DATA OR PROFITS, WHETHER IN AN
+ * ACTION OF CONTRACT, NEGLIGENCE OR OTHER TORTIOUS ACTION, ARISING OUT OF
+ * OR IN CONNECTION WITH THE USE OR PERFORMANCE OF THIS SOFTWARE.
+ */
+
+//! Deterministic synthetic QR-code images.
+//!
+//! `encode()` builds a byte mode `QuircCode` of any version, ECC level and
+//! mask, `encode_segments()` one mixing numeric, byte and ECI segments,
+//! `encode_rmqr()` a byte mode rMQR code, and `render()` and
+//! `render_rmqr()` draw a code the way a camera might see it. The
+//! encoders are written from the specifications rather than by inverting
+//! the decoders, so that both can be tested against each other. The same
+//! parameters and seed always give the same pixels.
+
+use image::{GrayImage, Luma};
+
+use quirc_rs::decode::{
+    alignment_positions, ecc_blocks, model1_ecc_blocks, MODEL1_MAX_VERSION, QUIRC_MAX_VERSION,
+};
+use quirc_rs::quirc::*;
+use quirc_rs::rmqr::RMQR_VERSIONS;
+
+/// Exponent and logarithm tables of GF(2^8) with the QR-code polynomial
+/// 0x11d.
+struct Gf256 {
+    exp: [u8; 255],
+    log: [u8; 256],
+}
+
+impl Gf256 {
+    fn new() -> Self {
+        let mut gf = Gf256 {
+            exp: [0; 255],
+            log: [0; 256],
+        };
+        let mut x: u32 = 1;
+        for i in 0..255 {
+            gf.exp[i] = x as u8;
+            gf.log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= 0x11d;
+            }
+        }
+        gf
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        self.exp[(usize::from(self.log[a as usize]) + usize::from(self.log[b as usize])) % 255]
+    }
+}
+
+/// Reed-Solomon parity of `data`, with `npar` parity bytes.
+fn rs_parity(data: &[u8], npar: usize) -> Vec<u8> {
+    let gf = Gf256::new();
+
+    // Generator polynomial (x - a^0)(x - a^1)...(x - a^(npar - 1)),
+    // highest order coefficient first
+    let mut gen = vec![1u8];
+    for i in 0..npar {
+        let mut next = vec![0u8; gen.len() + 1];
+        for (j, &c) in gen.iter().enumerate() {
+            next[j] ^= c;
+            next[j + 1] ^= gf.mul(c, gf.exp[i]);
+        }
+        gen = next;
+    }
+
+    let mut parity = vec![0u8; npar];
+    for &b in data {
+        let factor = b ^ parity[0];
+        parity.remove(0);
+        parity.push(0);
+        for (p, &g) in parity.iter_mut().zip(&gen[1..]) {
+            *p ^= gf.mul(g, factor);
+        }
+    }
+    parity
+}
+
+/// Remainder of the BCH code used by the format and version information.
+fn bch_remainder(data: u32, bits: u32, poly: u32) -> u32 {
+    let degree = 31 - poly.leading_zeros();
+    let mut rem = data << degree;
+    for i in (degree..degree + bits).rev() {
+        if rem & (1 << i) != 0 {
+            rem ^= poly << (i - degree);
+        }
+    }
+    rem
+}
+
+fn mask_bit(mask: i32, i: i32, j: i32) -> bool {
+    match mask {
+        0 => (i + j) % 2 == 0,
+        1 => i % 2 == 0,
+        2 => j % 3 == 0,
+        3 => (i + j) % 3 == 0,
+        4 => (i / 2 + j / 3) % 2 == 0,
+        5 => i * j % 2 + i * j % 3 == 0,
+        6 => (i * j % 2 + i * j % 3) % 2 == 0,
+        _ => (i * j % 3 + (i + j) % 2) % 2 == 0,
+    }
+}
+
+/// The largest payload which fits in a code in byte mode.
+pub fn byte_capacity(version: i32, ecc_level: i32) -> usize {
+    byte_mode_capacity(version, &ecc_blocks(version, ecc_level))
+}
+
+/// The largest payload which fits in a Model 1 code in byte mode.
+pub fn model1_byte_capacity(version: i32, ecc_level: i32) -> usize {
+    byte_mode_capacity(version, &model1_ecc_blocks(version, ecc_level))
+}
+
+fn byte_mode_capacity(version: i32, blocks: &[(usize, usize)]) -> usize {
+    let data_words: usize = blocks.iter().map(|b| b.0).sum();
+    let count_bits = if version < 10 { 8 } else { 16 };
+    (data_words * 8 - 4 - count_bits) / 8
+}
+
+/// Data codewords of `payload` in byte mode: mode, count, payload,
+/// terminator and padding, with parity added for `blocks`.
+fn byte_mode_codewords(payload: &[u8], version: i32, blocks: &[(usize, usize)]) -> Vec<u8> {
+    codewords(segment_bits(&[Part::Byte(payload)], version), blocks, 4)
+}
+
+/// A segment of the data stream built by `encode_segments()`.
+#[derive(Clone, Copy, Debug)]
+pub enum Part<'a> {
+    /// ASCII digits in numeric mode
+    Numeric(&'a [u8]),
+
+    /// Bytes in byte mode
+    Byte(&'a [u8]),
+
+    /// An ECI designator for the given assignment number
+    Eci(u32),
+}
+
+/// The data bits of the QR-code segments `parts`, without terminator.
+fn segment_bits(parts: &[Part], version: i32) -> Bits {
+    let mut bits = Bits(Vec::new(), 0);
+    for part in parts {
+        match *part {
+            Part::Numeric(digits) => {
+                bits.push(0b0001, 4);
+                let count_bits = match version {
+                    v if v < 10 => 10,
+                    v if v < 27 => 12,
+                    _ => 14,
+                };
+                bits.push(digits.len() as u32, count_bits);
+                for group in digits.chunks(3) {
+                    let value = group
+                        .iter()
+                        .fold(0, |acc, &d| acc * 10 + u32::from(d - b'0'));
+                    bits.push(value, group.len() * 3 + 1);
+                }
+            }
+            Part::Byte(payload) => {
+                bits.push(0b0100, 4);
+                bits.push(payload.len() as u32, if version < 10 { 8 } else { 16 });
+                for &b in payload {
+                    bits.push(u32::from(b), 8);
+                }
+            }
+            Part::Eci(eci) => {
+                bits.push(0b0111, 4);
+                match eci {
+                    0..=127 => bits.push(eci, 8),
+                    128..=16383 => bits.push(0x8000 | eci, 16),
+                    _ => bits.push(0xc0_0000 | eci, 24),
+                }
+            }
+        }
+    }
+    bits
+}
+
+struct Bits(Vec<u8>, usize);
+
+impl Bits {
+    fn push(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            if self.1 & 7 == 0 {
+                self.0.push(0);
+            }
+            if value & (1 << i) != 0 {
+                *self.0.last_mut().unwrap() |= 0x80 >> (self.1 % 8);
+            }
+            self.1 += 1;
+        }
+    }
+}
+
+/// Modules of a code under construction, and which of them belong to
+/// function patterns.
+struct Matrix {
+    width: i32,
+    height: i32,
+    dark: Vec<bool>,
+    function: Vec<bool>,
+}
+
+impl Matrix {
+    fn new(width: i32, height: i32) -> Self {
+        let cells = (width * height) as usize;
+        Matrix {
+            width,
+            height,
+            dark: vec![false; cells],
+            function: vec![false; cells],
+        }
+    }
+
+    fn set(&mut self, x: i32, y: i32, dark: bool) {
+        let i = (y * self.width + x) as usize;
+        self.dark[i] = dark;
+        self.function[i] = true;
+    }
+
+    fn finder(&mut self, left: i32, top: i32) {
+        for dy in -1..=7 {
+            for dx in -1..=7 {
+                let (x, y) = (left + dx, top + dy);
+                if x < 0 || y < 0 || x >= self.width || y >= self.height {
+                    continue;
+                }
+                let ring = dx.max(dy).max(6 - dx).max(6 - dy);
+                let stone = (2..=4).contains(&dx) && (2..=4).contains(&dy);
+                self.set(
+                    x,
+                    y,
+                    (0..=6).contains(&dx) && (0..=6).contains(&dy) && (ring == 6 || stone),
+                );
+            }
+        }
+    }
+
+    /// Place the codewords `stream` in two-column zigzags from the bottom
+    /// right corner, skipping function patterns and columns for which
+    /// `skip` is true. Cells for which `mask` is true are flipped.
+    fn place(
+        &mut self,
+        stream: &[u8],
+        skip: impl Fn(i32, i32) -> bool,
+        mask: impl Fn(i32, i32) -> bool,
+    ) {
+        let mut index = 0;
+        let mut x = self.width - 1;
+        let mut upward = true;
+        while x > 0 {
+            if skip(x, 0) {
+                x -= 1;
+            }
+            for k in 0..self.height {
+                let y = if upward { self.height - 1 - k } else { k };
+                for &cx in &[x, x - 1] {
+                    let i = (y * self.width + cx) as usize;
+                    if self.function[i] {
+                        continue;
+                    }
+                    let bit = match stream.get(index / 8) {
+                        Some(b) => b & (0x80 >> (index % 8)) != 0,
+                        None => false,
+                    };
+                    self.dark[i] = bit ^ mask(cx, y);
+                    index += 1;
+                }
+            }
+            upward = !upward;
+            x -= 2;
+        }
+    }
+
+    /// Copy the cells into a cell bitmap.
+    fn fill(&self, cell_bitmap: &mut [u8]) {
+        for (i, &dark) in self.dark.iter().enumerate() {
+            if dark {
+                cell_bitmap[i >> 3] |= 1 << (i & 7);
+            }
+        }
+    }
+
+    /// Draw the finder patterns, timing patterns and format information
+    /// of a QR-code.
+    fn finders_and_format(&mut self, ecc_level: i32, mask: i32) {
+        let size = self.width;
+        self.finder(0, 0);
+        self.finder(size - 7, 0);
+        self.finder(0, size - 7);
+        for i in 8..size - 8 {
+            self.set(i, 6, i % 2 == 0);
+            self.set(6, i, i % 2 == 0);
+        }
+
+        let format = (ecc_level << 3 | mask) as u32;
+        let format = (format << 10 | bch_remainder(format, 5, 0x537)) ^ 0x5412;
+        const XS: [i32; 15] = [8, 8, 8, 8, 8, 8, 8, 8, 7, 5, 4, 3, 2, 1, 0];
+        const YS: [i32; 15] = [0, 1, 2, 3, 4, 5, 7, 8, 8, 8, 8, 8, 8, 8, 8];
+        for i in 0..15 {
+            let bit = format & (1 << i) != 0;
+            self.set(XS[i], YS[i], bit);
+            if i < 8 {
+                self.set(size - 1 - i as i32, 8, bit);
+            } else {
+                self.set(8, size - 15 + i as i32, bit);
+            }
+        }
+    }
+
+    fn alignment(&mut self, cx: i32, cy: i32) {
+        for dy in -2..=2i32 {
+            for dx in -2..=2i32 {
+                self.set(cx + dx, cy + dy, dx.abs().max(dy.abs()) != 1);
+            }
+        }
+    }
+}
+
+/// Encode `payload` in byte mode as a code of the given version, ECC level
+/// (one of the `ECC_LEVEL_*` constants) and mask. Returns `None` if the
+/// payload doesn't fit.
+pub fn encode(payload: &[u8], version: i32, ecc_level: i32, mask: i32) -> Option<QuircCode> {
+    encode_segments(&[Part::Byte(payload)], version, ecc_level, mask)
+}
+
+/// Encode the segments `parts` as a code, like `encode()`.
+pub fn encode_segments(
+    parts: &[Part],
+    version: i32,
+    ecc_level: i32,
+    mask: i32,
+) -> Option<QuircCode> {
+    assert!((1..=QUIRC_MAX_VERSION as i32).contains(&version));
+    assert!((0..4).contains(&ecc_level) && (0..8).contains(&mask));
+
+    let blocks = ecc_blocks(version, ecc_level);
+    let bits = segment_bits(parts, version);
+    if bits.1 > blocks.iter().map(|b| b.0).sum::<usize>() * 8 {
+        return None;
+    }
+
+    let stream = codewords(bits, &blocks, 4);
+
+    // Function patterns
+    let size = version * 4 + 17;
+    let mut m = Matrix::new(size, size);
+    m.finders_and_format(ecc_level, mask);
+    m.set(8, size - 8, true);
+
+    let apat = alignment_positions(version);
+    for (i, &y) in apat.iter().enumerate() {
+        for (j, &x) in apat.iter().enumerate() {
+            let last = apat.len() - 1;
+            if (i == 0 && (j == 0 || j == last)) || (i == last && j == 0) {
+                continue;
+            }
+            m.alignment(x, y);
+        }
+    }
+
+    if version >= 7 {
+        let info = (version as u32) << 12 | bch_remainder(version as u32, 6, 0x1f25);
+        for i in 0..18 {
+            let bit = info & (1 << i) != 0;
+            m.set(i / 3, size - 11 + i % 3, bit);
+            m.set(size - 11 + i % 3, i / 3, bit);
+        }
+    }
+
+    m.place(&stream, |x, _| x == 6, |x, y| mask_bit(mask, y, x));
+
+    let mut code = QuircCode {
+        size,
+        ..Default::default()
+    };
+    m.fill(&mut code.cell_bitmap);
+    Some(code)
+}
+
+/// Encode `payload` in byte mode as a Model 1 code, like `encode()`.
+/// Versions go up to `MODEL1_MAX_VERSION`.
+pub fn encode_model1(payload: &[u8], version: i32, ecc_level: i32, mask: i32) -> Option<QuircCode> {
+    assert!((1..=MODEL1_MAX_VERSION as i32).contains(&version));
+    assert!((0..4).contains(&ecc_level) && (0..8).contains(&mask));
+
+    if payload.len() > model1_byte_capacity(version, ecc_level) {
+        return None;
+    }
+
+    let stream = byte_mode_codewords(payload, version, &model1_ecc_blocks(version, ecc_level));
+    let size = version * 4 + 17;
+    let mut m = Matrix::new(size, size);
+    m.finders_and_format(ecc_level, mask);
+
+    // Each codeword fills a block of 2x4 cells up the two column pairs on
+    // the right, then 4x2 cells up the columns in the middle, then 2x4
+    // cells up the column pairs on the left between the finder patterns.
+    // Blocks along the right and bottom edges alternate with extension
+    // patterns.
+    let mut codewords = Vec::new();
+    let pairs = (size - 9) / 4;
+    for (j, x) in [size - 1, size - 3].iter().enumerate() {
+        for i in 0..pairs {
+            let extension = j == 0 && i % 2 == 0 && i > 0 && i < pairs - 1;
+            codewords.push((*x, size - 1 - i * 4, 2, extension));
+        }
+    }
+
+    let quads = (size - 13) / 4;
+    for k in 0..quads {
+        let x = size - 5 - k * 4;
+        let extension_at_bottom = k % 2 == 1 && k != quads - 1;
+        let mut y = size - 1;
+        while y > 0 {
+            if y == 6 {
+                y -= 1;
+            }
+            if !(k == 0 && y < 9) {
+                codewords.push((x, y, 4, extension_at_bottom && y == size - 1));
+            }
+            y -= 2;
+        }
+    }
+
+    for &x in &[8, 5, 3, 1] {
+        for y in (9..size - 8).rev().step_by(4) {
+            codewords.push((x, y, 2, false));
+        }
+    }
+
+    let mut index = 0;
+    for (x, y, width, extension) in codewords {
+        for b in 0..8 {
+            let (cx, cy) = (x - b % width, y - b / width);
+            if extension {
+                // A dark bar along the edge of the code, with a light one
+                // inside it
+                m.set(cx, cy, cx == size - 1 || cy == size - 1);
+            } else {
+                let bit = stream[index / 8] & (0x80 >> (index % 8)) != 0;
+                m.set(cx, cy, bit ^ mask_bit(mask, cy, cx));
+                index += 1;
+            }
+        }
+    }
+    assert_eq!(index, stream.len() * 8);
+
+    let mut code = QuircCode {
+        size,
+        ..Default::default()
+    };
+    m.fill(&mut code.cell_bitmap);
+    Some(code)
+}
+
+/// Terminate and pad the data `bits` to fill `blocks`, with a terminator
+/// of up to `terminator` bits, then add parity and interleave the blocks.
+fn codewords(mut bits: Bits, blocks: &[(usize, usize)], terminator: usize) -> Vec<u8> {
+    let data_words: usize = blocks.iter().map(|b| b.0).sum();
+
+    bits.push(0, (data_words * 8 - bits.1).min(terminator));
+    let mut data = bits.0;
+    for pad in [0xec, 0x11].iter().cycle() {
+        if data.len() >= data_words {
+            break;
+        }
+        data.push(*pad);
+    }
+
+    // Split into blocks, add parity and interleave
+    let mut split = Vec::new();
+    let mut offset = 0;
+    for &(dw, bs) in blocks {
+        let block = &data[offset..offset + dw];
+        split.push((block.to_vec(), rs_parity(block, bs - dw)));
+        offset += dw;
+    }
+    let mut stream = Vec::new();
+    for j in 0..blocks.iter().map(|b| b.0).max().unwrap() {
+        stream.extend(split.iter().filter_map(|b| b.0.get(j)));
+    }
+    for j in 0..blocks[0].1 - blocks[0].0 {
+        stream.extend(split.iter().map(|b| b.1[j]));
+    }
+    stream
+}
+
+/// Byte mode capacity of an rMQR code of the given version (an index into
+/// `RMQR_VERSIONS`), at ECC level H if `ecc_h` or M otherwise.
+pub fn rmqr_byte_capacity(version: i32, ecc_h: bool) -> usize {
+    let ver = &RMQR_VERSIONS[version as usize];
+    let blocks = ver.ecc[ecc_h as usize].blocks(ver.data_bytes);
+    let data_words: usize = blocks.iter().map(|b| b.0).sum();
+    (data_words * 8 - 3 - ver.count_bits[2] as usize) / 8
+}
+
+/// Encode `payload` in byte mode as an rMQR code of the given version (an
+/// index into `RMQR_VERSIONS`), at ECC level H if `ecc_h` or M otherwise.
+/// Returns `None` if the payload doesn't fit.
+pub fn encode_rmqr(payload: &[u8], version: i32, ecc_h: bool) -> Option<RmqrCode> {
+    let ver = &RMQR_VERSIONS[version as usize];
+    if payload.len() > rmqr_byte_capacity(version, ecc_h) {
+        return None;
+    }
+
+    let mut bits = Bits(Vec::new(), 0);
+    bits.push(0b011, 3);
+    bits.push(payload.len() as u32, ver.count_bits[2] as usize);
+    for &b in payload {
+        bits.push(u32::from(b), 8);
+    }
+    let blocks = ver.ecc[ecc_h as usize].blocks(ver.data_bytes);
+    let stream = codewords(bits, &blocks, 3);
+
+    // Timing patterns along the edges and through the alignment patterns
+    let (w, h) = (ver.width, ver.height);
+    let mut m = Matrix::new(w, h);
+    for x in 0..w {
+        m.set(x, 0, x % 2 == 0);
+        m.set(x, h - 1, x % 2 == 0);
+    }
+    for y in 0..h {
+        m.set(0, y, y % 2 == 0);
+        m.set(w - 1, y, y % 2 == 0);
+    }
+    for &cx in ver.apat.iter().filter(|&&cx| cx != 0) {
+        for y in 0..h {
+            m.set(cx, y, y % 2 == 0);
+        }
+        for &cy in &[1, h - 2] {
+            for dy in -1..=1i32 {
+                for dx in -1..=1i32 {
+                    m.set(cx + dx, cy + dy, dx != 0 || dy != 0);
+                }
+            }
+        }
+    }
+
+    // Finder pattern and its separator, sub-finder pattern and corner
+    // patterns, the one at the bottom left only if there's room for it
+    // below the separator
+    m.finder(0, 0);
+    for dy in -2..=2i32 {
+        for dx in -2..=2i32 {
+            m.set(w - 3 + dx, h - 3 + dy, dx.abs().max(dy.abs()) != 1);
+        }
+    }
+    for &(x, y) in &[(w - 2, 0), (w - 1, 1), (w - 2, 1)] {
+        m.set(x, y, y == 0 || x == w - 1);
+    }
+    if h > 9 {
+        for &(x, y) in &[(1, h - 1), (0, h - 2), (1, h - 2)] {
+            m.set(x, y, y == h - 1 || x == 0);
+        }
+    }
+
+    // Format information, next to the finder and sub-finder patterns
+    let format = (ecc_h as u32) << 5 | version as u32;
+    let format = format << 12 | bch_remainder(format, 6, 0x1f25);
+    for i in 0..18 {
+        let bit = |mask: u32| (format ^ mask) & (1 << i) != 0;
+        if i < 15 {
+            m.set(8 + i / 5, 1 + i % 5, bit(0x1fab2));
+            m.set(w - 8 + i / 5, h - 6 + i % 5, bit(0x20a7b));
+        } else {
+            m.set(11, i - 14, bit(0x1fab2));
+            m.set(w - 20 + i, h - 6, bit(0x20a7b));
+        }
+    }
+
+    m.place(&stream, |_, _| false, |x, y| (y / 2 + x / 3) % 2 == 0);
+
+    let mut code = RmqrCode {
+        width: w,
+        height: h,
+        ..Default::default()
+    };
+    m.fill(&mut code.cell_bitmap);
+    Some(code)
+}
+
+/// How `render()` draws a code. The default is a clean, upright code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Params {
+    /// Size of a module in pixels, before the perspective distortion
+    pub module_size: f64,
+
+    /// Width of the light border around the code, in modules
+    pub quiet_zone: i32,
+
+    /// Clockwise rotation in degrees
+    pub rotation: f64,
+
+    /// Keystone distortion: the top edge is narrower than the bottom one by
+    /// this fraction of its length
+    pub perspective: f64,
+
+    /// Standard deviation of the Gaussian blur, in pixels
+    pub blur: f64,
+
+    /// Standard deviation of the Gaussian noise, in gray levels
+    pub noise: f64,
+
+    /// Difference between the light and dark modules, from 0 to 1, centred
+    /// on mid-gray
+    pub contrast: f64,
+
+    /// Draw light modules on a dark background
+    pub invert: bool,
+
+    /// Seed of the noise
+    pub seed: u64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            module_size: 4.0,
+            quiet_zone: 4,
+            rotation: 0.0,
+            perspective: 0.0,
+            blur: 0.0,
+            noise: 0.0,
+            contrast: 1.0,
+            invert: false,
+            seed: 0,
+        }
+    }
+}
+
+/// SplitMix64, so that images don't depend on the version of any random
+/// number crate.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in (0, 1]
+    fn uniform(&mut self) -> f64 {
+        ((self.next() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal, by the Box-Muller transform
+    fn normal(&mut self) -> f64 {
+        let (u, v) = (self.uniform(), self.uniform());
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+}
+
+/// Homography taking the unit square to the quadrilateral `q` (top left,
+/// top right, bottom right, bottom left), as a row-major 3x3 matrix.
+fn square_to_quad(q: &[(f64, f64); 4]) -> [f64; 9] {
+    let (x0, y0) = q[0];
+    let (x1, y1) = q[1];
+    let (x2, y2) = q[2];
+    let (x3, y3) = q[3];
+    let (sx, sy) = (x0 - x1 + x2 - x3, y0 - y1 + y2 - y3);
+    let (dx1, dy1) = (x1 - x2, y1 - y2);
+    let (dx2, dy2) = (x3 - x2, y3 - y2);
+    let den = dx1 * dy2 - dx2 * dy1;
+    let g = (sx * dy2 - dx2 * sy) / den;
+    let h = (dx1 * sy - sx * dy1) / den;
+
+    [
+        x1 - x0 + g * x1,
+        x3 - x0 + h * x3,
+        x0,
+        y1 - y0 + g * y1,
+        y3 - y0 + h * y3,
+        y0,
+        g,
+        h,
+        1.0,
+    ]
+}
+
+fn invert3(m: &[f64; 9]) -> [f64; 9] {
+    let det = m[0] * (m[4] * m[8] - m[5] * m[7]) - m[1] * (m[3] * m[8] - m[5] * m[6])
+        + m[2] * (m[3] * m[7] - m[4] * m[6]);
+    [
+        (m[4] * m[8] - m[5] * m[7]) / det,
+        (m[2] * m[7] - m[1] * m[8]) / det,
+        (m[1] * m[5] - m[2] * m[4]) / det,
+        (m[5] * m[6] - m[3] * m[8]) / det,
+        (m[0] * m[8] - m[2] * m[6]) / det,
+        (m[2] * m[3] - m[0] * m[5]) / det,
+        (m[3] * m[7] - m[4] * m[6]) / det,
+        (m[1] * m[6] - m[0] * m[7]) / det,
+        (m[0] * m[4] - m[1] * m[3]) / det,
+    ]
+}
+
+fn blur_1d(src: &[f64], dst: &mut [f64], kernel: &[f64], len: usize, stride: usize) {
+    let r = (kernel.len() / 2) as isize;
+    for i in 0..len as isize {
+        let mut sum = 0.0;
+        for (k, w) in kernel.iter().enumerate() {
+            let j = (i + k as isize - r).max(0).min(len as isize - 1);
+            sum += w * src[j as usize * stride];
+        }
+        dst[i as usize * stride] = sum;
+    }
+}
+
+fn gaussian_blur(pixels: &mut [f64], width: usize, height: usize, sigma: f64) {
+    let r = (sigma * 3.0).ceil() as isize;
+    let mut kernel: Vec<f64> = (-r..=r)
+        .map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|w| *w /= total);
+
+    let mut tmp = pixels.to_vec();
+    for y in 0..height {
+        let row = y * width..(y + 1) * width;
+        blur_1d(&pixels[row.clone()], &mut tmp[row], &kernel, width, 1);
+    }
+    for x in 0..width {
+        blur_1d(&tmp[x..], &mut pixels[x..], &kernel, height, width);
+    }
+}
+
+/// Draw a code, with its quiet zone, distorted as described by `params`.
+/// The image is just large enough to hold the rotated code and its quiet
+/// zone.
+pub fn render(code: &QuircCode, params: &Params) -> GrayImage {
+    render_cells(code.size, code.size, &code.cell_bitmap, params)
+}
+
+/// Draw an rMQR code, like `render()`.
+pub fn render_rmqr(code: &RmqrCode, params: &Params) -> GrayImage {
+    render_cells(code.width, code.height, &code.cell_bitmap, params)
+}
+
+fn render_cells(cols: i32, rows: i32, cell_bitmap: &[u8], params: &Params) -> GrayImage {
+    let nx = f64::from(cols + 2 * params.quiet_zone);
+    let ny = f64::from(rows + 2 * params.quiet_zone);
+    let (side_x, side_y) = (nx * params.module_size, ny * params.module_size);
+
+    // Keystone, then rotation about the centre
+    let inset = side_x * params.perspective / 2.0;
+    let quad = [
+        (inset, 0.0),
+        (side_x - inset, 0.0),
+        (side_x, side_y),
+        (0.0, side_y),
+    ];
+    let (sin, cos) = params.rotation.to_radians().sin_cos();
+    let mut quad = quad.map(|(x, y)| {
+        let (x, y) = (x - side_x / 2.0, y - side_y / 2.0);
+        (x * cos - y * sin, x * sin + y * cos)
+    });
+    let min_x = quad.iter().map(|p| p.0).fold(f64::MAX, f64::min);
+    let min_y = quad.iter().map(|p| p.1).fold(f64::MAX, f64::min);
+    let max_x = quad.iter().map(|p| p.0).fold(f64::MIN, f64::max);
+    let max_y = quad.iter().map(|p| p.1).fold(f64::MIN, f64::max);
+    quad = quad.map(|(x, y)| (x - min_x, y - min_y));
+
+    let width = (max_x - min_x).ceil() as usize;
+    let height = (max_y - min_y).ceil() as usize;
+    let to_square = invert3(&square_to_quad(&quad));
+    let (dark, light) = if params.invert {
+        (1.0, 0.0)
+    } else {
+        (0.0, 1.0)
+    };
+
+    // Module value at a point of the image, outside of the quiet zone
+    // being light
+    let sample = |x: f64, y: f64| -> f64 {
+        let m = &to_square;
+        let w = m[6] * x + m[7] * y + m[8];
+        let u = (m[0] * x + m[1] * y + m[2]) / w * nx;
+        let v = (m[3] * x + m[4] * y + m[5]) / w * ny;
+        let (cx, cy) = (
+            (u.floor() as i32) - params.quiet_zone,
+            (v.floor() as i32) - params.quiet_zone,
+        );
+        if cx < 0 || cy < 0 || cx >= cols || cy >= rows {
+            return light;
+        }
+        let i = (cy * cols + cx) as usize;
+        if cell_bitmap[i >> 3] & (1 << (i & 7)) != 0 {
+            dark
+        } else {
+            light
+        }
+    };
+
+    // Supersample 3x3 for anti-aliasing
+    let mut pixels = vec![0.0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            for j in 0..3 {
+                for i in 0..3 {
+                    sum += sample(
+                        x as f64 + (f64::from(i) + 0.5) / 3.0,
+                        y as f64 + (f64::from(j) + 0.5) / 3.0,
+                    );
+                }
+            }
+            pixels[y * width + x] = sum / 9.0;
+        }
+    }
+
+    if params.blur > 0.0 {
+        gaussian_blur(&mut pixels, width, height, params.blur);
+    }
+
+    let mut rng = Rng(params.seed);
+    let mut img = GrayImage::new(width as u32, height as u32);
+    for (i, p) in img.pixels_mut().enumerate() {
+        let mut v = 127.5 + (pixels[i] - 0.5) * params.contrast * 255.0;
+        if params.noise > 0.0 {
+            v += rng.normal() * params.noise;
+        }
+        *p = Luma([v.round().clamp(0.0, 255.0) as u8]);
+    }
+    img
+}
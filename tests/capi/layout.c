@@ -0,0 +1,51 @@
+/* Compiled, but not linked, by capi_tests.rs against include/quirc.h, to
+ * check that the header is valid C, that the C API can be called as in
+ * libquirc, and that its types have the layout of their Rust
+ * counterparts. The expected sizes and offsets are passed with -D.
+ */
+
+#include <stddef.h>
+#include <string.h>
+
+#include "quirc.h"
+
+_Static_assert(sizeof(struct quirc_point) == SIZEOF_POINT, "quirc_point");
+_Static_assert(sizeof(struct quirc_code) == SIZEOF_CODE, "quirc_code");
+_Static_assert(offsetof(struct quirc_code, cell_bitmap) == OFFSETOF_CELL_BITMAP,
+	       "quirc_code.cell_bitmap");
+_Static_assert(sizeof(struct quirc_data) == SIZEOF_DATA, "quirc_data");
+_Static_assert(offsetof(struct quirc_data, payload_len) == OFFSETOF_PAYLOAD_LEN,
+	       "quirc_data.payload_len");
+_Static_assert(offsetof(struct quirc_data, eci) == OFFSETOF_ECI,
+	       "quirc_data.eci");
+_Static_assert(sizeof(quirc_decode_error_t) == SIZEOF_DECODE_ERROR,
+	       "quirc_decode_error_t");
+
+int scan(const uint8_t *image, int w, int h, struct quirc_data *data)
+{
+	struct quirc *q = quirc_new();
+	struct quirc_code code;
+	quirc_decode_error_t err = QUIRC_ERROR_INVALID_GRID_SIZE;
+	int i;
+
+	if (!q || quirc_resize(q, w, h) < 0)
+		return -1;
+
+	memcpy(quirc_begin(q, NULL, NULL), image, (size_t)w * h);
+	quirc_end(q);
+
+	for (i = 0; i < quirc_count(q); i++) {
+		quirc_extract(q, i, &code);
+		err = quirc_decode(&code, data);
+		if (!err)
+			break;
+	}
+
+	quirc_destroy(q);
+	return err;
+}
+
+const char *describe(quirc_decode_error_t err)
+{
+	return err ? quirc_strerror(err) : quirc_version();
+}
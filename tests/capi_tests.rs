@@ -0,0 +1,194 @@
+#![cfg(feature = "capi")]
+
+use std::ffi::CStr;
+use std::mem;
+use std::path::Path;
+use std::process::Command;
+use std::ptr;
+
+use quirc_rs::capi::quirc_decode_error_t::*;
+use quirc_rs::capi::*;
+use quirc_rs::quirc::consts::*;
+use quirc_rs::quirc::{Point, QuircCode, QuircData};
+
+use test_utils::synth::*;
+
+/// Byte offset of a field, from pointers into a value.
+fn offset<T, F>(value: &T, field: &F) -> usize {
+    field as *const F as usize - value as *const T as usize
+}
+
+#[test]
+fn test_header_up_to_date() {
+    let checked_in = include_str!("../include/quirc.h");
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/quirc.h"));
+    assert!(
+        checked_in == generated,
+        "include/quirc.h is out of date: copy {}/quirc.h over it",
+        env!("OUT_DIR")
+    );
+}
+
+/// Run cbindgen as build.rs does, so that the checked in header is
+/// compared with the current sources and cbindgen.toml rather than with
+/// whatever the last build left in `OUT_DIR`.
+#[test]
+fn test_header_matches_cbindgen() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(root.join("src/capi.rs"))
+        .with_src(root.join("src/quirc.rs"))
+        .generate()
+        .unwrap()
+        .write(&mut generated);
+
+    let checked_in = std::fs::read(root.join("include/quirc.h")).unwrap();
+    assert!(
+        checked_in == generated,
+        "include/quirc.h doesn't match cbindgen.toml: copy {}/quirc.h over it",
+        env!("OUT_DIR")
+    );
+}
+
+#[test]
+fn test_header_compiles() {
+    let code = QuircCode::default();
+    let data = QuircData::default();
+    let defines = [
+        ("SIZEOF_POINT", mem::size_of::<Point>()),
+        ("SIZEOF_CODE", mem::size_of::<QuircCode>()),
+        ("OFFSETOF_CELL_BITMAP", offset(&code, &code.cell_bitmap)),
+        ("SIZEOF_DATA", mem::size_of::<QuircData>()),
+        ("OFFSETOF_PAYLOAD_LEN", offset(&data, &data.payload_len)),
+        ("OFFSETOF_ECI", offset(&data, &data.eci)),
+        (
+            "SIZEOF_DECODE_ERROR",
+            mem::size_of::<quirc_decode_error_t>(),
+        ),
+    ];
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let object = Path::new(env!("OUT_DIR")).join("layout.o");
+    let mut cc = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".into()));
+    cc.args(["-std=c11", "-Wall", "-Werror", "-c", "-o"])
+        .arg(&object)
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg(root.join("tests/capi/layout.c"));
+    for (name, value) in &defines {
+        cc.arg(format!("-D{}={}", name, value));
+    }
+
+    let status = cc.status().expect("Unable to run the C compiler");
+    assert!(status.success());
+}
+
+#[test]
+fn test_decode() {
+    let payload = b"through the C API";
+    let code = encode(payload, 2, ECC_LEVEL_M, 5).unwrap();
+    let img = render(&code, &Params::default());
+    let (width, height) = (img.width() as i32, img.height() as i32);
+
+    unsafe {
+        let q = quirc_new();
+        assert!(!q.is_null());
+        assert_eq!(quirc_resize(q, width, height), 0);
+
+        let (mut w, mut h) = (0, 0);
+        let buf = quirc_begin(q, &mut w, &mut h);
+        assert_eq!((w, h), (width, height));
+        ptr::copy_nonoverlapping(img.as_ptr(), buf, img.len());
+        quirc_end(q);
+        assert_eq!(quirc_count(q), 1);
+
+        let mut extracted = mem::MaybeUninit::<QuircCode>::uninit();
+        quirc_extract(q, 0, extracted.as_mut_ptr());
+        let extracted = extracted.assume_init();
+        assert_eq!(extracted.size, code.size);
+
+        let mut data = mem::MaybeUninit::<QuircData>::uninit();
+        assert_eq!(quirc_decode(&extracted, data.as_mut_ptr()), QUIRC_SUCCESS);
+        let data = data.assume_init();
+        assert_eq!(&data.payload[..data.payload_len as usize], &payload[..]);
+        assert_eq!((data.version, data.mask), (2, 5));
+
+        // An index out of range gives an empty code
+        let mut missing = mem::MaybeUninit::<QuircCode>::uninit();
+        quirc_extract(q, 1, missing.as_mut_ptr());
+        assert_eq!(missing.assume_init().size, 0);
+
+        let mut data = mem::MaybeUninit::<QuircData>::uninit();
+        let err = quirc_decode(&missing.assume_init(), data.as_mut_ptr());
+        assert_eq!(err, QUIRC_ERROR_INVALID_GRID_SIZE);
+        let msg = CStr::from_ptr(quirc_strerror(err));
+        assert_eq!(msg.to_str().unwrap(), "Invalid grid size");
+
+        quirc_destroy(q);
+    }
+}
+
+#[test]
+fn test_resize_failure() {
+    unsafe {
+        let q = quirc_new();
+        assert_eq!(quirc_resize(q, 20, 10), 0);
+
+        assert_eq!(quirc_resize(q, -1, 10), -1);
+        // Too large to allocate
+        assert_eq!(quirc_resize(q, i32::MAX, i32::MAX), -1);
+
+        // The recognizer keeps its previous size
+        let (mut w, mut h) = (0, 0);
+        quirc_begin(q, &mut w, &mut h);
+        assert_eq!((w, h), (20, 10));
+
+        quirc_destroy(q);
+    }
+}
+
+#[test]
+fn test_unset_size() {
+    unsafe {
+        let q = quirc_new();
+        let (mut w, mut h) = (-1, -1);
+        quirc_begin(q, &mut w, &mut h);
+        assert_eq!((w, h), (0, 0));
+        quirc_end(q);
+        assert_eq!(quirc_count(q), 0);
+
+        let mut code = mem::MaybeUninit::<QuircCode>::uninit();
+        quirc_extract(q, 0, code.as_mut_ptr());
+        assert_eq!(code.assume_init().size, 0);
+
+        quirc_destroy(q);
+    }
+}
+
+#[test]
+fn test_null() {
+    unsafe {
+        let q = ptr::null_mut();
+        assert_eq!(quirc_resize(q, 20, 10), -1);
+        assert!(quirc_begin(q, ptr::null_mut(), ptr::null_mut()).is_null());
+        quirc_end(q);
+        assert_eq!(quirc_count(q), 0);
+
+        let mut code = mem::MaybeUninit::<QuircCode>::uninit();
+        quirc_extract(q, 0, code.as_mut_ptr());
+        assert_eq!(code.assume_init().size, 0);
+        quirc_extract(q, 0, ptr::null_mut());
+
+        let mut data = mem::MaybeUninit::<QuircData>::uninit();
+        let err = quirc_decode(ptr::null(), data.as_mut_ptr());
+        assert_eq!(err, QUIRC_ERROR_INVALID_GRID_SIZE);
+        assert_eq!(data.assume_init().payload_len, 0);
+        let code = QuircCode::default();
+        assert_eq!(quirc_decode(&code, ptr::null_mut()), err);
+
+        quirc_destroy(q);
+    }
+}
@@ -0,0 +1,100 @@
+use image::{GrayImage, Luma};
+
+use quirc_rs::decode::*;
+use quirc_rs::fusion::*;
+use quirc_rs::identify::*;
+use quirc_rs::quirc::consts::*;
+use quirc_rs::quirc::*;
+
+use test_utils::synth::*;
+
+const PAYLOAD: &[u8] = b"fused from three frames";
+
+/// Flip the cells of a code in the given rows, leaving the finders, the
+/// timing patterns and the format bits alone.
+fn damage(code: &QuircCode, rows: std::ops::Range<i32>) -> QuircCode {
+    let mut damaged = *code;
+    for y in rows {
+        for x in 9..code.size - 8 {
+            let i = (y * code.size + x) as usize;
+            damaged.cell_bitmap[i >> 3] ^= 1 << (i & 7);
+        }
+    }
+    damaged
+}
+
+#[test]
+fn test_fuse_codes() {
+    let code = encode(PAYLOAD, 3, ECC_LEVEL_L, 2).unwrap();
+    let frames = [
+        damage(&code, 9..12),
+        damage(&code, 13..16),
+        damage(&code, 17..20),
+    ];
+    for frame in &frames {
+        assert!(quirc_decode(frame).is_err());
+    }
+
+    let mut fusion = Fusion::new();
+    assert!(quirc_fuse_codes(&frames[..1], &mut fusion)[0].is_err());
+    assert!(quirc_fuse_codes(&frames[1..2], &mut fusion)[0].is_err());
+    let data = quirc_fuse_codes(&frames[2..], &mut fusion)[0].unwrap();
+    assert_eq!(&data.payload[..data.payload_len as usize], PAYLOAD);
+    assert_eq!(fusion.len(), 1);
+
+    // Codes elsewhere, or of another size, are kept apart
+    let mut moved = frames[0];
+    moved.corners.iter_mut().for_each(|p| p.x += 40);
+    let other = encode(b"other", 1, ECC_LEVEL_L, 0).unwrap();
+    let results = quirc_fuse_codes(&[moved, other], &mut fusion);
+    assert!(results[0].is_err());
+    assert!(results[1].is_ok());
+    assert_eq!(fusion.len(), 3);
+
+    // Forgotten after `max_frames` frames without a sighting
+    fusion.max_frames = 2;
+    quirc_fuse_codes(&[], &mut fusion);
+    assert_eq!(fusion.len(), 2);
+    quirc_fuse_codes(&[], &mut fusion);
+    assert!(fusion.is_empty());
+}
+
+#[test]
+fn test_fuse_frames() {
+    let code = encode(PAYLOAD, 3, ECC_LEVEL_L, 2).unwrap();
+    let params = Params::default();
+    let m = params.module_size as u32;
+    let origin = params.quiet_zone as u32 * m;
+
+    // Glare over a different band of the code in each frame
+    let mut fusion = Fusion::new();
+    for (i, rows) in [9..13, 13..17, 17..21].iter().enumerate() {
+        let mut img = render(&code, &params);
+        for y in origin + rows.start * m..origin + rows.end * m {
+            for x in origin + 9 * m..origin + 20 * m {
+                img.put_pixel(x, y, Luma([0xff]));
+            }
+        }
+
+        let (width, height) = img.dimensions();
+        let mut q = Quirc::new(Image::new(width, height, &mut img));
+        quirc_identify(&mut q);
+        assert_eq!(quirc_count(&q), 1);
+        assert!(quirc_decode(&quirc_extract(&mut q, 0).unwrap()).is_err());
+
+        let results = quirc_fuse(&q, &mut fusion);
+        if i < 2 {
+            assert!(results[0].is_err());
+        } else {
+            let data = results[0].unwrap();
+            assert_eq!(&data.payload[..data.payload_len as usize], PAYLOAD);
+        }
+    }
+
+    // A clean frame decodes on its own
+    let mut img: GrayImage = render(&code, &params);
+    let (width, height) = img.dimensions();
+    let mut q = Quirc::new(Image::new(width, height, &mut img));
+    quirc_identify(&mut q);
+    assert!(quirc_fuse(&q, &mut fusion)[0].is_ok());
+}
@@ -2,9 +2,12 @@ use std::path::Path;
 
 use quirc_rs::decode::*;
 use quirc_rs::identify::*;
+use quirc_rs::quirc::consts::MODEL_2;
 use quirc_rs::quirc::*;
 
 use test_utils::dbgutil::*;
+#[cfg(feature = "oracle")]
+use test_utils::oracle::validate;
 
 #[derive(Debug, Eq, PartialEq)]
 struct Data {
@@ -36,17 +39,11 @@ impl Data {
     }
 }
 
-unsafe fn validate_against_original(path: &Path, expected_contents: &[Option<Data>]) {
-    let (width, height, mut image_bytes) = load_image(path);
-    let mut image_bytes_clone = image_bytes.clone();
-
-    let mut decoder = Quirc::new(Image::new(width, height, &mut image_bytes));
-
-    quirc_identify(&mut decoder);
-
-    let result: Vec<_> = (0..quirc_count(&decoder))
+/// Extract and decode each code found by `quirc_identify()`.
+fn decode_all(decoder: &mut Quirc) -> Vec<Option<Data>> {
+    (0..quirc_count(decoder))
         .map(|i| {
-            let code = quirc_extract(&mut decoder, i).unwrap();
+            let code = quirc_extract(decoder, i).unwrap();
             if let Ok(data) = quirc_decode(&code) {
                 Some(Data::new(
                     data.version,
@@ -60,9 +57,22 @@ unsafe fn validate_against_original(path: &Path, expected_contents: &[Option<Dat
                 None
             }
         })
-        .collect();
+        .collect()
+}
+
+unsafe fn validate_against_original(path: &Path, expected_contents: &[Option<Data>]) {
+    let (width, height, mut image_bytes) = load_image(path);
+    #[cfg(feature = "oracle")]
+    let mut image_bytes_clone = image_bytes.clone();
+
+    let mut decoder = Quirc::new(Image::new(width, height, &mut image_bytes));
+
+    quirc_identify(&mut decoder);
+
+    let result = decode_all(&mut decoder);
     assert_eq!(result, expected_contents);
 
+    #[cfg(feature = "oracle")]
     validate(
         &mut decoder,
         Image::new(width, height, &mut image_bytes_clone),
@@ -233,3 +243,88 @@ check!(
         payload: "http://sk.wikipedia.org/".to_owned()
     })]
 );
+
+/// The finder patterns of these are squeezed by perspective or smudged
+/// along some of the lines `Quirc::cross_check` follows, but not most, so
+/// the same capstones are found with it as without.
+#[test]
+fn test_cross_check() {
+    for filename in &[
+        "20150618_Prospekt_Mira_39-41_02.jpg",
+        "2_150_150DPI_ty_oerny_08_2011.jpg",
+        "Cong_Cem_DC_Old_QR.JPG",
+    ] {
+        let path = Path::new("tests/images").join(filename);
+        let (width, height, image_bytes) = load_image(&path);
+        let capstones: Vec<_> = [false, true]
+            .iter()
+            .map(|&cross_check| {
+                let mut image_bytes = image_bytes.clone();
+                let mut decoder = Quirc::new(Image::new(width, height, &mut image_bytes));
+                decoder.cross_check = cross_check;
+                quirc_identify(&mut decoder);
+                decoder
+                    .capstones
+                    .iter()
+                    .map(|cap| (cap.ring, cap.stone))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        assert!(!capstones[0].is_empty(), "{}", filename);
+        assert_eq!(capstones[0], capstones[1], "{}", filename);
+    }
+}
+
+/// One finder pattern of each of these is damaged, but with
+/// `Quirc::recover_grids` set, their grids are still set up.
+#[test]
+fn test_recover_grids() {
+    let cases: &[(&str, &[Option<Data>])] = &[
+        (
+            "Cong_Cem_DC_Old_QR.JPG",
+            &[Some(Data {
+                version: 2,
+                data_type: 4,
+                ecc_level: 1,
+                eci: 0,
+                mask: 7,
+                payload: "http://en.qrwp.org/Uriah_Tracy".to_owned(),
+            })],
+        ),
+        (
+            "QRpedia_code_for_Ohrenqualle_at_Phyletisches_Museum_-_IMAG6096.jpg",
+            &[None],
+        ),
+    ];
+
+    for &(filename, expected_contents) in cases {
+        let path = Path::new("tests/images").join(filename);
+        let (width, height, mut image_bytes) = load_image(&path);
+        let mut decoder = Quirc::new(Image::new(width, height, &mut image_bytes));
+        decoder.recover_grids = true;
+
+        quirc_identify(&mut decoder);
+
+        assert_eq!(decoder.capstones.len(), 3, "{}", filename);
+        assert_eq!(decoder.capstones[2].ring, -1, "{}", filename);
+        assert_eq!(decode_all(&mut decoder), expected_contents, "{}", filename);
+    }
+}
+
+/// The alignment pattern of this code is damaged, so a Model 1 grid is
+/// tried too, but the Model 2 grid decodes and is kept as it was.
+#[test]
+fn test_damaged_alignment() {
+    let path = Path::new("tests/images/QR_Code_Damaged.jpg");
+    let (width, height, mut image_bytes) = load_image(path);
+    let mut decoder = Quirc::new(Image::new(width, height, &mut image_bytes));
+
+    quirc_identify(&mut decoder);
+
+    assert_eq!(quirc_count(&decoder), 1);
+    assert_eq!(decoder.grids[0].model, MODEL_2);
+    let code = quirc_extract(&mut decoder, 0).unwrap();
+    let corners: Vec<_> = code.corners.iter().map(|p| (p.x, p.y)).collect();
+    assert_eq!(corners, [(210, 192), (875, 193), (875, 857), (208, 848)]);
+}
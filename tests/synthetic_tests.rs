@@ -0,0 +1,905 @@
+use std::convert::TryFrom;
+
+use quirc_rs::decode::*;
+use quirc_rs::identify::*;
+use quirc_rs::observer::*;
+use quirc_rs::quality::*;
+use quirc_rs::quirc::consts::*;
+use quirc_rs::quirc::*;
+use quirc_rs::rmqr::*;
+
+use test_utils::synth::*;
+
+use image::{GrayImage, Luma};
+
+/// A payload filling the whole code, different for each version, ECC
+/// level and mask.
+fn payload(version: i32, ecc_level: i32, mask: i32) -> Vec<u8> {
+    let len = byte_capacity(version, ecc_level);
+    (0..len)
+        .map(|i| (i as i32 * 31 + version * 7 + ecc_level * 3 + mask) as u8)
+        .collect()
+}
+
+/// Render and scan a code, checking that it's decoded as encoded.
+fn roundtrip(version: i32, ecc_level: i32, mask: i32, params: &Params) {
+    let what = format!(
+        "version {}, ECC level {}, mask {}, {:?}",
+        version, ecc_level, mask, params
+    );
+    let payload = payload(version, ecc_level, mask);
+    let code = encode(&payload, version, ecc_level, mask).unwrap();
+
+    let mut img = render(&code, params);
+    if params.invert {
+        img.pixels_mut().for_each(|p| p.0[0] = 0xff - p.0[0]);
+    }
+    let (width, height) = img.dimensions();
+    let mut q = Quirc::new(Image::new(width, height, &mut img));
+    // Dense codes have rows of modules in the ratio of a finder pattern,
+    // and small ones run out of regions before the last one is reached
+    q.cross_check = true;
+    q.recover_grids = true;
+    quirc_identify(&mut q);
+    assert_eq!(quirc_count(&q), 1, "{}", what);
+
+    let extracted = quirc_extract(&mut q, 0).unwrap();
+    let data = quirc_decode(&extracted).unwrap_or_else(|e| panic!("{}: {:?}", what, e));
+    assert_eq!(
+        (data.version, data.ecc_level, data.mask, data.data_type),
+        (version, ecc_level, mask, DATA_TYPE_BYTE),
+        "{}",
+        what
+    );
+    assert_eq!(
+        &data.payload[..data.payload_len as usize],
+        &payload[..],
+        "{}",
+        what
+    );
+}
+
+#[test]
+fn test_encode() {
+    for version in 1..=QUIRC_MAX_VERSION as i32 {
+        for &ecc_level in &[ECC_LEVEL_M, ECC_LEVEL_L, ECC_LEVEL_H, ECC_LEVEL_Q] {
+            let payload = payload(version, ecc_level, 0);
+            let code = encode(&payload, version, ecc_level, version % 8).unwrap();
+
+            let (data, ecc) = quirc_decode_ecc(&code).unwrap();
+            assert_eq!(&data.payload[..data.payload_len as usize], &payload[..]);
+            assert!(ecc.iter().all(|block| block.corrected == 0));
+        }
+    }
+
+    let too_long = vec![0; byte_capacity(1, ECC_LEVEL_L) + 1];
+    assert!(encode(&too_long, 1, ECC_LEVEL_L, 0).is_none());
+}
+
+#[test]
+fn test_code_conversion() {
+    let quirc_code = encode(b"conversion", 40, ECC_LEVEL_L, 0).unwrap();
+    let code = Code::try_from(&quirc_code).unwrap();
+    assert_eq!(code.cell_bitmap.len(), MAX_BITMAP);
+    let back = QuircCode::try_from(&code).unwrap();
+    assert_eq!(back.size, quirc_code.size);
+    assert_eq!(&back.cell_bitmap[..], &quirc_code.cell_bitmap[..]);
+    assert_eq!(code.decode().unwrap().payload, b"conversion");
+
+    let too_large = Code {
+        size: 181,
+        cell_bitmap: vec![0; MAX_BITMAP + 1],
+        ..code
+    };
+    assert!(matches!(
+        QuircCode::try_from(&too_large),
+        Err(DecodeError::InvalidGridSize)
+    ));
+    assert_eq!(too_large.decode(), Err(DecodeError::InvalidGridSize));
+
+    for &size in &[-1, 178, i32::MAX] {
+        let bad = QuircCode { size, ..quirc_code };
+        assert_eq!(Code::try_from(&bad), Err(DecodeError::InvalidGridSize));
+    }
+}
+
+#[test]
+fn test_code_cell_bounds() {
+    // The outer corners of the finder patterns are black
+    let code = Code::try_from(&encode(b"cells", 1, ECC_LEVEL_L, 0).unwrap()).unwrap();
+    assert!(code.cell(0, 0));
+    assert!(code.cell(0, 20));
+    for &(x, y) in &[(-1, 0), (0, -1), (21, 0), (0, 21), (i32::MIN, i32::MAX)] {
+        assert!(!code.cell(x, y), "({}, {})", x, y);
+    }
+
+    let truncated = Code {
+        cell_bitmap: vec![0xff; 2],
+        ..code
+    };
+    assert!(truncated.cell(1, 0));
+    assert!(!truncated.cell(20, 20));
+}
+
+/// A scanned symbol of numeric, ECI and byte segments decodes into the same
+/// segments, each with its mode, ECI, offset and count.
+#[test]
+fn test_segments() {
+    let parts = [
+        Part::Numeric(b"0123456789"),
+        Part::Eci(26),
+        Part::Byte("h\u{e9}llo".as_bytes()),
+    ];
+    let code = encode_segments(&parts, 2, ECC_LEVEL_M, 3).unwrap();
+    let mut img = render(&code, &Params::default());
+    let (width, height) = img.dimensions();
+    let mut q = Quirc::new(Image::new(width, height, &mut img));
+    quirc_identify(&mut q);
+    assert_eq!(quirc_count(&q), 1);
+
+    let data = Code::try_from(&quirc_extract(&mut q, 0).unwrap())
+        .unwrap()
+        .decode()
+        .unwrap();
+    let segments: Vec<_> = data
+        .segments
+        .iter()
+        .map(|s| (s.mode, s.eci, s.bit_offset, s.count, &s.data[..]))
+        .collect();
+    assert_eq!(
+        segments,
+        [
+            // 4 + 10 bit header, 3 x 10 + 4 bits of digits
+            (DATA_TYPE_NUMERIC, 0, 0, 10, &b"0123456789"[..]),
+            (DATA_TYPE_ECI, 26, 48, 0, &b""[..]),
+            (DATA_TYPE_BYTE, 26, 60, 6, "h\u{e9}llo".as_bytes()),
+        ]
+    );
+    assert_eq!(data.data_type, DATA_TYPE_BYTE);
+    assert_eq!(data.eci, 26);
+    assert_eq!(data.payload, "0123456789h\u{e9}llo".as_bytes());
+
+    // 72 data bits: 14 bit header, 5 x 10 + 7 bits of digits
+    let digits = [b'7'; 18];
+    assert!(encode_segments(&[Part::Numeric(&digits[..17])], 1, ECC_LEVEL_H, 0).is_some());
+    assert!(encode_segments(&[Part::Numeric(&digits)], 1, ECC_LEVEL_H, 0).is_none());
+}
+
+/// The last data word of each long RS block comes after those of all the
+/// short blocks, so reading it from anywhere else costs a correction.
+#[test]
+fn test_long_blocks() {
+    for &(version, ecc_level) in &[(5, ECC_LEVEL_Q), (13, ECC_LEVEL_M), (36, ECC_LEVEL_L)] {
+        let blocks = ecc_blocks(version, ecc_level);
+        assert!(blocks[0].0 < blocks[blocks.len() - 1].0);
+
+        let payload = payload(version, ecc_level, 0);
+        let code = encode(&payload, version, ecc_level, 0).unwrap();
+        let (data, ecc) = quirc_decode_ecc(&code).unwrap();
+        assert_eq!(&data.payload[..data.payload_len as usize], &payload[..]);
+        assert_eq!(ecc.len(), blocks.len());
+        assert!(ecc.iter().all(|block| block.corrected == 0), "{:?}", ecc);
+    }
+}
+
+/// Alignment patterns are evenly spaced between the second one and the
+/// last, which is 7 modules from the edge.
+#[test]
+fn test_alignment_positions() {
+    assert!(alignment_positions(1).is_empty());
+    for version in 2..=QUIRC_MAX_VERSION as i32 {
+        let apat = alignment_positions(version);
+        let size = version * 4 + 17;
+        assert_eq!(apat[0], 6, "version {}", version);
+        assert_eq!(apat[apat.len() - 1], size - 7, "version {}", version);
+        for w in apat[1..].windows(3) {
+            assert_eq!(w[1] - w[0], w[2] - w[1], "version {}", version);
+        }
+    }
+}
+
+#[test]
+fn test_versions() {
+    let params = Params {
+        module_size: 3.0,
+        ..Default::default()
+    };
+    for version in 1..=QUIRC_MAX_VERSION as i32 {
+        roundtrip(version, version % 4, version % 8, &params);
+    }
+}
+
+#[test]
+fn test_masks() {
+    for &version in &[2, 7] {
+        for mask in 0..8 {
+            for ecc_level in 0..4 {
+                roundtrip(version, ecc_level, mask, &Params::default());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_distortions() {
+    let distortions = [
+        Params {
+            rotation: 33.0,
+            ..Default::default()
+        },
+        Params {
+            perspective: 0.1,
+            rotation: -10.0,
+            ..Default::default()
+        },
+        Params {
+            blur: 0.8,
+            ..Default::default()
+        },
+        Params {
+            noise: 25.0,
+            seed: 1,
+            ..Default::default()
+        },
+        Params {
+            contrast: 0.25,
+            ..Default::default()
+        },
+        Params {
+            invert: true,
+            ..Default::default()
+        },
+        Params {
+            quiet_zone: 1,
+            ..Default::default()
+        },
+        Params {
+            module_size: 2.5,
+            ..Default::default()
+        },
+    ];
+
+    // Version 1 has no alignment pattern, so the fourth corner of rotated
+    // codes is often out by a module.
+    for params in &distortions {
+        for &version in &[2, 5, 12] {
+            roundtrip(version, ECC_LEVEL_M, 3, params);
+        }
+    }
+}
+
+#[test]
+fn test_deterministic() {
+    let code = encode(b"quirc", 3, ECC_LEVEL_Q, 5).unwrap();
+    let params = Params {
+        rotation: 12.0,
+        perspective: 0.1,
+        blur: 0.8,
+        noise: 10.0,
+        seed: 42,
+        ..Default::default()
+    };
+
+    assert_eq!(render(&code, &params), render(&code, &params));
+    assert_ne!(
+        render(&code, &params),
+        render(&code, &Params { seed: 43, ..params })
+    );
+}
+
+/// Shapes which look like a finder pattern along a row, but not along
+/// most other lines through them, with `Quirc::cross_check` set.
+#[test]
+fn test_false_capstones() {
+    let mut img = GrayImage::from_pixel(200, 120, Luma([0xff]));
+    let mut fill = |x0: u32, y0: u32, x1: u32, y1: u32, v: u8| {
+        for y in y0..y1 {
+            for x in x0..x1 {
+                img.put_pixel(x, y, Luma([v]));
+            }
+        }
+    };
+
+    // A ring twice as tall as wide around a square stone, with the
+    // corners inside the ring filled in
+    fill(20, 10, 62, 94, 0);
+    fill(26, 16, 56, 88, 0xff);
+    fill(32, 43, 50, 61, 0);
+    for &(x, y) in &[(26, 16), (51, 16), (26, 62), (51, 62)] {
+        fill(x, y, x + 5, y + 26, 0);
+    }
+
+    // A square ring with the stone off its middle, and the corners inside
+    // the ring filled in
+    fill(100, 20, 142, 62, 0);
+    fill(106, 26, 136, 56, 0xff);
+    fill(112, 28, 130, 46, 0);
+    for &(x, y0, y1) in &[(106, 26, 28), (131, 26, 28), (106, 47, 56), (131, 47, 56)] {
+        fill(x, y0, x + 5, y1, 0);
+    }
+
+    let (width, height) = img.dimensions();
+    let mut q = Quirc::new(Image::new(width, height, &mut img));
+    q.cross_check = true;
+    let mut reasons = Vec::new();
+    quirc_identify_observed(&mut q, &mut |event: &Event| match *event {
+        Event::CapstoneAccepted { .. } => panic!("{:?}", event),
+        Event::CapstoneRejected { reason, .. } => reasons.push(reason),
+        _ => (),
+    });
+
+    let vertical = CapstoneRejection::CrossCheck { dx: 0, dy: 1 };
+    let off_centre = CapstoneRejection::NotConcentric { dx: 0, dy: 1 };
+    assert!(reasons.contains(&vertical), "{:?}", reasons);
+    assert!(reasons.contains(&off_centre), "{:?}", reasons);
+}
+
+/// Finder patterns with a hole punched in the middle of their stones, as
+/// by a logo, are still found.
+#[test]
+fn test_holed_stones() {
+    let code = encode(b"holed stones", 2, ECC_LEVEL_H, 0).unwrap();
+    let params = Params {
+        module_size: 8.0,
+        ..Default::default()
+    };
+    let mut img = render(&code, &params);
+
+    // A one module hole in the middle of each stone
+    let side = code.size as u32;
+    for &(cx, cy) in &[(3, 3), (side - 4, 3), (3, side - 4)] {
+        let (x0, y0) = ((cx + 4) * 8, (cy + 4) * 8);
+        for y in y0..y0 + 8 {
+            for x in x0..x0 + 8 {
+                img.put_pixel(x, y, Luma([0xff]));
+            }
+        }
+    }
+
+    let (width, height) = img.dimensions();
+    let mut q = Quirc::new(Image::new(width, height, &mut img));
+    quirc_identify(&mut q);
+    assert_eq!(quirc_count(&q), 1);
+
+    let data = quirc_decode(&quirc_extract(&mut q, 0).unwrap()).unwrap();
+    assert_eq!(&data.payload[..data.payload_len as usize], b"holed stones");
+}
+
+#[test]
+fn test_scores() {
+    let code = encode(b"scores", 4, ECC_LEVEL_M, 0).unwrap();
+    let clean = render(&code, &Params::default());
+    let noisy = render(
+        &code,
+        &Params {
+            rotation: 20.0,
+            blur: 1.0,
+            noise: 40.0,
+            seed: 2,
+            ..Default::default()
+        },
+    );
+
+    let score = |mut img: GrayImage, min_grid_score: f64| {
+        let (width, height) = img.dimensions();
+        let mut q = Quirc::new(Image::new(width, height, &mut img));
+        q.min_grid_score = min_grid_score;
+        quirc_identify(&mut q);
+
+        for cap in &q.capstones {
+            assert!(cap.score > 0.0 && cap.score <= 1.0, "{:?}", cap);
+        }
+        match q.grids.first() {
+            Some(grid) => grid.score,
+            None => {
+                assert!(q
+                    .identify_errors
+                    .iter()
+                    .any(|e| matches!(e, IdentifyError::LowScore { .. })));
+                0.0
+            }
+        }
+    };
+
+    let clean_score = score(clean.clone(), 0.0);
+    let noisy_score = score(noisy, 0.0);
+    assert!(clean_score > 0.95, "{}", clean_score);
+    assert!(
+        noisy_score > 0.5 && noisy_score < clean_score,
+        "{}",
+        noisy_score
+    );
+
+    assert_eq!(score(clean.clone(), clean_score), clean_score);
+    assert_eq!(score(clean, clean_score + 0.01), 0.0);
+}
+
+/// Failures are reported with the stage at which they were detected: an
+/// empty image at the finder scan, and a code whose only RS block is
+/// damaged beyond repair at data ECC.
+#[test]
+fn test_errors() {
+    let mut img = GrayImage::from_pixel(120, 120, Luma([0xff]));
+    let mut q = Quirc::new(Image::new(120, 120, &mut img));
+    quirc_identify(&mut q);
+    assert_eq!(quirc_count(&q), 0);
+    assert_eq!(q.identify_errors, [IdentifyError::NoCapstones]);
+    assert_eq!(q.identify_errors[0].stage(), Stage::FinderScan);
+    assert_eq!(q.identify_errors[0].to_string(), "No capstones found");
+
+    // Flip half the cells below and right of the format information,
+    // about 8 codewords of a block which can correct 3
+    let mut code = encode(b"damaged", 1, ECC_LEVEL_M, 2).unwrap();
+    for y in 9..21 {
+        for x in 9..21 {
+            let p = (y * code.size + x) as usize;
+            code.cell_bitmap[p >> 3] ^= ((x + y) as u8 & 1) << (p & 7);
+        }
+    }
+    let mut img = render(&code, &Params::default());
+    let (width, height) = img.dimensions();
+    let mut q = Quirc::new(Image::new(width, height, &mut img));
+    quirc_identify(&mut q);
+    assert_eq!(quirc_count(&q), 1);
+    assert_eq!(q.identify_errors, []);
+
+    let err = Code::try_from(&quirc_extract(&mut q, 0).unwrap())
+        .unwrap()
+        .decode()
+        .unwrap_err();
+    match err {
+        DecodeError::DataEcc {
+            block,
+            uncorrectable,
+            ..
+        } => assert!(block == 0 && uncorrectable >= 1, "{:?}", err),
+        _ => panic!("{:?}", err),
+    }
+    assert_eq!(err.stage(), Stage::DataEcc);
+    assert_eq!(err.code(), 4);
+    assert!(err.to_string().starts_with("ECC failure in block 0 ("));
+}
+
+/// Codes packed so tightly that capstones of neighbouring codes line up
+/// as well as those of the same code.
+#[test]
+fn test_packed_codes() {
+    let params = Params {
+        quiet_zone: 1,
+        ..Default::default()
+    };
+
+    let mut sheet = GrayImage::from_pixel(300, 220, Luma([0xff]));
+    let mut payloads = Vec::new();
+    for row in 0..2 {
+        for col in 0..3 {
+            let payload = format!("packed {}", row * 3 + col).into_bytes();
+            let img = render(&encode(&payload, 1, ECC_LEVEL_M, col).unwrap(), &params);
+            let size = img.width();
+            image::imageops::overlay(
+                &mut sheet,
+                &img,
+                20 + col as u32 * size,
+                20 + row as u32 * size,
+            );
+            payloads.push(payload);
+        }
+    }
+
+    let (width, height) = sheet.dimensions();
+    let mut q = Quirc::new(Image::new(width, height, &mut sheet));
+    quirc_identify(&mut q);
+    assert_eq!(q.capstones.len(), 18);
+
+    let mut decoded = Vec::new();
+    for i in 0..quirc_count(&q) {
+        let data = quirc_decode(&quirc_extract(&mut q, i).unwrap()).unwrap();
+        decoded.push(data.payload[..data.payload_len as usize].to_vec());
+    }
+    decoded.sort();
+    assert_eq!(decoded, payloads);
+}
+
+/// Codes with one finder pattern covered by a sticker.
+#[test]
+fn test_covered_finder() {
+    let payload = b"covered finder";
+    let params = Params::default();
+    let img = render(&encode(payload, 2, ECC_LEVEL_M, 3).unwrap(), &params);
+    let m = params.module_size as u32;
+    let (origin, far) = (
+        params.quiet_zone as u32 * m,
+        img.width() - params.quiet_zone as u32 * m,
+    );
+
+    // Top-left, top-right and bottom-left
+    for &(x, y) in &[
+        (origin, origin),
+        (far - 7 * m, origin),
+        (origin, far - 7 * m),
+    ] {
+        let mut img = img.clone();
+        for py in y - m..y + 8 * m {
+            for px in x - m..x + 8 * m {
+                img.put_pixel(px, py, Luma([0xff]));
+            }
+        }
+
+        let (width, height) = img.dimensions();
+        let mut q = Quirc::new(Image::new(width, height, &mut img));
+        q.recover_grids = true;
+        let mut inferred = Vec::new();
+        quirc_identify_observed(&mut q, &mut |event: &Event| {
+            if let Event::CapstoneInferred { capstone, .. } = *event {
+                inferred.push(capstone);
+            }
+        });
+
+        assert_eq!(q.capstones.len(), 3, "({}, {})", x, y);
+        assert_eq!(inferred, vec![2], "({}, {})", x, y);
+        assert_eq!(q.capstones[2].ring, -1);
+        assert_eq!(quirc_count(&q), 1);
+        let data = quirc_decode(&quirc_extract(&mut q, 0).unwrap()).unwrap();
+        assert_eq!(&data.payload[..data.payload_len as usize], payload);
+    }
+}
+
+/// A code with a finder pattern covered isn't recovered if its format
+/// information doesn't read either.
+#[test]
+fn test_covered_finder_and_format() {
+    let mut code = encode(b"covered format", 2, ECC_LEVEL_M, 3).unwrap();
+    let size = code.size;
+
+    // Both copies of the format information, filled with alternate dark
+    // and light cells, which are too far from any valid format to correct
+    let mut cells: Vec<_> = (0..9)
+        .map(|i| (8, i))
+        .chain((0..8).map(|i| (i, 8)))
+        .collect();
+    cells.extend((0..8).map(|i| (8, size - 1 - i)));
+    cells.extend((0..8).map(|i| (size - 1 - i, 8)));
+    for (n, &(x, y)) in cells.iter().enumerate() {
+        if x != 6 && y != 6 {
+            let i = (y * size + x) as usize;
+            code.cell_bitmap[i >> 3] &= !(1 << (i & 7));
+            code.cell_bitmap[i >> 3] |= ((n % 2) as u8) << (i & 7);
+        }
+    }
+
+    // And the top-right finder pattern covered
+    let params = Params::default();
+    let mut img = render(&code, &params);
+    let m = params.module_size as u32;
+    let (origin, far) = (
+        params.quiet_zone as u32 * m,
+        img.width() - params.quiet_zone as u32 * m,
+    );
+    for py in origin - m..origin + 8 * m {
+        for px in far - 8 * m..far + m {
+            img.put_pixel(px, py, Luma([0xff]));
+        }
+    }
+
+    let (width, height) = img.dimensions();
+    let mut q = Quirc::new(Image::new(width, height, &mut img));
+    q.recover_grids = true;
+    quirc_identify(&mut q);
+    assert_eq!(q.capstones.len(), 2);
+    assert_eq!(quirc_count(&q), 0);
+}
+
+/// Two finder patterns alone aren't taken for a code, and trying to set
+/// up a grid from them leaves them as they were found.
+#[test]
+fn test_unrecovered_finders() {
+    let mut code = encode(b"two finders", 2, ECC_LEVEL_M, 3).unwrap();
+    let size = code.size;
+    for y in 0..size {
+        for x in 0..size {
+            if y >= 7 || (x >= 7 && x < size - 7) {
+                let i = (y * size + x) as usize;
+                code.cell_bitmap[i >> 3] &= !(1 << (i & 7));
+            }
+        }
+    }
+    let params = Params {
+        rotation: 90.0,
+        ..Default::default()
+    };
+    let img = render(&code, &params);
+
+    let capstones: Vec<_> = [false, true]
+        .iter()
+        .map(|&recover_grids| {
+            let mut img = img.clone();
+            let (width, height) = img.dimensions();
+            let mut q = Quirc::new(Image::new(width, height, &mut img));
+            q.recover_grids = recover_grids;
+            quirc_identify(&mut q);
+            assert_eq!(quirc_count(&q), 0);
+            q.capstones
+        })
+        .collect();
+
+    assert_eq!(capstones[0].len(), 2);
+    assert_eq!(capstones[0], capstones[1]);
+}
+
+#[test]
+fn test_model1_encode() {
+    for version in 1..=MODEL1_MAX_VERSION as i32 {
+        for &ecc_level in &[ECC_LEVEL_M, ECC_LEVEL_L, ECC_LEVEL_H, ECC_LEVEL_Q] {
+            let len = model1_byte_capacity(version, ecc_level);
+            let payload: Vec<u8> = (0..len).map(|i| (i * 13 + 5) as u8).collect();
+            let mut code = encode_model1(&payload, version, ecc_level, version % 8).unwrap();
+
+            // Spoil a few data cells, one per codeword at most
+            for i in 0..3 {
+                let (x, y) = (code.size - 1, code.size - 1 - i * 4);
+                let p = (y * code.size + x) as usize;
+                code.cell_bitmap[p >> 3] ^= 1 << (p & 7);
+            }
+
+            let (data, model) = quirc_decode_model(&code).unwrap();
+            assert_eq!(model, MODEL_1, "version {}", version);
+            assert_eq!(&data.payload[..data.payload_len as usize], &payload[..]);
+        }
+    }
+
+    let code = encode(b"Model 2", 3, ECC_LEVEL_M, 0).unwrap();
+    assert_eq!(quirc_decode_model(&code).unwrap().1, MODEL_2);
+
+    let too_long = vec![0; model1_byte_capacity(1, ECC_LEVEL_L) + 1];
+    assert!(encode_model1(&too_long, 1, ECC_LEVEL_L, 0).is_none());
+}
+
+#[test]
+fn test_model1() {
+    let distortions = [
+        Params::default(),
+        Params {
+            rotation: 30.0,
+            perspective: 0.1,
+            blur: 0.8,
+            noise: 8.0,
+            seed: 3,
+            ..Default::default()
+        },
+    ];
+
+    for version in 1..=MODEL1_MAX_VERSION as i32 {
+        for params in &distortions {
+            let what = format!("Model 1 version {}, {:?}", version, params);
+            let payload = format!("M1 v{}", version);
+            let code = encode_model1(payload.as_bytes(), version, ECC_LEVEL_M, 2).unwrap();
+
+            let mut img = render(&code, params);
+            let (width, height) = img.dimensions();
+            let mut q = Quirc::new(Image::new(width, height, &mut img));
+            quirc_identify(&mut q);
+            assert_eq!(quirc_count(&q), 1, "{}", what);
+
+            let extracted = quirc_extract(&mut q, 0).unwrap();
+            let (data, model) =
+                quirc_decode_model(&extracted).unwrap_or_else(|e| panic!("{}: {:?}", what, e));
+            assert_eq!((data.version, model), (version, MODEL_1), "{}", what);
+            assert_eq!(
+                &data.payload[..data.payload_len as usize],
+                payload.as_bytes(),
+                "{}",
+                what
+            );
+        }
+    }
+}
+
+/// Model 2 codes with the alignment pattern nearest the bottom right
+/// corner covered, which carry version information, aren't jiggled as
+/// Model 1 as well.
+#[test]
+fn test_covered_alignment() {
+    let params = Params {
+        rotation: 30.0,
+        perspective: 0.1,
+        blur: 0.8,
+        noise: 8.0,
+        seed: 3,
+        ..Default::default()
+    };
+    for version in 7..=MODEL1_MAX_VERSION as i32 {
+        let mut code = encode(b"covered alignment", version, ECC_LEVEL_M, 1).unwrap();
+        let p = *alignment_positions(version).last().unwrap();
+        for y in p - 2..p + 3 {
+            for x in p - 2..p + 3 {
+                let i = (y * code.size + x) as usize;
+                code.cell_bitmap[i >> 3] &= !(1 << (i & 7));
+            }
+        }
+        let mut img = render(&code, &params);
+
+        let (width, height) = img.dimensions();
+        let mut q = Quirc::new(Image::new(width, height, &mut img));
+        q.cross_check = true;
+        let mut jiggles = 0;
+        quirc_identify_observed(&mut q, &mut |event: &Event| {
+            if let Event::JiggleFitness { pass: 0, .. } = *event {
+                jiggles += 1;
+            }
+        });
+        assert_eq!(jiggles, 1, "version {}", version);
+        assert_eq!(quirc_count(&q), 1, "version {}", version);
+        assert_eq!(q.grids[0].model, MODEL_2, "version {}", version);
+    }
+}
+
+/// Render and measure the quality of a code, checking that a grayscale
+/// image of the wrong size is refused.
+fn grade(code: &QuircCode, params: &Params) -> Quality {
+    let mut img = render(code, params);
+    let gray = img.clone().into_raw();
+    let (width, height) = img.dimensions();
+    let mut q = Quirc::new(Image::new(width, height, &mut img));
+    quirc_identify(&mut q);
+    assert_eq!(quirc_count(&q), 1);
+
+    assert_eq!(
+        quirc_quality(&mut q, 0, &gray[1..]).unwrap_err(),
+        QualityError::ImageSize {
+            expected: gray.len(),
+            found: gray.len() - 1
+        }
+    );
+    quirc_quality(&mut q, 0, &gray).unwrap()
+}
+
+#[test]
+fn test_quality() {
+    let code = encode(b"quality", 2, ECC_LEVEL_L, 0).unwrap();
+    let quality = grade(&code, &Params::default());
+    assert_eq!(quality.unused_ecc, 1.0);
+    assert!(quality.symbol_contrast > 0.95, "{:?}", quality);
+    assert_eq!(quality.grades().overall, Grade::A, "{:?}", quality);
+
+    let params = Params {
+        contrast: 0.5,
+        ..Default::default()
+    };
+    let grades = grade(&code, &params).grades();
+    assert_eq!(grades.symbol_contrast, Grade::C);
+    assert_eq!(grades.overall, Grade::C);
+
+    // One error in the single block of 10 parity codewords uses 2 of the 8
+    // left by a Model 2 code, but 2 of all 10 of a Model 1 code
+    for &(model, unused) in &[(MODEL_2, 0.75), (MODEL_1, 0.8)] {
+        let mut code = if model == MODEL_2 {
+            encode(b"quality", 2, ECC_LEVEL_L, 0)
+        } else {
+            encode_model1(b"quality", 2, ECC_LEVEL_L, 0)
+        }
+        .unwrap();
+        let p = (code.size * code.size - 1) as usize;
+        code.cell_bitmap[p >> 3] ^= 1 << (p & 7);
+
+        let quality = grade(&code, &Params::default());
+        assert_eq!(quality.blocks.len(), 1);
+        assert_eq!(quality.blocks[0].corrected, 1);
+        assert!((quality.unused_ecc - unused).abs() < 1e-9, "{:?}", quality);
+    }
+}
+
+/// Each binarizer on a faded code, and on one lit unevenly.
+#[test]
+fn test_binarizers() {
+    let code = encode(b"binarizer", 2, ECC_LEVEL_M, 1).unwrap();
+    let faded = render(
+        &code,
+        &Params {
+            contrast: 0.3,
+            ..Default::default()
+        },
+    );
+
+    // Light from the right, so the dark modules there are lighter than
+    // the light ones on the left
+    let mut lit = faded.clone();
+    let width = lit.width();
+    for (x, _, p) in lit.enumerate_pixels_mut() {
+        p.0[0] = (u32::from(p.0[0]) + x * 120 / width).min(255) as u8;
+    }
+
+    // All of the faded code is lighter than the middle of the range
+    let mut faded = faded;
+    faded.pixels_mut().for_each(|p| p.0[0] += 80);
+
+    let decoded = |img: &GrayImage, binarizer: Binarizer| {
+        let mut img = img.clone();
+        let (width, height) = img.dimensions();
+        let mut q = Quirc::new(Image::new(width, height, &mut img));
+        q.binarizer = binarizer;
+        quirc_identify(&mut q);
+        (0..quirc_count(&q))
+            .filter_map(|i| quirc_extract(&mut q, i))
+            .any(|code| quirc_decode(&code).is_ok())
+    };
+
+    assert!(decoded(&faded, Binarizer::Adaptive));
+    assert!(decoded(&faded, Binarizer::Otsu));
+    assert!(decoded(&faded, Binarizer::Fixed(208)));
+    assert!(!decoded(&faded, Binarizer::Fixed(128)));
+
+    assert!(decoded(&lit, Binarizer::Adaptive));
+    assert!(!decoded(&lit, Binarizer::Otsu));
+}
+
+#[test]
+fn test_rmqr_encode() {
+    for version in 0..=RMQR_MAX_VERSION as i32 {
+        for &ecc_h in &[false, true] {
+            let len = rmqr_byte_capacity(version, ecc_h);
+            let payload: Vec<u8> = (0..len).map(|i| (i as i32 * 29 + version) as u8).collect();
+            let code = encode_rmqr(&payload, version, ecc_h).unwrap();
+
+            let data = quirc_decode_rmqr(&code).unwrap();
+            let ecc_level = if ecc_h { ECC_LEVEL_H } else { ECC_LEVEL_M };
+            assert_eq!((data.version, data.ecc_level), (version, ecc_level));
+            assert_eq!(&data.payload[..data.payload_len as usize], &payload[..]);
+        }
+    }
+
+    let too_long = vec![0; rmqr_byte_capacity(0, true) + 1];
+    assert!(encode_rmqr(&too_long, 0, true).is_none());
+}
+
+/// rMQR codes of every height, upright and distorted.
+#[test]
+fn test_rmqr() {
+    let distortions = [
+        Params::default(),
+        Params {
+            rotation: 90.0 + 17.0,
+            ..Default::default()
+        },
+        Params {
+            perspective: 0.02,
+            blur: 0.8,
+            noise: 8.0,
+            seed: 7,
+            ..Default::default()
+        },
+    ];
+
+    for version in (0..=RMQR_MAX_VERSION as i32).step_by(3) {
+        for params in &distortions {
+            let what = format!("rMQR version {}, {:?}", version, params);
+            let payload = format!("R{}", version);
+            let ecc_h = version % 2 == 1 && rmqr_byte_capacity(version, true) >= payload.len();
+            let code = encode_rmqr(payload.as_bytes(), version, ecc_h).unwrap();
+
+            let mut img = render_rmqr(&code, params);
+            let (width, height) = img.dimensions();
+            let mut q = Quirc::new(Image::new(width, height, &mut img));
+            q.cross_check = true;
+            quirc_identify(&mut q);
+            assert_eq!(quirc_count(&q), 0, "{}", what);
+            assert_eq!(quirc_rmqr_count(&q), 1, "{}", what);
+            assert!(
+                q.identify_errors.is_empty(),
+                "{}: {:?}",
+                what,
+                q.identify_errors
+            );
+
+            let extracted = quirc_extract_rmqr(&q, 0).unwrap();
+            let data =
+                quirc_decode_rmqr(&extracted).unwrap_or_else(|e| panic!("{}: {:?}", what, e));
+            assert_eq!(data.version, version, "{}", what);
+            assert_eq!(
+                &data.payload[..data.payload_len as usize],
+                payload.as_bytes(),
+                "{}",
+                what
+            );
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use image::imageops::overlay;
+use image::{GrayImage, Luma};
+
+use quirc_rs::decode::*;
+use quirc_rs::identify::*;
+use quirc_rs::quirc::consts::*;
+use quirc_rs::quirc::*;
+use quirc_rs::track::*;
+
+use test_utils::synth::*;
+
+/// Render each code at its position on a white frame.
+fn frame(codes: &[(&GrayImage, u32, u32)]) -> GrayImage {
+    let mut img = GrayImage::from_pixel(400, 240, Luma([0xff]));
+    for &(code, x, y) in codes {
+        overlay(&mut img, code, x, y);
+    }
+    img
+}
+
+/// Track the codes of a frame, returning the payload decoded with each
+/// track ID.
+fn track(tracker: &mut Tracker, mut img: GrayImage) -> HashMap<u32, Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let mut q = Quirc::new(Image::new(width, height, &mut img));
+    quirc_track(&mut q, tracker);
+    assert_eq!(tracker.ids().len(), quirc_count(&q) as usize);
+
+    let mut decoded = HashMap::new();
+    for (i, &id) in tracker.ids().iter().enumerate() {
+        let code = quirc_extract(&mut q, i as i32).unwrap();
+        let data = quirc_decode(&code).unwrap();
+        let payload = data.payload[..data.payload_len as usize].to_vec();
+        assert!(decoded.insert(id, payload).is_none());
+    }
+    decoded
+}
+
+fn code(payload: &[u8]) -> GrayImage {
+    let code = encode(payload, 2, ECC_LEVEL_M, 1).unwrap();
+    render(&code, &Params::default())
+}
+
+#[test]
+fn test_moving_codes() {
+    let (first, second) = (code(b"first"), code(b"second"));
+    let mut tracker = Tracker::new();
+    tracker.full_scan_interval = 5;
+    let mut ids = HashMap::new();
+
+    for i in 0..12 {
+        let img = frame(&[
+            (&first, 10 + 4 * i, 20 + 2 * i),
+            (&second, 240 - 3 * i, 100),
+        ]);
+        let decoded = track(&mut tracker, img);
+        assert_eq!(decoded.len(), 2, "frame {}", i);
+        assert_eq!(tracker.full_scan(), i % 5 == 0, "frame {}", i);
+
+        for (id, payload) in decoded {
+            assert_eq!(*ids.entry(payload).or_insert(id), id, "frame {}", i);
+        }
+    }
+
+    assert_eq!(ids.len(), 2);
+    assert_eq!(tracker.tracks().len(), 2);
+}
+
+/// Two codes of the same size passing each other in neighbouring lanes
+/// keep their IDs, although which is found first changes as they do.
+#[test]
+fn test_crossing_codes() {
+    let small = Params {
+        module_size: 3.0,
+        ..Default::default()
+    };
+    let down = render(&encode(b"down", 2, ECC_LEVEL_M, 1).unwrap(), &small);
+    let up = render(&encode(b"up", 2, ECC_LEVEL_M, 1).unwrap(), &small);
+
+    // Between full scans the codes are looked for in the order of their
+    // tracks, so only full scans put them in the order of the image
+    for &interval in &[1, 30] {
+        let mut tracker = Tracker::new();
+        tracker.full_scan_interval = interval;
+        let mut ids = HashMap::new();
+
+        for i in 0..18 {
+            let img = frame(&[(&down, 10, 2 + 8 * i), (&up, 115, 139 - 8 * i)]);
+            let decoded = track(&mut tracker, img);
+            let what = format!("interval {}, frame {}", interval, i);
+            assert_eq!(decoded.len(), 2, "{}", what);
+            assert_eq!(tracker.full_scan(), interval == 1 || i == 0, "{}", what);
+
+            for (id, payload) in decoded {
+                assert_eq!(*ids.entry(payload).or_insert(id), id, "{}", what);
+            }
+        }
+
+        assert_eq!(ids.len(), 2);
+        assert_eq!(tracker.tracks().len(), 2);
+    }
+}
+
+#[test]
+fn test_lost_codes() {
+    let (first, second) = (code(b"first"), code(b"second"));
+    let mut tracker = Tracker::new();
+    tracker.max_missed = 2;
+
+    let decoded = track(&mut tracker, frame(&[(&first, 20, 20)]));
+    let first_id = *decoded.keys().next().unwrap();
+
+    // A code appearing between full scans isn't looked for
+    let decoded = track(
+        &mut tracker,
+        frame(&[(&first, 20, 20), (&second, 240, 100)]),
+    );
+    assert!(!tracker.full_scan());
+    assert_eq!(decoded.len(), 1);
+
+    // Losing a code triggers a full scan in the next frame
+    track(&mut tracker, frame(&[(&second, 240, 100)]));
+    assert_eq!(tracker.tracks().len(), 1);
+    assert_eq!(tracker.tracks()[0].missed, 1);
+
+    let decoded = track(&mut tracker, frame(&[(&second, 240, 100)]));
+    assert!(tracker.full_scan());
+    assert_eq!(decoded.len(), 1);
+    assert!(!decoded.contains_key(&first_id));
+    assert_eq!(tracker.tracks().len(), 2);
+
+    track(&mut tracker, frame(&[(&second, 240, 100)]));
+    assert_eq!(tracker.tracks().len(), 1);
+
+    // A code coming back after its track was dropped gets a new ID
+    let decoded = track(
+        &mut tracker,
+        frame(&[(&first, 20, 20), (&second, 240, 100)]),
+    );
+    assert_eq!(decoded.len(), 2);
+    assert!(!decoded.contains_key(&first_id));
+}
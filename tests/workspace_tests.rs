@@ -0,0 +1,79 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use image::imageops::overlay;
+use image::{GrayImage, Luma};
+
+use quirc_rs::decode::*;
+use quirc_rs::identify::*;
+use quirc_rs::quirc::consts::*;
+use quirc_rs::quirc::*;
+
+use test_utils::synth::*;
+
+/// The system allocator, counting allocations. This is the only test in
+/// its binary, so nothing else allocates while it counts.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Identifying and decoding with a reused `Workspace` doesn't touch the
+/// heap, whether the frame holds codes, a damaged one or none at all.
+#[test]
+fn test_workspace_allocations() {
+    let params = Params::default();
+    let good = render(&encode(b"workspace", 2, ECC_LEVEL_M, 0).unwrap(), &params);
+    let mut damaged = encode(b"damaged", 3, ECC_LEVEL_L, 1).unwrap();
+    for y in 9..20 {
+        for x in 9..20 {
+            let p = (y * damaged.size + x) as usize;
+            damaged.cell_bitmap[p >> 3] ^= ((x + y) as u8 & 1) << (p & 7);
+        }
+    }
+    let damaged = render(&damaged, &params);
+
+    let mut frames = Vec::new();
+    for codes in &[vec![&good, &damaged], vec![], vec![&good]] {
+        let mut img = GrayImage::from_pixel(320, 200, Luma([0xff]));
+        for (i, code) in codes.iter().enumerate() {
+            overlay(&mut img, *code, i as u32 * 160, 20);
+        }
+        frames.push(img.into_raw());
+    }
+
+    let mut ws = Workspace::new(320, 200);
+    let mut data = QuircData::default();
+    let mut results = Vec::with_capacity(frames.len() * 2);
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    for pixels in &mut frames {
+        let mut q = Quirc::from_workspace(Image::new(320, 200, pixels), ws);
+        quirc_identify(&mut q);
+        for i in 0..quirc_count(&q) {
+            results.push(quirc_extract_decode(&mut q, i, &mut data).is_ok());
+        }
+        ws = q.into_workspace();
+    }
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), before);
+
+    assert_eq!(results, [true, false, true]);
+}